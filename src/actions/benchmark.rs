@@ -0,0 +1,145 @@
+use crate::sanitization::mem::AlignedBuffer;
+use crate::storage::StorageAccess;
+use anyhow::{Context, Result};
+use rand::RngCore;
+use std::cmp::Ordering;
+use std::time::Instant;
+
+/// Block sizes tried by the benchmark, smaller ones are too slow for any device.
+pub const BENCHMARK_BLOCK_SIZES: [usize; 8] = [
+    64 << 10,
+    128 << 10,
+    256 << 10,
+    512 << 10,
+    1 << 20,
+    2 << 20,
+    4 << 20,
+    8 << 20,
+];
+
+/// Bytes written with each block size, enough to get past the device write cache
+/// without delaying the wipe for long.
+const BENCHMARK_BYTES: u64 = 64 << 20;
+
+/// Write throughput measured at the start of the storage with several block sizes.
+/// The data written is random, so it's going to be overwritten by the wipe anyway.
+#[derive(Debug, Clone)]
+pub struct BlockSizeBenchmark {
+    /// Block sizes with the measured bytes per second.
+    pub results: Vec<(usize, f64)>,
+    /// The fastest block size.
+    pub block_size: usize,
+}
+
+impl BlockSizeBenchmark {
+    /// Block sizes aligned to the sector size.
+    pub fn candidates(sector_size: usize) -> Vec<usize> {
+        BENCHMARK_BLOCK_SIZES
+            .iter()
+            .copied()
+            .filter(|s| sector_size > 0 && s.is_multiple_of(sector_size))
+            .collect()
+    }
+
+    pub fn run(
+        access: &mut dyn StorageAccess,
+        total_size: u64,
+        sector_size: usize,
+    ) -> Result<BlockSizeBenchmark> {
+        let candidates = Self::candidates(sector_size);
+        let largest = *candidates
+            .last()
+            .ok_or_else(|| anyhow!("No block size fits the sector size of {}", sector_size))?;
+        let whole_sectors = total_size / sector_size as u64 * sector_size as u64;
+        if whole_sectors == 0 {
+            return Err(anyhow!("The storage is smaller than a sector"));
+        }
+
+        let buf = AlignedBuffer::new(largest, largest);
+        rand::thread_rng().fill_bytes(buf.as_mut_slice());
+
+        let mut results = vec![];
+        for block_size in candidates {
+            // too small devices are measured with a single block
+            let blocks = (BENCHMARK_BYTES.min(total_size) / block_size as u64).max(1);
+            let length = (block_size as u64).min(whole_sectors) as usize;
+
+            access.seek(0)?;
+            let started = Instant::now();
+            for _ in 0..blocks {
+                access
+                    .write(&buf.as_mut_slice()[..length])
+                    .context("Block size benchmark failed")?;
+            }
+            access.flush()?;
+            let elapsed = started.elapsed().as_secs_f64();
+
+            results.push((
+                block_size,
+                (blocks * length as u64) as f64 / elapsed.max(1e-9),
+            ));
+        }
+
+        let block_size = results
+            .iter()
+            .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(Ordering::Equal))
+            .map(|(s, _)| *s)
+            .unwrap_or(largest);
+
+        Ok(BlockSizeBenchmark {
+            results,
+            block_size,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_support::MemoryStorage;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_block_size_candidates() {
+        assert_eq!(
+            BENCHMARK_BLOCK_SIZES.to_vec(),
+            BlockSizeBenchmark::candidates(512)
+        );
+        assert_eq!(
+            vec![4 << 20, 8 << 20],
+            BlockSizeBenchmark::candidates(4 << 20)
+        );
+        assert!(BlockSizeBenchmark::candidates(3000).is_empty());
+    }
+
+    #[test]
+    fn test_benchmark_writes_at_the_start() {
+        let size = 16 << 20;
+        let mut storage = MemoryStorage(Cursor::new(vec![0; size + 4096]));
+
+        let benchmark = BlockSizeBenchmark::run(&mut storage, size as u64, 4096).unwrap();
+
+        assert_eq!(BENCHMARK_BLOCK_SIZES.len(), benchmark.results.len());
+        assert!(benchmark
+            .results
+            .iter()
+            .any(|(s, _)| *s == benchmark.block_size));
+        assert!(benchmark.results.iter().all(|(_, t)| *t > 0.0));
+
+        let data = storage.0.get_ref();
+        assert_eq!(size + 4096, data.len());
+        assert!(data[size..].iter().all(|b| *b == 0));
+        assert!(data[..4096].iter().any(|b| *b != 0));
+    }
+
+    #[test]
+    fn test_benchmark_small_storage() {
+        let mut storage = MemoryStorage(Cursor::new(vec![0; 100_000]));
+
+        let benchmark = BlockSizeBenchmark::run(&mut storage, 100_000, 512).unwrap();
+
+        assert_eq!(100_000, storage.0.get_ref().len());
+        assert!(storage.0.get_ref()[99_840..].iter().all(|b| *b == 0));
+        assert_eq!(BENCHMARK_BLOCK_SIZES.len(), benchmark.results.len());
+    }
+}
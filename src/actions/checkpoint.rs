@@ -0,0 +1,243 @@
+use crate::actions::{Verify, WipeState, WipeTask};
+use crate::sanitization::{Scheme, Stage};
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Everything required to resume an interrupted wipe, stored as a plain text file.
+#[derive(Debug, Clone)]
+pub struct Checkpoint {
+    pub device_id: String,
+    pub total_size: u64,
+    pub block_size: usize,
+    pub sector_size: usize,
+    pub verify: Verify,
+    pub scheme: Scheme,
+    pub stage: usize,
+    pub position: u64,
+    pub at_verification: bool,
+    pub bad_sectors: Vec<u64>,
+}
+
+impl Checkpoint {
+    pub fn capture(device_id: &str, task: &WipeTask, state: &WipeState) -> Self {
+        Checkpoint {
+            device_id: device_id.to_owned(),
+            total_size: task.total_size,
+            block_size: task.block_size,
+            sector_size: task.sector_size,
+            verify: task.verify.clone(),
+            scheme: task.scheme.clone(),
+            stage: state.stage,
+            position: state.position,
+            at_verification: state.at_verification,
+            bad_sectors: state.bad_blocks.borrow().iter().collect(),
+        }
+    }
+
+    pub fn task(&self) -> Result<WipeTask> {
+        WipeTask::new(
+            self.scheme.clone(),
+            self.verify.clone(),
+            self.total_size,
+            self.block_size,
+            self.sector_size,
+        )
+    }
+
+    pub fn state(&self) -> WipeState {
+        let state = WipeState {
+            stage: self.stage,
+            position: self.position,
+            at_verification: self.at_verification,
+            ..Default::default()
+        };
+
+        for s in &self.bad_sectors {
+            state.bad_blocks.borrow_mut().mark(*s);
+        }
+
+        state
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        std::fs::write(path, self.to_string()).context("Unable to save the checkpoint")
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let content = std::fs::read_to_string(path).context("Unable to read the checkpoint")?;
+        Self::parse(&content).context("Invalid checkpoint")
+    }
+
+    fn parse(content: &str) -> Result<Self> {
+        let values = KeyValues::parse(content);
+        let get = |key: &str| values.get(key);
+
+        let stages = get("stages")?
+            .split(',')
+            .map(parse_stage)
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Checkpoint {
+            device_id: get("device")?.to_owned(),
+            total_size: get("size")?.parse()?,
+            block_size: get("block_size")?.parse()?,
+            sector_size: get("sector_size")?.parse()?,
+            verify: match get("verify")? {
+                "no" => Verify::No,
+                "last" => Verify::Last,
+                "all" => Verify::All,
+                v => return Err(anyhow!("Unknown verification mode {}", v)),
+            },
+            scheme: Scheme {
+                description: get("description")?.to_owned(),
+                stages,
+            },
+            stage: get("stage")?.parse()?,
+            position: get("position")?.parse()?,
+            at_verification: get("at_verification")?.parse()?,
+            bad_sectors: values.sectors("bad_sectors")?,
+        })
+    }
+}
+
+/// Lines of `key=value` pairs, the format of the files saved between runs.
+pub(crate) struct KeyValues<'a>(HashMap<&'a str, &'a str>);
+
+impl<'a> KeyValues<'a> {
+    pub(crate) fn parse(content: &'a str) -> Self {
+        KeyValues(
+            content
+                .lines()
+                .filter_map(|l| {
+                    let mut parts = l.splitn(2, '=');
+                    Some((parts.next()?.trim(), parts.next()?.trim()))
+                })
+                .collect(),
+        )
+    }
+
+    pub(crate) fn get(&self, key: &str) -> Result<&'a str> {
+        self.0
+            .get(key)
+            .copied()
+            .ok_or_else(|| anyhow!("Missing {}", key))
+    }
+
+    /// Comma separated sector numbers, possibly none.
+    pub(crate) fn sectors(&self, key: &str) -> Result<Vec<u64>> {
+        self.get(key)?
+            .split(',')
+            .filter(|s| !s.is_empty())
+            .map(|s| s.parse().context("Invalid bad sector"))
+            .collect()
+    }
+}
+
+impl std::fmt::Display for Checkpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let stages: Vec<String> = self.scheme.stages.iter().map(format_stage).collect();
+        let bad_sectors: Vec<String> = self.bad_sectors.iter().map(|s| s.to_string()).collect();
+        let verify = match self.verify {
+            Verify::No => "no",
+            Verify::Last => "last",
+            Verify::All => "all",
+        };
+
+        writeln!(f, "device={}", self.device_id)?;
+        writeln!(f, "size={}", self.total_size)?;
+        writeln!(f, "block_size={}", self.block_size)?;
+        writeln!(f, "sector_size={}", self.sector_size)?;
+        writeln!(f, "verify={}", verify)?;
+        writeln!(f, "description={}", self.scheme.description)?;
+        writeln!(f, "stages={}", stages.join(","))?;
+        writeln!(f, "stage={}", self.stage)?;
+        writeln!(f, "position={}", self.position)?;
+        writeln!(f, "at_verification={}", self.at_verification)?;
+        writeln!(f, "bad_sectors={}", bad_sectors.join(","))
+    }
+}
+
+fn format_stage(stage: &Stage) -> String {
+    match stage {
+        Stage::Fill { value } => format!("fill:{:02x}", value),
+        Stage::Random { seed } => {
+            let hex: Vec<String> = seed.iter().map(|b| format!("{:02x}", b)).collect();
+            format!("random:{}", hex.concat())
+        }
+    }
+}
+
+fn parse_stage(s: &str) -> Result<Stage> {
+    let mut parts = s.splitn(2, ':');
+    match (parts.next(), parts.next()) {
+        (Some("fill"), Some(v)) => Ok(Stage::constant(
+            u8::from_str_radix(v, 16).context("Invalid fill value")?,
+        )),
+        (Some("random"), Some(v)) if v.len() == 64 => {
+            let mut seed = [0u8; 32];
+            for (i, b) in seed.iter_mut().enumerate() {
+                *b = u8::from_str_radix(&v[i * 2..i * 2 + 2], 16).context("Invalid seed")?;
+            }
+            Ok(Stage::random_with_seed(seed))
+        }
+        _ => Err(anyhow!("Unknown stage {}", s)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::sanitization::SchemeRepo;
+    use assert_matches::*;
+
+    #[test]
+    fn test_checkpoint_roundtrip() {
+        let schemes = SchemeRepo::default();
+        let scheme = schemes.find("dod").unwrap();
+        let task = WipeTask::new(scheme.clone(), Verify::All, 100000, 32768, 512).unwrap();
+        let state = WipeState {
+            stage: 2,
+            position: 65536,
+            at_verification: true,
+            ..Default::default()
+        };
+        state.bad_blocks.borrow_mut().mark(7);
+        state.bad_blocks.borrow_mut().mark(130);
+
+        let checkpoint = Checkpoint::capture("/dev/sdx", &task, &state);
+        let restored = Checkpoint::parse(&checkpoint.to_string()).unwrap();
+
+        assert_eq!("/dev/sdx", restored.device_id);
+        assert_eq!(checkpoint.to_string(), restored.to_string());
+
+        let restored_task = restored.task().unwrap();
+        assert_eq!(100000, restored_task.total_size);
+        assert_eq!(32768, restored_task.block_size);
+        assert_eq!(512, restored_task.sector_size);
+        assert_eq!(Verify::All, restored_task.verify);
+        assert_eq!(3, restored_task.scheme.stages.len());
+        assert_matches!(restored_task.scheme.stages[1], Stage::Fill { value: 0xff });
+
+        match (&scheme.stages[2], &restored_task.scheme.stages[2]) {
+            (Stage::Random { seed: a }, Stage::Random { seed: b }) => assert_eq!(a, b),
+            _ => panic!("random stage expected"),
+        }
+
+        let restored_state = restored.state();
+        assert_eq!(2, restored_state.stage);
+        assert_eq!(65536, restored_state.position);
+        assert!(restored_state.at_verification);
+        assert_eq!(2, restored_state.bad_blocks.borrow().total_marked());
+        assert!(restored_state.bad_blocks.borrow().is_marked(130));
+    }
+
+    #[test]
+    fn test_checkpoint_invalid() {
+        assert_matches!(Checkpoint::parse(""), Err(_));
+        assert_matches!(Checkpoint::parse("device=/dev/sdx\nsize=xxx"), Err(_));
+        assert_matches!(parse_stage("random:00"), Err(_));
+        assert_matches!(parse_stage("fill:zz"), Err(_));
+        assert_matches!(parse_stage("fill:aa"), Ok(Stage::Fill { value: 0xaa }));
+    }
+}
@@ -0,0 +1,97 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Allows to pause, resume or cancel a running wipe from another thread.
+/// The requests are handled between blocks, after flushing the written data.
+#[derive(Debug, Clone, Default)]
+pub struct WipeControl {
+    paused: Arc<AtomicBool>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl WipeControl {
+    pub fn pause(&self) {
+        self.paused.store(true, Ordering::SeqCst);
+    }
+
+    pub fn resume(&self) {
+        self.paused.store(false, Ordering::SeqCst);
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst)
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+}
+
+/// A cancellation request checked by the wipe engine between blocks. A cancelled wipe completes
+/// with `WipeError::Cancelled` leaving `WipeState` at the last written block, the same state can
+/// be passed to another run to continue the wipe.
+pub trait CancellationToken: Send + Sync {
+    fn is_cancelled(&self) -> bool;
+}
+
+impl CancellationToken for AtomicBool {
+    fn is_cancelled(&self) -> bool {
+        self.load(Ordering::SeqCst)
+    }
+}
+
+impl<T: CancellationToken + ?Sized> CancellationToken for Arc<T> {
+    fn is_cancelled(&self) -> bool {
+        (**self).is_cancelled()
+    }
+}
+
+impl CancellationToken for WipeControl {
+    fn is_cancelled(&self) -> bool {
+        WipeControl::is_cancelled(self)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_control_shared_state() {
+        let control = WipeControl::default();
+        let remote = control.clone();
+
+        assert!(!control.is_paused());
+        assert!(!control.is_cancelled());
+
+        remote.pause();
+        assert!(control.is_paused());
+
+        remote.resume();
+        assert!(!control.is_paused());
+
+        remote.cancel();
+        assert!(control.is_cancelled());
+    }
+
+    #[test]
+    fn test_cancellation_tokens() {
+        let flag = Arc::new(AtomicBool::new(false));
+        let token: &dyn CancellationToken = &flag;
+        assert!(!token.is_cancelled());
+
+        flag.store(true, Ordering::SeqCst);
+        assert!(token.is_cancelled());
+
+        let control = WipeControl::default();
+        let token: &dyn CancellationToken = &control;
+        assert!(!token.is_cancelled());
+
+        control.cancel();
+        assert!(token.is_cancelled());
+    }
+}
@@ -0,0 +1,253 @@
+use crate::actions::{Verify, WipeError, WipeEventReceiver, WipeState, WipeTask};
+use crate::sanitization::{Scheme, Stage};
+use crate::storage::{read_sectors, StorageAccess};
+use anyhow::{Context, Result};
+use regex::Regex;
+use std::convert::TryInto;
+
+const LUKS_MAGIC: &[u8] = b"LUKS\xba\xbe";
+const LUKS1_KEYSLOTS: usize = 8;
+const LUKS1_KEYSLOTS_OFFSET: usize = 208;
+const LUKS1_KEYSLOT_SIZE: usize = 48;
+const LUKS1_SECTOR_SIZE: u64 = 512;
+const LUKS1_KEYSLOT_ALIGNMENT: u64 = 4096;
+const LUKS2_BINARY_HEADER_SIZE: u64 = 4096;
+/// The largest header size allowed by the LUKS2 specification.
+const LUKS2_MAX_HEADER_SIZE: u64 = 4 * 1024 * 1024;
+
+/// Location of the LUKS key material, found in the header.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LuksHeader {
+    pub version: u16,
+    pub uuid: Option<String>,
+    /// End of the area with the headers and all keyslots, the encrypted data follows.
+    pub key_material_end: u64,
+}
+
+impl LuksHeader {
+    /// Returns `None` if there is no LUKS header at the beginning of the storage.
+    pub fn read(access: &mut dyn StorageAccess, sector_size: usize) -> Result<Option<LuksHeader>> {
+        let binary_sectors = (LUKS2_BINARY_HEADER_SIZE as usize).div_ceil(sector_size);
+        let header = read_sectors(access, 0, binary_sectors, sector_size)?;
+        if !header.starts_with(LUKS_MAGIC) {
+            return Ok(None);
+        }
+
+        let version = u16::from_be_bytes([header[6], header[7]]);
+        let uuid: String = header[168..208]
+            .iter()
+            .take_while(|c| **c != 0 && c.is_ascii())
+            .map(|c| *c as char)
+            .collect();
+        let uuid = Some(uuid).filter(|u| !u.is_empty());
+
+        let key_material_end = match version {
+            1 => luks1_key_material_end(&header),
+            2 => {
+                let header_size = u64::from_be_bytes(header[8..16].try_into().unwrap());
+                if header_size <= LUKS2_BINARY_HEADER_SIZE || header_size > LUKS2_MAX_HEADER_SIZE {
+                    Err(anyhow!("Invalid LUKS2 header size {}", header_size))?;
+                }
+                let sectors = (header_size as usize).div_ceil(sector_size);
+                let header = read_sectors(access, 0, sectors, sector_size)?;
+                let json = &header[LUKS2_BINARY_HEADER_SIZE as usize..header_size as usize];
+                // both copies of the header go first, then the keyslots area
+                2 * header_size + luks2_keyslots_size(json)?
+            }
+            v => Err(anyhow!("Unsupported LUKS version {}", v))?,
+        };
+
+        Ok(Some(LuksHeader {
+            version,
+            uuid,
+            key_material_end,
+        }))
+    }
+}
+
+fn luks1_key_material_end(header: &[u8]) -> u64 {
+    let be = |offset: usize| u32::from_be_bytes(header[offset..offset + 4].try_into().unwrap());
+    let payload_offset = be(104) as u64 * LUKS1_SECTOR_SIZE;
+    let key_bytes = be(108) as u64;
+
+    // disabled keyslots are included too, their areas may still contain old key material
+    (0..LUKS1_KEYSLOTS)
+        .map(|i| LUKS1_KEYSLOTS_OFFSET + i * LUKS1_KEYSLOT_SIZE)
+        .map(|slot| {
+            let offset = be(slot + 40) as u64 * LUKS1_SECTOR_SIZE;
+            let stripes = be(slot + 44) as u64;
+            offset
+                + (key_bytes * stripes).div_ceil(LUKS1_KEYSLOT_ALIGNMENT) * LUKS1_KEYSLOT_ALIGNMENT
+        })
+        .fold(payload_offset, u64::max)
+}
+
+fn luks2_keyslots_size(json: &[u8]) -> Result<u64> {
+    let json = String::from_utf8_lossy(json);
+    let keyslots_size_regex = Regex::new(r#""keyslots_size"\s*:\s*"(\d+)""#).unwrap();
+    keyslots_size_regex
+        .captures(&json)
+        .and_then(|c| c[1].parse().ok())
+        .ok_or_else(|| anyhow!("Unable to find the keyslots area in the LUKS2 metadata"))
+}
+
+/// Destroys the key material of a LUKS container, making the encrypted data unrecoverable
+/// without wiping all of it. Both copies of the header and all keyslots are overwritten
+/// with random data and verified.
+#[derive(Debug)]
+pub struct CryptoErase {
+    pub header: LuksHeader,
+    pub task: WipeTask,
+}
+
+impl CryptoErase {
+    pub fn new(
+        header: LuksHeader,
+        total_size: u64,
+        block_size: usize,
+        sector_size: usize,
+    ) -> Result<CryptoErase> {
+        if header.key_material_end > total_size {
+            Err(anyhow!(
+                "LUKS key material ends beyond the end of the storage ({} bytes)",
+                header.key_material_end
+            ))?;
+        }
+
+        let scheme = Scheme {
+            description: "Random Fill".to_string(),
+            stages: vec![Stage::random()],
+        };
+        let task = WipeTask::new(
+            scheme,
+            Verify::Last,
+            header.key_material_end,
+            block_size,
+            sector_size,
+        )?;
+
+        Ok(CryptoErase { header, task })
+    }
+
+    /// Overwrites the key material, then makes sure the header is not recognized anymore.
+    pub fn run(
+        &self,
+        access: &mut dyn StorageAccess,
+        state: &mut WipeState,
+        frontend: &mut dyn WipeEventReceiver,
+    ) -> Result<()> {
+        if !self.task.run(access, state, frontend) {
+            Err(anyhow!("Unable to overwrite the LUKS key material"))?;
+        }
+
+        if state.bad_blocks.borrow().total_marked() > 0 {
+            Err(anyhow!(
+                "Some sectors of the LUKS key material couldn't be overwritten"
+            ))?;
+        }
+
+        if LuksHeader::read(access, self.task.sector_size)?.is_some() {
+            Err(WipeError::VerificationFailed)
+                .context("LUKS header is still present after erasing")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_support::{MemoryStorage, StubReceiver};
+    use std::io::Cursor;
+
+    const UUID: &[u8] = b"0ed1ae93-8b9b-4b7b-a0b0-8a7c5a4d8f2e";
+
+    fn luks1_image() -> Vec<u8> {
+        let mut data = vec![0x55u8; 1 << 20];
+        data[..LUKS1_KEYSLOTS_OFFSET + LUKS1_KEYSLOTS * LUKS1_KEYSLOT_SIZE].fill(0);
+        data[..6].copy_from_slice(LUKS_MAGIC);
+        data[6..8].copy_from_slice(&1u16.to_be_bytes());
+        data[104..108].copy_from_slice(&1024u32.to_be_bytes());
+        data[108..112].copy_from_slice(&64u32.to_be_bytes());
+        data[168..168 + UUID.len()].copy_from_slice(UUID);
+        for i in 0..LUKS1_KEYSLOTS {
+            let slot = LUKS1_KEYSLOTS_OFFSET + i * LUKS1_KEYSLOT_SIZE;
+            let offset = 8 + i as u32 * 512;
+            data[slot + 40..slot + 44].copy_from_slice(&offset.to_be_bytes());
+            data[slot + 44..slot + 48].copy_from_slice(&4000u32.to_be_bytes());
+        }
+        data
+    }
+
+    fn luks2_image() -> Vec<u8> {
+        let mut data = vec![0x55u8; 1 << 20];
+        let json = br#"{"keyslots":{},"config":{"json_size":"12288","keyslots_size":"131072"}}"#;
+        for header_offset in &[0usize, 16384] {
+            let h = &mut data[*header_offset..header_offset + 16384];
+            h.fill(0);
+            h[..6].copy_from_slice(LUKS_MAGIC);
+            h[6..8].copy_from_slice(&2u16.to_be_bytes());
+            h[8..16].copy_from_slice(&16384u64.to_be_bytes());
+            h[168..168 + UUID.len()].copy_from_slice(UUID);
+            h[4096..4096 + json.len()].copy_from_slice(json);
+        }
+        data
+    }
+
+    #[test]
+    fn test_reading_luks1_header() {
+        let mut storage = MemoryStorage(Cursor::new(luks1_image()));
+        let header = LuksHeader::read(&mut storage, 512).unwrap().unwrap();
+
+        assert_eq!(1, header.version);
+        assert_eq!(Some(String::from_utf8(UUID.to_vec()).unwrap()), header.uuid);
+        // the last keyslot starts at sector 3592 and takes 64 * 4000 bytes rounded up to 4k
+        assert_eq!(3592 * 512 + 258048, header.key_material_end);
+    }
+
+    #[test]
+    fn test_reading_luks2_header() {
+        let mut storage = MemoryStorage(Cursor::new(luks2_image()));
+        let header = LuksHeader::read(&mut storage, 512).unwrap().unwrap();
+
+        assert_eq!(2, header.version);
+        assert_eq!(2 * 16384 + 131072, header.key_material_end);
+    }
+
+    #[test]
+    fn test_reading_no_luks_header() {
+        let mut storage = MemoryStorage(Cursor::new(vec![0u8; 8192]));
+        assert!(LuksHeader::read(&mut storage, 512).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_crypto_erase() {
+        let mut storage = MemoryStorage(Cursor::new(luks2_image()));
+        let header = LuksHeader::read(&mut storage, 512).unwrap().unwrap();
+        let end = header.key_material_end as usize;
+
+        let erase = CryptoErase::new(header, 1 << 20, 65536, 512).unwrap();
+        erase
+            .run(
+                &mut storage,
+                &mut WipeState::default(),
+                &mut StubReceiver::new(),
+            )
+            .unwrap();
+
+        let data = storage.0.into_inner();
+        assert!(!data.starts_with(LUKS_MAGIC));
+        assert!(!data[16384..].starts_with(LUKS_MAGIC));
+        assert!(data[..end].windows(UUID.len()).all(|w| w != UUID));
+        assert!(data[end..].iter().all(|b| *b == 0x55));
+    }
+
+    #[test]
+    fn test_crypto_erase_beyond_storage() {
+        let mut storage = MemoryStorage(Cursor::new(luks2_image()));
+        let header = LuksHeader::read(&mut storage, 512).unwrap().unwrap();
+
+        assert!(CryptoErase::new(header, 65536, 65536, 512).is_err());
+    }
+}
@@ -0,0 +1,260 @@
+use crate::actions::{Verify, WipeEventReceiver, WipeState, WipeTask};
+use crate::sanitization::Scheme;
+use crate::storage::{StorageAccess, StorageError, System, IMAGE_SECTOR_SIZE};
+use anyhow::{Context, Result};
+use std::fs::{File, OpenOptions};
+use std::io::prelude::*;
+use std::io::SeekFrom;
+use std::path::{Path, PathBuf};
+
+/// Maximum size of a single fill file, below the FAT32 file size limit.
+const FILL_FILE_SIZE: u64 = 1 << 30;
+
+/// Fills the free space of a mounted filesystem with files, overwriting the remnants
+/// of deleted data, then removes the files. A reserve of free space is left untouched,
+/// so other processes don't fail while the filesystem is full.
+pub struct FreeSpaceWipe {
+    pub task: WipeTask,
+    pub mount_point: PathBuf,
+}
+
+impl FreeSpaceWipe {
+    pub fn new(
+        mount_point: &Path,
+        scheme: Scheme,
+        verify: Verify,
+        block_size: usize,
+        reserve: u64,
+    ) -> Result<Self> {
+        if block_size == 0 || !FILL_FILE_SIZE.is_multiple_of(block_size as u64) {
+            Err(anyhow!(
+                "Block size should be a power of two up to {} bytes.",
+                FILL_FILE_SIZE
+            ))?;
+        }
+
+        let available = System::free_space(mount_point)?;
+        let total_size = available.saturating_sub(reserve);
+        if total_size == 0 {
+            Err(anyhow!(
+                "Not enough free space, only {} bytes available.",
+                available
+            ))?;
+        }
+
+        Ok(FreeSpaceWipe {
+            task: WipeTask::new(scheme, verify, total_size, block_size, IMAGE_SECTOR_SIZE)?,
+            mount_point: mount_point.to_path_buf(),
+        })
+    }
+
+    /// Runs the task over the fill files, which are removed afterwards. The filesystem
+    /// getting full before the estimated free space is taken ends the stage early.
+    pub fn run(&self, state: &mut WipeState, frontend: &mut dyn WipeEventReceiver) -> Result<bool> {
+        let mut files = FillFiles::create(&self.mount_point)?;
+        let succeeded = self.task.run(&mut files, state, frontend);
+        files.remove()?;
+        Ok(succeeded)
+    }
+}
+
+/// Files taking up the free space, created in a hidden directory at the root of the filesystem.
+struct FillFiles {
+    dir: PathBuf,
+    files: Vec<File>,
+    position: u64,
+}
+
+impl FillFiles {
+    fn create(mount_point: &Path) -> Result<FillFiles> {
+        let dir = mount_point.join(format!(".lethe-free-space-{}", std::process::id()));
+        std::fs::create_dir(&dir).context(format!("Unable to create {}", dir.display()))?;
+        Ok(FillFiles {
+            dir,
+            files: vec![],
+            position: 0,
+        })
+    }
+
+    fn file_at(&mut self, position: u64) -> std::io::Result<(&mut File, u64)> {
+        let index = (position / FILL_FILE_SIZE) as usize;
+        while self.files.len() <= index {
+            let path = self.dir.join(format!("fill-{:06}", self.files.len()));
+            let file = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .open(path)?;
+            self.files.push(file);
+        }
+        Ok((&mut self.files[index], position % FILL_FILE_SIZE))
+    }
+
+    /// Writes the data to the disk and evicts it from the cache, so the verification
+    /// reads the actual disk content.
+    fn sync(&mut self) -> Result<()> {
+        for file in &self.files {
+            file.sync_all().context("Unable to sync fill file")?;
+            drop_cache(file);
+        }
+        Ok(())
+    }
+
+    fn remove(self) -> Result<()> {
+        let dir = self.dir;
+        drop(self.files);
+        std::fs::remove_dir_all(&dir).context(format!("Unable to remove {}", dir.display()))
+    }
+}
+
+/// The files are accessed as one storage, each block of the task falls into a single file.
+impl StorageAccess for FillFiles {
+    fn position(&mut self) -> Result<u64> {
+        Ok(self.position)
+    }
+
+    fn seek(&mut self, position: u64) -> Result<u64> {
+        self.position = position;
+        Ok(position)
+    }
+
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize> {
+        let (file, offset) = self.file_at(self.position).map_err(StorageError::from)?;
+        file.seek(SeekFrom::Start(offset))
+            .and_then(|_| file.read_exact(buffer))
+            .map_err(StorageError::from)?;
+        self.position += buffer.len() as u64;
+        Ok(buffer.len())
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<()> {
+        let (file, offset) = self.file_at(self.position).map_err(StorageError::from)?;
+        file.seek(SeekFrom::Start(offset))
+            .and_then(|_| file.write_all(data))
+            .map_err(StorageError::from)?;
+        self.position += data.len() as u64;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.sync()
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn drop_cache(file: &File) {
+    use std::os::unix::io::AsRawFd;
+    unsafe {
+        libc::posix_fadvise(file.as_raw_fd(), 0, 0, libc::POSIX_FADV_DONTNEED);
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn drop_cache(_file: &File) {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::actions::{WipeError, WipeEvent};
+    use crate::sanitization::SchemeRepo;
+    use crate::test_support::{StubReceiver, TempDir};
+    use assert_matches::*;
+    use WipeEvent::*;
+
+    #[test]
+    fn test_free_space_wipe_validation() {
+        let dir = TempDir::new("free-validation");
+        let root = dir.path();
+        let scheme = SchemeRepo::default().find("zero").unwrap().clone();
+
+        assert!(FreeSpaceWipe::new(root, scheme.clone(), Verify::No, 4096, 0).is_ok());
+        assert!(FreeSpaceWipe::new(root, scheme.clone(), Verify::No, 4000, 0).is_err());
+        assert!(FreeSpaceWipe::new(root, scheme.clone(), Verify::No, 4096, u64::MAX).is_err());
+    }
+
+    #[test]
+    fn test_free_space_wipe_happy_path() {
+        let dir = TempDir::new("free-wipe");
+        let root = dir.path();
+        let scheme = SchemeRepo::default().find("random2x").unwrap().clone();
+
+        let wipe = FreeSpaceWipe {
+            task: WipeTask::new(scheme, Verify::All, 100000, 32768, IMAGE_SECTOR_SIZE).unwrap(),
+            mount_point: root.to_path_buf(),
+        };
+        let mut receiver = StubReceiver::new();
+        let mut state = WipeState::default();
+
+        assert!(wipe.run(&mut state, &mut receiver).unwrap());
+
+        let mut e = receiver.collected.iter();
+        assert_matches!(e.next(), Some((_, Started)));
+        for stage in 0..2 {
+            for verification in &[false, true] {
+                assert_matches!(e.next(), Some((ref s, StageStarted))
+                    if s.stage == stage && s.at_verification == *verification);
+                assert_matches!(e.next(), Some((_, Progress(0))));
+                assert_matches!(e.next(), Some((_, Progress(32768))));
+                assert_matches!(e.next(), Some((_, Progress(65536))));
+                assert_matches!(e.next(), Some((_, Progress(98304))));
+                assert_matches!(e.next(), Some((_, Progress(100000))));
+                assert_matches!(e.next(), Some((_, StageCompleted(None))));
+            }
+        }
+        assert_matches!(e.next(), Some((_, Completed(None))));
+        assert_matches!(e.next(), None);
+
+        assert_eq!(0, std::fs::read_dir(root).unwrap().count());
+    }
+
+    #[test]
+    fn test_free_space_wipe_cancelled() {
+        let dir = TempDir::new("free-cancel");
+        let root = dir.path();
+        let scheme = SchemeRepo::default().find("zero").unwrap().clone();
+
+        let wipe = FreeSpaceWipe {
+            task: WipeTask::new(scheme, Verify::No, 100000, 32768, IMAGE_SECTOR_SIZE).unwrap(),
+            mount_point: root.to_path_buf(),
+        };
+        wipe.task.control.cancel();
+        let mut receiver = StubReceiver::new();
+        let mut state = WipeState::default();
+
+        assert!(!wipe.run(&mut state, &mut receiver).unwrap());
+
+        let mut e = receiver.collected.iter();
+        assert_matches!(e.next(), Some((_, Started)));
+        assert_matches!(e.next(), Some((_, StageStarted)));
+        assert_matches!(e.next(), Some((_, Progress(0))));
+        assert_matches!(e.next(), Some((_, StageCompleted(Some(_)))));
+        assert_matches!(e.next(), Some((_, Completed(Some(err))))
+            if matches!(err.downcast_ref::<WipeError>(), Some(WipeError::Cancelled)));
+        assert_matches!(e.next(), None);
+
+        // the fill files are removed anyway
+        assert_eq!(0, std::fs::read_dir(root).unwrap().count());
+    }
+
+    #[test]
+    fn test_fill_files_span() {
+        let dir = TempDir::new("free-files");
+        let root = dir.path();
+        let mut files = FillFiles::create(root).unwrap();
+
+        files.seek(FILL_FILE_SIZE - 512).unwrap();
+        files.write(&[1u8; 512]).unwrap();
+        files.write(&[2u8; 512]).unwrap();
+        assert_eq!(2, files.files.len());
+        assert_eq!(FILL_FILE_SIZE + 512, files.position().unwrap());
+
+        let mut buf = [0u8; 512];
+        files.seek(FILL_FILE_SIZE).unwrap();
+        files.read(&mut buf).unwrap();
+        assert!(buf.iter().all(|b| *b == 2));
+
+        files.remove().unwrap();
+        assert_eq!(0, std::fs::read_dir(root).unwrap().count());
+    }
+}
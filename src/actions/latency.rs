@@ -0,0 +1,83 @@
+use std::time::Duration;
+
+const BUCKET_BOUNDS_MICROS: [u64; 6] = [1_000, 10_000, 100_000, 1_000_000, 10_000_000, 60_000_000];
+
+/// Counts of I/O operations grouped by their duration on a logarithmic scale.
+#[derive(Debug, Clone, Default)]
+pub struct LatencyHistogram {
+    buckets: [u64; BUCKET_BOUNDS_MICROS.len() + 1],
+    max: Duration,
+}
+
+impl LatencyHistogram {
+    pub fn record(&mut self, latency: Duration) {
+        let micros = latency.as_micros() as u64;
+        let bucket = BUCKET_BOUNDS_MICROS
+            .iter()
+            .position(|b| micros < *b)
+            .unwrap_or(BUCKET_BOUNDS_MICROS.len());
+
+        self.buckets[bucket] += 1;
+        self.max = self.max.max(latency);
+    }
+
+    pub fn total(&self) -> u64 {
+        self.buckets.iter().sum()
+    }
+
+    pub fn max(&self) -> Duration {
+        self.max
+    }
+
+    /// Non-empty buckets as (upper bound, count) pairs, the last bucket has no upper bound.
+    pub fn buckets(&self) -> Vec<(Option<Duration>, u64)> {
+        self.buckets
+            .iter()
+            .enumerate()
+            .filter(|(_, count)| **count > 0)
+            .map(|(i, count)| {
+                let bound = BUCKET_BOUNDS_MICROS
+                    .get(i)
+                    .map(|b| Duration::from_micros(*b));
+                (bound, *count)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_empty_histogram() {
+        let histogram = LatencyHistogram::default();
+
+        assert_eq!(0, histogram.total());
+        assert_eq!(Duration::from_secs(0), histogram.max());
+        assert!(histogram.buckets().is_empty());
+    }
+
+    #[test]
+    fn test_histogram_buckets() {
+        let mut histogram = LatencyHistogram::default();
+
+        histogram.record(Duration::from_micros(10));
+        histogram.record(Duration::from_micros(999));
+        histogram.record(Duration::from_millis(1));
+        histogram.record(Duration::from_millis(250));
+        histogram.record(Duration::from_secs(120));
+
+        assert_eq!(5, histogram.total());
+        assert_eq!(Duration::from_secs(120), histogram.max());
+        assert_eq!(
+            vec![
+                (Some(Duration::from_millis(1)), 2),
+                (Some(Duration::from_millis(10)), 1),
+                (Some(Duration::from_secs(1)), 1),
+                (None, 1),
+            ],
+            histogram.buckets()
+        );
+    }
+}
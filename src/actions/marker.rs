@@ -1,10 +1,11 @@
-use roaring::RoaringBitmap;
+use roaring::RoaringTreemap;
 use std::fmt::{Debug, Formatter};
 
 pub trait BlockMarker {
-    fn mark(&mut self, position: u32);
-    fn is_marked(&self, position: u32) -> bool;
-    fn total_marked(&self) -> u32;
+    fn mark(&mut self, position: u64);
+    fn is_marked(&self, position: u64) -> bool;
+    fn total_marked(&self) -> u64;
+    fn iter(&self) -> Box<dyn Iterator<Item = u64> + '_>;
 }
 
 impl Debug for dyn BlockMarker {
@@ -14,28 +15,32 @@ impl Debug for dyn BlockMarker {
 }
 
 pub struct RoaringBlockMarker {
-    store: RoaringBitmap,
+    store: RoaringTreemap,
 }
 
 impl RoaringBlockMarker {
     pub(crate) fn new() -> RoaringBlockMarker {
         RoaringBlockMarker {
-            store: RoaringBitmap::new(),
+            store: RoaringTreemap::new(),
         }
     }
 }
 
 impl BlockMarker for RoaringBlockMarker {
-    fn mark(&mut self, position: u32) {
+    fn mark(&mut self, position: u64) {
         self.store.insert(position);
     }
 
-    fn is_marked(&self, position: u32) -> bool {
+    fn is_marked(&self, position: u64) -> bool {
         self.store.contains(position)
     }
 
-    fn total_marked(&self) -> u32 {
-        self.store.len() as u32
+    fn total_marked(&self) -> u64 {
+        self.store.len()
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = u64> + '_> {
+        Box::new(self.store.iter())
     }
 }
 
@@ -70,6 +75,8 @@ mod test {
         assert_eq!(2, marker.total_marked());
         assert!(marker.is_marked(13));
         assert!(marker.is_marked(133));
+
+        assert_eq!(vec![13, 133], marker.iter().collect::<Vec<_>>());
     }
 
     #[test]
@@ -82,9 +89,10 @@ mod test {
         assert!(marker.is_marked(0));
         assert!(!marker.is_marked(1));
 
-        marker.mark(u32::max_value());
+        marker.mark(u64::max_value());
         assert_eq!(2, marker.total_marked());
         assert!(marker.is_marked(0));
-        assert!(marker.is_marked(u32::max_value()));
+        assert!(marker.is_marked(u64::max_value()));
+        assert!(!marker.is_marked(1 << 32));
     }
 }
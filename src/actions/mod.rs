@@ -1,4 +1,30 @@
+mod benchmark;
+mod checkpoint;
+mod control;
+mod crypto_erase;
+mod free_space;
+mod latency;
 mod marker;
+mod sanitize;
+mod scan;
+mod shred;
+mod survey;
+mod throttle;
+mod throughput;
+mod watchdog;
 mod wipe;
 
+pub use benchmark::*;
+pub use checkpoint::Checkpoint;
+pub use control::{CancellationToken, WipeControl};
+pub use crypto_erase::*;
+pub use free_space::FreeSpaceWipe;
+pub use latency::LatencyHistogram;
+pub use sanitize::*;
+pub use scan::*;
+pub use shred::*;
+pub use survey::*;
+pub use throttle::RateLimit;
+pub use throughput::ThroughputProfile;
+pub use watchdog::{Clock, IoWatchdog, SystemClock};
 pub use wipe::*;
@@ -0,0 +1,369 @@
+use crate::actions::{Verify, WipeEvent, WipeEventReceiver, WipeState, WipeTask};
+use crate::sanitization::Scheme;
+use crate::storage::{
+    is_command_supported, request_sense, sense_of, DataDirection, ScsiTransport, SCSI_FORMAT_UNIT,
+    SCSI_SANITIZE, SENSE_KEY_NOT_READY, SENSE_KEY_NO_SENSE,
+};
+use anyhow::{Context, Result};
+use std::fmt::{Display, Formatter};
+use std::rc::Rc;
+use std::time::Duration;
+
+/// The command returns right away and the operation continues in the background.
+const IMMEDIATE: u8 = 0x80;
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(60);
+const PROGRESS_POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// Progress indication is reported in 1/65536 units.
+const PROGRESS_SCALE: u64 = 1 << 16;
+/// Nothing is written by the host, the block size only keeps the task valid for large devices.
+const NOMINAL_BLOCK_SECTORS: usize = 2048;
+
+/// Sanitization performed by the device firmware. Unlike overwriting from the host,
+/// it also covers the over-provisioned and remapped areas.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SanitizeMethod {
+    /// SANITIZE OVERWRITE with a zero pattern.
+    Overwrite,
+    /// SANITIZE BLOCK ERASE, sets the physical blocks to a vendor specific value.
+    BlockErase,
+    /// SANITIZE CRYPTOGRAPHIC ERASE, changes the media encryption key.
+    CryptoErase,
+    /// FORMAT UNIT with the security initialize bit, overwrites all the user data
+    /// including the reallocated sectors.
+    SecureFormat,
+}
+
+impl SanitizeMethod {
+    pub const ALL: [SanitizeMethod; 4] = [
+        SanitizeMethod::Overwrite,
+        SanitizeMethod::BlockErase,
+        SanitizeMethod::CryptoErase,
+        SanitizeMethod::SecureFormat,
+    ];
+
+    fn opcode(&self) -> (u8, Option<u16>) {
+        match self {
+            SanitizeMethod::Overwrite => (SCSI_SANITIZE, Some(0x01)),
+            SanitizeMethod::BlockErase => (SCSI_SANITIZE, Some(0x02)),
+            SanitizeMethod::CryptoErase => (SCSI_SANITIZE, Some(0x03)),
+            SanitizeMethod::SecureFormat => (SCSI_FORMAT_UNIT, None),
+        }
+    }
+
+    fn command(&self) -> (Vec<u8>, Vec<u8>) {
+        match self {
+            SanitizeMethod::SecureFormat => {
+                // short parameter list header with FOV, IP and IMMED bits,
+                // then the initialization pattern descriptor with the SI bit and the default pattern
+                let parameters = vec![0, 0x8a, 0, 0, 0x20, 0, 0, 0];
+                (vec![SCSI_FORMAT_UNIT, 0x10, 0, 0, 0, 0], parameters)
+            }
+            _ => {
+                let (opcode, service_action) = self.opcode();
+                let parameters = match self {
+                    // a single pass of a 4 bytes zero pattern
+                    SanitizeMethod::Overwrite => vec![0x01, 0, 0, 4, 0, 0, 0, 0],
+                    _ => vec![],
+                };
+                let mut cdb = vec![0u8; 10];
+                cdb[0] = opcode;
+                cdb[1] = IMMEDIATE | service_action.unwrap() as u8;
+                cdb[7..9].copy_from_slice(&(parameters.len() as u16).to_be_bytes());
+                (cdb, parameters)
+            }
+        }
+    }
+
+    /// Methods implemented by the device.
+    pub fn supported(transport: &mut dyn ScsiTransport) -> Result<Vec<SanitizeMethod>> {
+        let mut supported = vec![];
+        for method in SanitizeMethod::ALL.iter() {
+            let (opcode, service_action) = method.opcode();
+            if is_command_supported(transport, opcode, service_action)? {
+                supported.push(*method);
+            }
+        }
+        Ok(supported)
+    }
+}
+
+impl Display for SanitizeMethod {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            SanitizeMethod::Overwrite => "SANITIZE (overwrite)",
+            SanitizeMethod::BlockErase => "SANITIZE (block erase)",
+            SanitizeMethod::CryptoErase => "SANITIZE (crypto erase)",
+            SanitizeMethod::SecureFormat => "FORMAT UNIT (security initialize)",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Runs a sanitize operation on a SCSI device and reports its progress as wipe events.
+/// The task only describes the operation, the device does all the writing.
+pub struct DeviceSanitize {
+    pub method: SanitizeMethod,
+    pub task: WipeTask,
+    pub poll_interval: Duration,
+}
+
+impl DeviceSanitize {
+    pub fn new(method: SanitizeMethod, total_size: u64, sector_size: usize) -> Result<Self> {
+        let scheme = Scheme {
+            description: method.to_string(),
+            stages: vec![],
+        };
+        Ok(DeviceSanitize {
+            method,
+            task: WipeTask::new(
+                scheme,
+                Verify::No,
+                total_size,
+                sector_size * NOMINAL_BLOCK_SECTORS,
+                sector_size,
+            )?,
+            poll_interval: PROGRESS_POLL_INTERVAL,
+        })
+    }
+
+    pub fn run(
+        &self,
+        transport: &mut dyn ScsiTransport,
+        state: &mut WipeState,
+        frontend: &mut dyn WipeEventReceiver,
+    ) -> bool {
+        frontend.handle(&self.task, state, WipeEvent::Started);
+        frontend.handle(&self.task, state, WipeEvent::StageStarted);
+
+        let error = self.sanitize(transport, state, frontend).err().map(Rc::new);
+
+        let succeeded = error.is_none();
+        frontend.handle(&self.task, state, WipeEvent::StageCompleted(error.clone()));
+        frontend.handle(&self.task, state, WipeEvent::Completed(error));
+
+        succeeded
+    }
+
+    fn sanitize(
+        &self,
+        transport: &mut dyn ScsiTransport,
+        state: &mut WipeState,
+        frontend: &mut dyn WipeEventReceiver,
+    ) -> Result<()> {
+        let (opcode, service_action) = self.method.opcode();
+        if !is_command_supported(transport, opcode, service_action)? {
+            Err(anyhow!("{} is not supported by the device", self.method))?;
+        }
+
+        let (cdb, mut parameters) = self.method.command();
+        let direction = if parameters.is_empty() {
+            DataDirection::None
+        } else {
+            DataDirection::ToDevice
+        };
+        transport
+            .execute(&cdb, direction, &mut parameters, COMMAND_TIMEOUT)
+            .context(format!("{} failed", self.method))?;
+
+        loop {
+            std::thread::sleep(self.poll_interval);
+
+            let sense = match request_sense(transport) {
+                Ok(sense) => sense,
+                // some devices report the progress as an error of the request itself
+                Err(err) => Some(sense_of(&err).cloned().ok_or(err)?),
+            };
+
+            match sense {
+                // logical unit not ready, operation in progress
+                Some(s)
+                    if s.progress.is_some() || (s.key == SENSE_KEY_NOT_READY && s.asc == 0x04) =>
+                {
+                    let progress = s.progress.unwrap_or(0) as u64;
+                    state.position = self.task.total_size / PROGRESS_SCALE * progress
+                        + self.task.total_size % PROGRESS_SCALE * progress / PROGRESS_SCALE;
+                    frontend.handle(&self.task, state, WipeEvent::Progress(state.position));
+                }
+                Some(s) if s.key != SENSE_KEY_NO_SENSE => Err(anyhow!(
+                    "{} failed (sense key {:#04x}, asc {:#04x}, ascq {:#04x})",
+                    self.method,
+                    s.key,
+                    s.asc,
+                    s.ascq
+                ))?,
+                Some(_) => break,
+                None => Err(anyhow!(
+                    "{} status is unknown, the device returned invalid sense data",
+                    self.method
+                ))?,
+            }
+        }
+
+        state.position = self.task.total_size;
+        frontend.handle(&self.task, state, WipeEvent::Progress(state.position));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::storage::{ScsiError, Sense};
+    use crate::test_support::StubReceiver;
+    use assert_matches::*;
+    use std::collections::VecDeque;
+
+    /// Answers every command with the next recorded response.
+    struct MockTransport {
+        sent: Vec<Vec<u8>>,
+        responses: VecDeque<Result<Vec<u8>>>,
+    }
+
+    impl MockTransport {
+        fn new(responses: Vec<Result<Vec<u8>>>) -> Self {
+            MockTransport {
+                sent: vec![],
+                responses: responses.into(),
+            }
+        }
+    }
+
+    impl ScsiTransport for MockTransport {
+        fn execute(
+            &mut self,
+            cdb: &[u8],
+            _direction: DataDirection,
+            data: &mut [u8],
+            _timeout: Duration,
+        ) -> Result<()> {
+            self.sent.push(cdb.to_vec());
+            let response = self.responses.pop_front().expect("unexpected command")?;
+            data[..response.len()].copy_from_slice(&response);
+            Ok(())
+        }
+    }
+
+    fn supported() -> Result<Vec<u8>> {
+        Ok(vec![0, 0x03])
+    }
+
+    fn sense(key: u8, asc: u8, ascq: u8, progress: Option<u16>) -> Vec<u8> {
+        let mut data = vec![0u8; 18];
+        data[0] = 0x70;
+        data[2] = key;
+        data[7] = 10;
+        data[12] = asc;
+        data[13] = ascq;
+        if let Some(p) = progress {
+            data[15] = 0x80;
+            data[16..18].copy_from_slice(&p.to_be_bytes());
+        }
+        data
+    }
+
+    fn sanitize(method: SanitizeMethod, transport: &mut MockTransport) -> (bool, StubReceiver) {
+        let mut sanitize = DeviceSanitize::new(method, 1 << 30, 512).unwrap();
+        sanitize.poll_interval = Duration::from_millis(0);
+        let mut receiver = StubReceiver::new();
+        let result = sanitize.run(transport, &mut WipeState::default(), &mut receiver);
+        (result, receiver)
+    }
+
+    #[test]
+    fn test_sanitize_with_progress() {
+        let mut transport = MockTransport::new(vec![
+            supported(),
+            Ok(vec![]),
+            Ok(sense(SENSE_KEY_NOT_READY, 0x04, 0x1b, Some(0x4000))),
+            Ok(sense(SENSE_KEY_NOT_READY, 0x04, 0x1b, Some(0xc000))),
+            Ok(sense(SENSE_KEY_NO_SENSE, 0, 0, None)),
+        ]);
+
+        let (result, receiver) = sanitize(SanitizeMethod::BlockErase, &mut transport);
+
+        assert!(result);
+        assert_eq!(vec![1 << 28, 3 << 28, 1 << 30], receiver.progress());
+        assert_eq!(
+            vec![0xa3, 0x0c, 0x02, 0x48, 0x00, 0x02, 0, 0, 0, 16, 0, 0],
+            transport.sent[0]
+        );
+        assert_eq!(vec![0x48, 0x82, 0, 0, 0, 0, 0, 0, 0, 0], transport.sent[1]);
+        assert_eq!(0x03, transport.sent[2][0]);
+    }
+
+    #[test]
+    fn test_secure_format() {
+        let mut transport = MockTransport::new(vec![
+            supported(),
+            Ok(vec![]),
+            Err(ScsiError::CheckCondition(
+                Sense::parse(&sense(SENSE_KEY_NOT_READY, 0x04, 0x04, Some(0x8000))).unwrap(),
+            )
+            .into()),
+            Ok(sense(SENSE_KEY_NO_SENSE, 0, 0, None)),
+        ]);
+
+        let (result, receiver) = sanitize(SanitizeMethod::SecureFormat, &mut transport);
+
+        assert!(result);
+        assert_eq!(vec![1 << 29, 1 << 30], receiver.progress());
+        assert_eq!(vec![0x04, 0x10, 0, 0, 0, 0], transport.sent[1]);
+    }
+
+    #[test]
+    fn test_unsupported_method() {
+        let mut transport = MockTransport::new(vec![Ok(vec![0, 0x01])]);
+
+        let (result, receiver) = sanitize(SanitizeMethod::CryptoErase, &mut transport);
+
+        assert!(!result);
+        assert_eq!(1, transport.sent.len());
+        assert_matches!(receiver.collected.last(), Some((_, WipeEvent::Completed(Some(err))))
+            if err.to_string().contains("not supported"));
+    }
+
+    #[test]
+    fn test_failed_sanitize() {
+        let mut transport = MockTransport::new(vec![
+            supported(),
+            Ok(vec![]),
+            Ok(sense(0x03, 0x31, 0x03, None)),
+        ]);
+
+        let (result, receiver) = sanitize(SanitizeMethod::Overwrite, &mut transport);
+
+        assert!(!result);
+        assert!(receiver.progress().is_empty());
+        assert_matches!(receiver.collected.last(), Some((_, WipeEvent::Completed(Some(err))))
+            if err.to_string().contains("asc 0x31"));
+    }
+
+    #[test]
+    fn test_invalid_sense_data() {
+        let mut transport = MockTransport::new(vec![supported(), Ok(vec![]), Ok(vec![0u8; 18])]);
+
+        let (result, receiver) = sanitize(SanitizeMethod::BlockErase, &mut transport);
+
+        assert!(!result);
+        assert_matches!(receiver.collected.last(), Some((_, WipeEvent::Completed(Some(err))))
+            if err.to_string().contains("invalid sense data"));
+    }
+
+    #[test]
+    fn test_supported_methods() {
+        let mut transport = MockTransport::new(vec![
+            Ok(vec![0, 0x01]),
+            supported(),
+            Err(
+                ScsiError::CheckCondition(Sense::parse(&sense(0x05, 0x24, 0, None)).unwrap())
+                    .into(),
+            ),
+            supported(),
+        ]);
+
+        assert_eq!(
+            vec![SanitizeMethod::BlockErase, SanitizeMethod::SecureFormat],
+            SanitizeMethod::supported(&mut transport).unwrap()
+        );
+    }
+}
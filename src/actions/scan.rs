@@ -0,0 +1,331 @@
+use crate::sanitization::mem::AlignedBuffer;
+use crate::storage::{ReadOnlyFileAccess, StorageAccess, StorageRef};
+use anyhow::Result;
+use std::fmt::{Display, Formatter};
+
+/// Size of the chunks the read data is classified by.
+pub const SCAN_CHUNK_SIZE: usize = 4096;
+
+/// Signatures are looked for at every sector boundary, where files usually start.
+const SIGNATURE_ALIGNMENT: usize = 512;
+
+/// Shannon entropy (bits per byte) above which a chunk is considered random.
+/// Random data gives ~7.95 for 4KiB chunks, while compressed files rarely stay that high for long.
+const HIGH_ENTROPY_THRESHOLD: f64 = 7.5;
+
+/// Maximum number of reported locations, the counters are still complete.
+const MAX_FINDINGS: usize = 100;
+
+const FILE_SIGNATURES: &[(&[u8], &str)] = &[
+    (b"\xff\xd8\xff", "JPEG"),
+    (b"%PDF-", "PDF"),
+    (b"PK\x03\x04", "ZIP"),
+    (b"SQLite format 3\x00", "SQLite"),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ChunkClass {
+    /// All bytes have the same value, e.g. zeroes.
+    Constant,
+    /// Looks like random data.
+    HighEntropy,
+    /// Anything else, possibly recognizable data.
+    Data,
+    Unreadable,
+}
+
+impl Display for ChunkClass {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            ChunkClass::Constant => "Constant",
+            ChunkClass::HighEntropy => "High entropy",
+            ChunkClass::Data => "Data",
+            ChunkClass::Unreadable => "Unreadable",
+        };
+        f.write_str(name)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ScanReport {
+    pub scanned: u64,
+    pub constant: u64,
+    pub high_entropy: u64,
+    pub data: u64,
+    pub unreadable: u64,
+    /// Positions of the first chunks classified as data.
+    pub data_positions: Vec<u64>,
+    /// Positions and names of the first file signatures found.
+    pub signatures: Vec<(u64, &'static str)>,
+    pub signatures_found: u64,
+}
+
+impl ScanReport {
+    /// No recognizable data was found.
+    pub fn is_clean(&self) -> bool {
+        self.data == 0 && self.signatures_found == 0
+    }
+
+    pub fn count(&self, class: ChunkClass) -> u64 {
+        match class {
+            ChunkClass::Constant => self.constant,
+            ChunkClass::HighEntropy => self.high_entropy,
+            ChunkClass::Data => self.data,
+            ChunkClass::Unreadable => self.unreadable,
+        }
+    }
+
+    fn add(&mut self, position: u64, class: ChunkClass) {
+        self.scanned += 1;
+        match class {
+            ChunkClass::Constant => self.constant += 1,
+            ChunkClass::HighEntropy => self.high_entropy += 1,
+            ChunkClass::Data => {
+                self.data += 1;
+                if self.data_positions.len() < MAX_FINDINGS {
+                    self.data_positions.push(position);
+                }
+            }
+            ChunkClass::Unreadable => self.unreadable += 1,
+        }
+    }
+}
+
+/// Reads a storage and looks for anything that doesn't look wiped,
+/// independently of how the storage was wiped.
+#[derive(Debug, Clone)]
+pub struct ScanTask {
+    pub total_size: u64,
+    pub block_size: usize,
+    /// Percentage of the blocks to read, evenly spread over the storage.
+    pub sample_percent: u32,
+}
+
+impl ScanTask {
+    pub fn new(total_size: u64, block_size: usize, sample_percent: u32) -> Result<ScanTask> {
+        if block_size == 0 || !block_size.is_multiple_of(SCAN_CHUNK_SIZE) {
+            Err(anyhow!(
+                "Block size should be a multiple of {} bytes.",
+                SCAN_CHUNK_SIZE
+            ))?;
+        }
+        if sample_percent == 0 || sample_percent > 100 {
+            Err(anyhow!("Sample should be between 1 and 100 percent."))?;
+        }
+        Ok(ScanTask {
+            total_size,
+            block_size,
+            sample_percent,
+        })
+    }
+
+    /// Scans the storage opened read-only, bypassing the OS cache, so the data still
+    /// cached after a wipe is not mistaken for the content of the media.
+    pub fn scan(
+        &self,
+        storage: &StorageRef,
+        on_progress: &mut dyn FnMut(u64),
+    ) -> Result<ScanReport> {
+        let mut access = ReadOnlyFileAccess::open_direct(storage)?;
+        self.run(&mut access, on_progress)
+    }
+
+    pub fn run(
+        &self,
+        access: &mut dyn StorageAccess,
+        on_progress: &mut dyn FnMut(u64),
+    ) -> Result<ScanReport> {
+        let buf = AlignedBuffer::new(self.block_size, SCAN_CHUNK_SIZE);
+        let mut report = ScanReport::default();
+        let mut position = 0;
+
+        for index in 0u64.. {
+            if position >= self.total_size {
+                break;
+            }
+            let length = (self.total_size - position).min(self.block_size as u64) as usize;
+
+            if self.is_sampled(index) {
+                let data = &mut buf.as_mut_slice()[..length];
+                match read_block(access, position, data) {
+                    Ok(()) => {
+                        for (i, chunk) in data.chunks(SCAN_CHUNK_SIZE).enumerate() {
+                            let chunk_position = position + (i * SCAN_CHUNK_SIZE) as u64;
+                            let class = classify(chunk);
+                            // short signatures occur in random data by chance
+                            if class != ChunkClass::HighEntropy {
+                                find_signatures(chunk, chunk_position, &mut report);
+                            }
+                            report.add(chunk_position, class);
+                        }
+                    }
+                    Err(_) => {
+                        for i in 0..length.div_ceil(SCAN_CHUNK_SIZE) {
+                            report.add(
+                                position + (i * SCAN_CHUNK_SIZE) as u64,
+                                ChunkClass::Unreadable,
+                            );
+                        }
+                    }
+                }
+            }
+
+            position += length as u64;
+            on_progress(position);
+        }
+
+        Ok(report)
+    }
+
+    /// Picks `sample_percent` blocks out of every 100, as evenly as possible.
+    fn is_sampled(&self, index: u64) -> bool {
+        (index * self.sample_percent as u64) % 100 < self.sample_percent as u64
+    }
+}
+
+fn read_block(access: &mut dyn StorageAccess, position: u64, data: &mut [u8]) -> Result<()> {
+    access.seek(position)?;
+    let mut read = 0;
+    while read < data.len() {
+        match access.read(&mut data[read..])? {
+            0 => Err(anyhow!("Unexpected end of the storage"))?,
+            n => read += n,
+        }
+    }
+    Ok(())
+}
+
+pub fn classify(chunk: &[u8]) -> ChunkClass {
+    if chunk.iter().all(|b| *b == chunk[0]) {
+        return ChunkClass::Constant;
+    }
+
+    let mut counts = [0u32; 256];
+    for b in chunk {
+        counts[*b as usize] += 1;
+    }
+    let total = chunk.len() as f64;
+    let entropy: f64 = counts
+        .iter()
+        .filter(|c| **c > 0)
+        .map(|c| {
+            let p = *c as f64 / total;
+            -p * p.log2()
+        })
+        .sum();
+
+    if entropy >= HIGH_ENTROPY_THRESHOLD {
+        ChunkClass::HighEntropy
+    } else {
+        ChunkClass::Data
+    }
+}
+
+fn find_signatures(chunk: &[u8], position: u64, report: &mut ScanReport) {
+    for offset in (0..chunk.len()).step_by(SIGNATURE_ALIGNMENT) {
+        for (signature, name) in FILE_SIGNATURES {
+            if chunk[offset..].starts_with(signature) {
+                report.signatures_found += 1;
+                if report.signatures.len() < MAX_FINDINGS {
+                    report.signatures.push((position + offset as u64, name));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_support::{MemoryStorage, TempDir};
+    use rand::RngCore;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_scan_task_validation() {
+        assert!(ScanTask::new(65536, 65536, 100).is_ok());
+        assert!(ScanTask::new(65536, 1000, 100).is_err());
+        assert!(ScanTask::new(65536, 65536, 0).is_err());
+        assert!(ScanTask::new(65536, 65536, 101).is_err());
+    }
+
+    #[test]
+    fn test_chunk_classification() {
+        let mut random = vec![0u8; SCAN_CHUNK_SIZE];
+        rand::thread_rng().fill_bytes(&mut random);
+        let text = "The quick brown fox jumps over the lazy dog. "
+            .repeat(100)
+            .into_bytes();
+
+        assert_eq!(ChunkClass::Constant, classify(&[0u8; SCAN_CHUNK_SIZE]));
+        assert_eq!(ChunkClass::Constant, classify(&[0xffu8; SCAN_CHUNK_SIZE]));
+        assert_eq!(ChunkClass::HighEntropy, classify(&random));
+        assert_eq!(ChunkClass::Data, classify(&text[..SCAN_CHUNK_SIZE]));
+    }
+
+    #[test]
+    fn test_scanning_storage() {
+        let size = 64 * SCAN_CHUNK_SIZE;
+        let mut data = vec![0u8; size];
+        rand::thread_rng().fill_bytes(&mut data[..32 * SCAN_CHUNK_SIZE]);
+        data[40 * SCAN_CHUNK_SIZE..40 * SCAN_CHUNK_SIZE + 9].copy_from_slice(b"%PDF-1.4\n");
+        data[50 * SCAN_CHUNK_SIZE + 512..50 * SCAN_CHUNK_SIZE + 516].copy_from_slice(b"PK\x03\x04");
+
+        let task = ScanTask::new(size as u64, 16 * SCAN_CHUNK_SIZE, 100).unwrap();
+        let mut progress = 0;
+        let report = task
+            .run(&mut MemoryStorage(Cursor::new(data)), &mut |p| progress = p)
+            .unwrap();
+
+        assert_eq!(size as u64, progress);
+        assert_eq!(64, report.scanned);
+        assert_eq!(32, report.high_entropy);
+        assert_eq!(30, report.constant);
+        assert_eq!(2, report.data);
+        assert_eq!(
+            vec![40 * SCAN_CHUNK_SIZE as u64, 50 * SCAN_CHUNK_SIZE as u64],
+            report.data_positions
+        );
+        assert_eq!(
+            vec![
+                (40 * SCAN_CHUNK_SIZE as u64, "PDF"),
+                (50 * SCAN_CHUNK_SIZE as u64 + 512, "ZIP")
+            ],
+            report.signatures
+        );
+        assert!(!report.is_clean());
+    }
+
+    #[test]
+    fn test_sampled_scanning() {
+        let size = 100 * SCAN_CHUNK_SIZE;
+        let task = ScanTask::new(size as u64, SCAN_CHUNK_SIZE, 10).unwrap();
+        let report = task
+            .run(
+                &mut MemoryStorage(Cursor::new(vec![0u8; size])),
+                &mut |_| {},
+            )
+            .unwrap();
+
+        assert_eq!(10, report.scanned);
+        assert_eq!(10, report.constant);
+        assert!(report.is_clean());
+    }
+
+    #[test]
+    fn test_scanning_image_file() {
+        let dir = TempDir::new("scan");
+        // the incomplete last chunk can't be read with O_DIRECT
+        for size in &[8 * SCAN_CHUNK_SIZE, 8 * SCAN_CHUNK_SIZE + 100] {
+            let mut data = vec![0u8; *size];
+            data[3 * SCAN_CHUNK_SIZE..3 * SCAN_CHUNK_SIZE + 9].copy_from_slice(b"%PDF-1.4\n");
+            let storage = StorageRef::from_file(dir.file("image", &data)).unwrap();
+
+            let task = ScanTask::new(*size as u64, 2 * SCAN_CHUNK_SIZE, 100).unwrap();
+            let report = task.scan(&storage, &mut |_| {}).unwrap();
+
+            assert_eq!(vec![3 * SCAN_CHUNK_SIZE as u64], report.data_positions);
+            assert_eq!(vec![(3 * SCAN_CHUNK_SIZE as u64, "PDF")], report.signatures);
+        }
+    }
+}
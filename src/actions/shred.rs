@@ -0,0 +1,325 @@
+use crate::actions::{Verify, WipeEventReceiver, WipeState, WipeTask};
+use crate::sanitization::Scheme;
+use crate::storage::{FilesystemInfo, ImageFileAccess, System, IMAGE_SECTOR_SIZE};
+use anyhow::{Context, Result};
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ShredTargetKind {
+    File,
+    Directory,
+    Link,
+}
+
+#[derive(Debug, Clone)]
+pub struct ShredTarget {
+    pub path: PathBuf,
+    pub kind: ShredTargetKind,
+    pub size: u64,
+    /// Reason why overwriting the file in place may leave copies of the data.
+    pub warning: Option<&'static str>,
+}
+
+/// Destroys individual files: overwrites the content using the wipe engine,
+/// then renames the file multiple times to obscure its name, truncates and removes it.
+#[derive(Debug)]
+pub struct ShredTask {
+    pub scheme: Scheme,
+    pub verify: Verify,
+    pub block_size: usize,
+    pub renames: u32,
+}
+
+impl ShredTask {
+    pub fn new(scheme: Scheme, verify: Verify, block_size: usize, renames: u32) -> Result<Self> {
+        if block_size == 0 || !block_size.is_multiple_of(IMAGE_SECTOR_SIZE) {
+            Err(anyhow!(
+                "Block size should be a multiple of {} bytes.",
+                IMAGE_SECTOR_SIZE
+            ))?;
+        }
+        Ok(ShredTask {
+            scheme,
+            verify,
+            block_size,
+            renames,
+        })
+    }
+
+    /// Lists the files to destroy. The content of a directory goes before the directory itself,
+    /// symbolic links are removed without following.
+    pub fn collect_targets(paths: &[PathBuf], recursive: bool) -> Result<Vec<ShredTarget>> {
+        let mut targets = Vec::new();
+        for path in paths {
+            let warning = System::filesystem_of(path)
+                .ok()
+                .and_then(|fs| overwrite_warning(&fs));
+            collect(path, recursive, warning, &mut targets)?;
+        }
+        Ok(targets)
+    }
+
+    pub fn shred(&self, target: &ShredTarget, frontend: &mut dyn WipeEventReceiver) -> Result<()> {
+        match target.kind {
+            ShredTargetKind::File => {
+                if target.size > 0 {
+                    self.overwrite(&target.path, target.size, frontend)?;
+                }
+                let path = self.obscure_name(&target.path)?;
+                fs::OpenOptions::new()
+                    .write(true)
+                    .open(&path)
+                    .and_then(|f| f.set_len(0).and_then(|_| f.sync_all()))
+                    .context(format!("Unable to truncate {}", path.display()))?;
+                fs::remove_file(&path).context(format!("Unable to remove {}", path.display()))
+            }
+            ShredTargetKind::Directory => {
+                let path = self.obscure_name(&target.path)?;
+                fs::remove_dir(&path).context(format!("Unable to remove {}", path.display()))
+            }
+            ShredTargetKind::Link => fs::remove_file(&target.path)
+                .context(format!("Unable to remove {}", target.path.display())),
+        }
+    }
+
+    fn overwrite(
+        &self,
+        path: &Path,
+        size: u64,
+        frontend: &mut dyn WipeEventReceiver,
+    ) -> Result<()> {
+        let task = WipeTask::new(
+            self.scheme.clone(),
+            self.verify.clone(),
+            size,
+            self.block_size,
+            IMAGE_SECTOR_SIZE,
+        )?;
+        let mut access = ImageFileAccess::open(path)?;
+        let mut state = WipeState::default();
+
+        if !task.run(&mut access, &mut state, frontend) {
+            Err(anyhow!("Unable to overwrite {}", path.display()))?;
+        }
+
+        if state.bad_blocks.borrow().total_marked() > 0 {
+            Err(anyhow!(
+                "Unable to overwrite {}, some sectors were skipped",
+                path.display()
+            ))?;
+        }
+
+        Ok(())
+    }
+
+    /// Renames the file to random names of the same length, so the original name
+    /// doesn't stay in the directory entry.
+    fn obscure_name(&self, path: &Path) -> Result<PathBuf> {
+        let name_length = path.file_name().map(|n| n.len()).unwrap_or(1).max(1);
+        let mut current = path.to_path_buf();
+
+        for _ in 0..self.renames {
+            let name: String = rand::thread_rng()
+                .sample_iter(&Alphanumeric)
+                .take(name_length)
+                .collect();
+            let next = current.with_file_name(name);
+            if next.exists() {
+                continue;
+            }
+
+            fs::rename(&current, &next)
+                .context(format!("Unable to rename {}", current.display()))?;
+            sync_parent(&next)?;
+            current = next;
+        }
+
+        Ok(current)
+    }
+}
+
+/// Explains why overwriting files in place is unreliable on the filesystem, if it is.
+pub fn overwrite_warning(fs: &FilesystemInfo) -> Option<&'static str> {
+    match fs.fs_type.as_str() {
+        "btrfs" | "zfs" | "bcachefs" | "apfs" | "refs" => {
+            Some("Copy-on-write filesystem, overwriting doesn't replace the original data blocks.")
+        }
+        "f2fs" | "nilfs2" | "jffs2" | "ubifs" => {
+            Some("Log-structured filesystem, overwriting doesn't replace the original data blocks.")
+        }
+        "nfs" | "nfs4" | "cifs" | "smb3" | "fuse.sshfs" => {
+            Some("Network filesystem, the server may keep copies or snapshots of the data.")
+        }
+        "ext3" | "ext4" if fs.options.iter().any(|o| o == "data=journal") => {
+            Some("Data journaling is enabled, copies of the data may remain in the journal.")
+        }
+        _ => None,
+    }
+}
+
+fn collect(
+    path: &Path,
+    recursive: bool,
+    warning: Option<&'static str>,
+    targets: &mut Vec<ShredTarget>,
+) -> Result<()> {
+    let metadata =
+        fs::symlink_metadata(path).context(format!("Unable to access {}", path.display()))?;
+    let file_type = metadata.file_type();
+
+    let kind = if file_type.is_symlink() {
+        ShredTargetKind::Link
+    } else if file_type.is_file() {
+        ShredTargetKind::File
+    } else if file_type.is_dir() {
+        if !recursive {
+            Err(anyhow!(
+                "{} is a directory, use --recursive to shred it",
+                path.display()
+            ))?;
+        }
+
+        let mut entries = fs::read_dir(path)
+            .context(format!("Unable to read directory {}", path.display()))?
+            .map(|e| e.map(|e| e.path()))
+            .collect::<std::io::Result<Vec<_>>>()?;
+        entries.sort();
+
+        for entry in entries {
+            collect(&entry, recursive, warning, targets)?;
+        }
+
+        ShredTargetKind::Directory
+    } else {
+        Err(anyhow!(
+            "{} is not a regular file, use the wipe command for devices",
+            path.display()
+        ))?
+    };
+
+    targets.push(ShredTarget {
+        path: path.to_path_buf(),
+        size: if kind == ShredTargetKind::File {
+            metadata.len()
+        } else {
+            0
+        },
+        kind,
+        warning,
+    });
+
+    Ok(())
+}
+
+#[cfg(unix)]
+fn sync_parent(path: &Path) -> Result<()> {
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::File::open(parent)?.sync_all()?;
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn sync_parent(_path: &Path) -> Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::sanitization::SchemeRepo;
+    use crate::test_support::{StubReceiver, TempDir};
+
+    #[test]
+    fn test_shred_task_validation() {
+        let scheme = SchemeRepo::default().find("zero").unwrap().clone();
+
+        assert!(ShredTask::new(scheme.clone(), Verify::No, 4096, 3).is_ok());
+        assert!(ShredTask::new(scheme.clone(), Verify::No, 1000, 3).is_err());
+        assert!(ShredTask::new(scheme.clone(), Verify::No, 0, 3).is_err());
+    }
+
+    #[test]
+    fn test_collecting_targets() {
+        let dir = TempDir::new("collect");
+        let root = dir.path();
+        fs::create_dir_all(root.join("nested")).unwrap();
+        fs::write(root.join("a"), [1u8; 100]).unwrap();
+        fs::write(root.join("nested/b"), [1u8; 10]).unwrap();
+
+        assert!(ShredTask::collect_targets(&[root.to_path_buf()], false).is_err());
+
+        let targets = ShredTask::collect_targets(&[root.to_path_buf()], true).unwrap();
+        let paths: Vec<_> = targets.iter().map(|t| t.path.clone()).collect();
+        assert_eq!(
+            vec![
+                root.join("a"),
+                root.join("nested/b"),
+                root.join("nested"),
+                root.to_path_buf()
+            ],
+            paths
+        );
+        assert_eq!(ShredTargetKind::File, targets[0].kind);
+        assert_eq!(100, targets[0].size);
+        assert_eq!(ShredTargetKind::Directory, targets[2].kind);
+    }
+
+    #[test]
+    fn test_shredding_directory() {
+        let dir = TempDir::new("shred");
+        let root = dir.path();
+        fs::create_dir_all(root.join("nested")).unwrap();
+        fs::write(root.join("secret.key"), [1u8; 10000]).unwrap();
+        fs::write(root.join("nested/empty"), []).unwrap();
+
+        let scheme = SchemeRepo::default().find("random2x").unwrap().clone();
+        let task = ShredTask::new(scheme, Verify::Last, 4096, 3).unwrap();
+
+        let targets = ShredTask::collect_targets(&[root.to_path_buf()], true).unwrap();
+        for target in &targets {
+            task.shred(target, &mut StubReceiver::new()).unwrap();
+        }
+
+        assert!(!root.exists());
+    }
+
+    #[test]
+    fn test_overwriting_file() {
+        let dir = TempDir::new("overwrite");
+        let root = dir.path();
+        let path = root.join("data");
+        fs::write(&path, [1u8; 10000]).unwrap();
+
+        let scheme = SchemeRepo::default().find("zero").unwrap().clone();
+        let task = ShredTask::new(scheme, Verify::Last, 4096, 3).unwrap();
+        task.overwrite(&path, 10000, &mut StubReceiver::new())
+            .unwrap();
+
+        let content = fs::read(&path).unwrap();
+        assert_eq!(10000, content.len());
+        assert!(content.iter().all(|b| *b == 0));
+
+        let renamed = task.obscure_name(&path).unwrap();
+        assert!(!path.exists());
+        assert!(renamed.exists());
+        assert_eq!(4, renamed.file_name().unwrap().len());
+    }
+
+    #[test]
+    fn test_overwrite_warnings() {
+        let fs = |fs_type: &str, options: &[&str]| FilesystemInfo {
+            fs_type: fs_type.to_owned(),
+            options: options.iter().map(|o| o.to_string()).collect(),
+        };
+
+        assert!(overwrite_warning(&fs("ext4", &["rw", "relatime"])).is_none());
+        assert!(overwrite_warning(&fs("ext4", &["rw", "data=journal"])).is_some());
+        assert!(overwrite_warning(&fs("btrfs", &["rw"])).is_some());
+        assert!(overwrite_warning(&fs("zfs", &[])).is_some());
+        assert!(overwrite_warning(&fs("tmpfs", &[])).is_none());
+    }
+}
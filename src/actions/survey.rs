@@ -0,0 +1,168 @@
+use crate::actions::checkpoint::KeyValues;
+use crate::actions::{Verify, WipeEventReceiver, WipeState, WipeTask};
+use crate::sanitization::Scheme;
+use crate::storage::{ReadOnlyFileAccess, StorageAccess, StorageRef, StorageType};
+use anyhow::{Context, Result};
+use std::path::Path;
+
+/// Read-only pass over the whole storage, finding the unreadable and slow sectors
+/// without modifying anything.
+#[derive(Debug)]
+pub struct SurveyTask {
+    pub task: WipeTask,
+}
+
+impl SurveyTask {
+    pub fn new(total_size: u64, block_size: usize, sector_size: usize) -> Result<Self> {
+        let scheme = Scheme {
+            description: String::from("Read-only survey"),
+            stages: vec![],
+        };
+        Ok(SurveyTask {
+            task: WipeTask::new(scheme, Verify::No, total_size, block_size, sector_size)?,
+        })
+    }
+
+    /// Opens the storage read-only, bypassing the OS cache, and surveys it.
+    /// Image files are read through the cache, like they are wiped, as failed reads are
+    /// bisected down to sectors which O_DIRECT may reject as unaligned.
+    pub fn survey(
+        &self,
+        storage: &StorageRef,
+        state: &mut WipeState,
+        frontend: &mut dyn WipeEventReceiver,
+    ) -> Result<bool> {
+        let mut access = match storage.details.storage_type {
+            StorageType::File => ReadOnlyFileAccess::open(storage)?,
+            _ => ReadOnlyFileAccess::open_direct(storage)?,
+        };
+        Ok(self.run(&mut access, state, frontend))
+    }
+
+    pub fn run(
+        &self,
+        access: &mut dyn StorageAccess,
+        state: &mut WipeState,
+        frontend: &mut dyn WipeEventReceiver,
+    ) -> bool {
+        self.task.survey(access, state, frontend)
+    }
+}
+
+/// Sectors found unreadable on a device, stored as a plain text file,
+/// so a later wipe can skip them right away.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BadBlockMap {
+    pub device_id: String,
+    pub total_size: u64,
+    pub sector_size: usize,
+    pub bad_sectors: Vec<u64>,
+}
+
+impl BadBlockMap {
+    pub fn capture(device_id: &str, task: &WipeTask, state: &WipeState) -> Self {
+        BadBlockMap {
+            device_id: device_id.to_owned(),
+            total_size: task.total_size,
+            sector_size: task.sector_size,
+            bad_sectors: state.bad_blocks.borrow().iter().collect(),
+        }
+    }
+
+    /// Marks the sectors as bad in the state of a wipe of the same device.
+    pub fn apply(&self, task: &WipeTask, state: &WipeState) -> Result<()> {
+        if self.sector_size != task.sector_size {
+            return Err(anyhow!(
+                "The bad block map uses another sector size ({} bytes)",
+                self.sector_size
+            ));
+        }
+
+        for s in &self.bad_sectors {
+            state.bad_blocks.borrow_mut().mark(*s);
+        }
+
+        Ok(())
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        std::fs::write(path, self.to_string()).context("Unable to save the bad block map")
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let content = std::fs::read_to_string(path).context("Unable to read the bad block map")?;
+        Self::parse(&content).context("Invalid bad block map")
+    }
+
+    fn parse(content: &str) -> Result<Self> {
+        let values = KeyValues::parse(content);
+
+        Ok(BadBlockMap {
+            device_id: values.get("device")?.to_owned(),
+            total_size: values.get("size")?.parse()?,
+            sector_size: values.get("sector_size")?.parse()?,
+            bad_sectors: values.sectors("bad_sectors")?,
+        })
+    }
+}
+
+impl std::fmt::Display for BadBlockMap {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let bad_sectors: Vec<String> = self.bad_sectors.iter().map(|s| s.to_string()).collect();
+
+        writeln!(f, "device={}", self.device_id)?;
+        writeln!(f, "size={}", self.total_size)?;
+        writeln!(f, "sector_size={}", self.sector_size)?;
+        writeln!(f, "bad_sectors={}", bad_sectors.join(","))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use assert_matches::*;
+
+    #[test]
+    fn test_bad_block_map_roundtrip() {
+        let survey = SurveyTask::new(100000, 32768, 512).unwrap();
+        let state = WipeState::default();
+        state.bad_blocks.borrow_mut().mark(7);
+        state.bad_blocks.borrow_mut().mark(130);
+
+        let map = BadBlockMap::capture("/dev/sdx", &survey.task, &state);
+        let restored = BadBlockMap::parse(&map.to_string()).unwrap();
+        assert_eq!(map, restored);
+
+        let task = WipeTask::new(
+            Scheme {
+                description: String::from("Wipe"),
+                stages: vec![],
+            },
+            Verify::Last,
+            100000,
+            65536,
+            512,
+        )
+        .unwrap();
+        let wipe_state = WipeState::default();
+        restored.apply(&task, &wipe_state).unwrap();
+        assert_eq!(
+            vec![7, 130],
+            wipe_state.bad_blocks.borrow().iter().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_bad_block_map_invalid() {
+        assert_matches!(BadBlockMap::parse(""), Err(_));
+        assert_matches!(
+            BadBlockMap::parse("device=/dev/sdx\nsize=100\nsector_size=512\nbad_sectors=1,x"),
+            Err(_)
+        );
+
+        let map = BadBlockMap::parse("device=/dev/sdx\nsize=100\nsector_size=4096\nbad_sectors=")
+            .unwrap();
+        let survey = SurveyTask::new(100000, 32768, 512).unwrap();
+        assert_matches!(map.apply(&survey.task, &WipeState::default()), Err(_));
+    }
+}
@@ -0,0 +1,107 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Throughput limit in bytes per second which can be changed while the wipe is running.
+#[derive(Debug, Clone, Default)]
+pub struct RateLimit {
+    bytes_per_second: Arc<AtomicU64>,
+}
+
+impl RateLimit {
+    pub fn new(bytes_per_second: Option<u64>) -> Self {
+        let limit = RateLimit::default();
+        limit.set(bytes_per_second);
+        limit
+    }
+
+    pub fn get(&self) -> Option<u64> {
+        match self.bytes_per_second.load(Ordering::Relaxed) {
+            0 => None,
+            v => Some(v),
+        }
+    }
+
+    pub fn set(&self, bytes_per_second: Option<u64>) {
+        self.bytes_per_second
+            .store(bytes_per_second.unwrap_or(0), Ordering::Relaxed);
+    }
+}
+
+/// Token bucket allowing bursts of up to one second worth of data.
+pub(crate) struct TokenBucket {
+    available: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    pub(crate) fn new(now: Instant) -> Self {
+        TokenBucket {
+            available: 0.0,
+            updated: now,
+        }
+    }
+
+    /// Takes the amount of tokens and returns how long to wait until they are actually available.
+    pub(crate) fn take(&mut self, amount: u64, rate: u64, now: Instant) -> Duration {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.available = (self.available + elapsed * rate as f64).min(rate as f64);
+        self.available -= amount as f64;
+        self.updated = now;
+
+        if self.available >= 0.0 {
+            Duration::from_secs(0)
+        } else {
+            Duration::from_secs_f64(-self.available / rate as f64)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_rate_limit_updates() {
+        let limit = RateLimit::new(None);
+        assert_eq!(None, limit.get());
+
+        let shared = limit.clone();
+        shared.set(Some(1024));
+        assert_eq!(Some(1024), limit.get());
+
+        shared.set(None);
+        assert_eq!(None, limit.get());
+    }
+
+    #[test]
+    fn test_token_bucket_throttles() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(start);
+
+        assert_eq!(Duration::from_millis(500), bucket.take(500, 1000, start));
+        assert_eq!(
+            Duration::from_millis(500),
+            bucket.take(500, 1000, start + Duration::from_millis(500))
+        );
+        assert_eq!(
+            Duration::from_secs(0),
+            bucket.take(500, 1000, start + Duration::from_millis(1500))
+        );
+    }
+
+    #[test]
+    fn test_token_bucket_limits_bursts() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(start);
+
+        assert_eq!(
+            Duration::from_secs(0),
+            bucket.take(1000, 1000, start + Duration::from_secs(10))
+        );
+        assert_eq!(
+            Duration::from_secs(1),
+            bucket.take(1000, 1000, start + Duration::from_secs(10))
+        );
+    }
+}
@@ -0,0 +1,142 @@
+use std::time::{Duration, Instant};
+
+/// Throughput measured over equal ranges of the storage, showing how the speed changes
+/// along the LBA range, e.g. because of slow regions or the outer tracks being faster.
+#[derive(Debug, Clone)]
+pub struct ThroughputProfile {
+    total_size: u64,
+    bytes: Vec<u64>,
+    time: Vec<Duration>,
+    last: Option<(u64, Instant)>,
+}
+
+impl ThroughputProfile {
+    pub fn new(total_size: u64, buckets: usize) -> Self {
+        ThroughputProfile {
+            total_size,
+            bytes: vec![0; buckets.max(1)],
+            time: vec![Duration::from_secs(0); buckets.max(1)],
+            last: None,
+        }
+    }
+
+    fn bucket_size(&self) -> u64 {
+        self.total_size.div_ceil(self.bytes.len() as u64).max(1)
+    }
+
+    /// Records the position reached at the given moment. The time since the previous record
+    /// is split between the buckets covered in between, proportionally to the bytes.
+    /// Going backwards (e.g. a new stage) only starts a new measurement.
+    pub fn record(&mut self, position: u64, at: Instant) {
+        let (start, started) = match self.last.replace((position, at)) {
+            Some((start, started)) if start < position => (start, started),
+            _ => return,
+        };
+
+        let elapsed = at.saturating_duration_since(started);
+        let total = position - start;
+        let bucket_size = self.bucket_size();
+        let mut from = start;
+
+        while from < position {
+            let bucket = ((from / bucket_size) as usize).min(self.bytes.len() - 1);
+            let to = position.min((bucket as u64 + 1) * bucket_size);
+            let bytes = to.max(from + 1) - from;
+            self.bytes[bucket] += bytes;
+            self.time[bucket] += elapsed.mul_f64(bytes as f64 / total as f64);
+            from += bytes;
+        }
+    }
+
+    /// Forgets the last recorded position, so the pause before the next record isn't counted.
+    pub fn interrupt(&mut self) {
+        self.last = None;
+    }
+
+    /// Start and end of each bucket with the average bytes per second,
+    /// `None` for the buckets without measurements.
+    pub fn buckets(&self) -> Vec<(u64, u64, Option<f64>)> {
+        let bucket_size = self.bucket_size();
+        self.bytes
+            .iter()
+            .zip(&self.time)
+            .enumerate()
+            .map(|(i, (bytes, time))| {
+                let start = (i as u64 * bucket_size).min(self.total_size);
+                let end = ((i as u64 + 1) * bucket_size).min(self.total_size);
+                let throughput = match time.as_secs_f64() {
+                    t if *bytes > 0 && t > 0.0 => Some(*bytes as f64 / t),
+                    _ => None,
+                };
+                (start, end, throughput)
+            })
+            .collect()
+    }
+
+    /// The slowest and the fastest measured buckets.
+    pub fn range(&self) -> Option<(f64, f64)> {
+        self.buckets()
+            .iter()
+            .filter_map(|(_, _, t)| *t)
+            .fold(None, |acc, t| match acc {
+                None => Some((t, t)),
+                Some((min, max)) => Some((t.min(min), t.max(max))),
+            })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_empty_profile() {
+        let profile = ThroughputProfile::new(1000, 4);
+
+        assert_eq!(
+            vec![
+                (0, 250, None),
+                (250, 500, None),
+                (500, 750, None),
+                (750, 1000, None)
+            ],
+            profile.buckets()
+        );
+        assert_eq!(None, profile.range());
+    }
+
+    #[test]
+    fn test_splitting_samples_between_buckets() {
+        let mut profile = ThroughputProfile::new(1000, 4);
+        let start = Instant::now();
+
+        profile.record(0, start);
+        profile.record(500, start + Duration::from_secs(1));
+        profile.record(750, start + Duration::from_secs(3));
+
+        let throughput: Vec<_> = profile.buckets().iter().map(|b| b.2).collect();
+        assert_eq!(
+            vec![Some(500.0), Some(500.0), Some(125.0), None],
+            throughput
+        );
+        assert_eq!(Some((125.0, 500.0)), profile.range());
+    }
+
+    #[test]
+    fn test_ignoring_time_between_measurements() {
+        let mut profile = ThroughputProfile::new(1000, 2);
+        let start = Instant::now();
+
+        profile.record(0, start);
+        profile.record(500, start + Duration::from_secs(1));
+        // next pass starts over
+        profile.record(0, start + Duration::from_secs(10));
+        profile.record(500, start + Duration::from_secs(11));
+        profile.interrupt();
+        profile.record(600, start + Duration::from_secs(20));
+        profile.record(1000, start + Duration::from_secs(21));
+
+        let throughput: Vec<_> = profile.buckets().iter().map(|b| b.2).collect();
+        assert_eq!(vec![Some(500.0), Some(400.0)], throughput);
+    }
+}
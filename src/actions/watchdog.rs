@@ -0,0 +1,103 @@
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Source of time for measuring the I/O latency, replaceable in tests.
+pub trait Clock: Debug + Send + Sync {
+    fn now(&self) -> Instant;
+}
+
+#[derive(Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// Tracks the I/O operation in flight, so it can be checked from another thread while
+/// the wiping thread is stuck in a call which doesn't return, e.g. while the kernel
+/// retries a failing sector. A stalled operation stops the wipe as soon as it returns.
+#[derive(Debug, Clone, Default)]
+pub struct IoWatchdog {
+    state: Arc<Mutex<WatchdogState>>,
+}
+
+#[derive(Debug, Default)]
+struct WatchdogState {
+    in_flight: Option<(u64, Instant)>,
+    stalled: Option<u64>,
+}
+
+impl IoWatchdog {
+    pub fn begin(&self, position: u64, at: Instant) {
+        self.state.lock().unwrap().in_flight = Some((position, at));
+    }
+
+    pub fn end(&self) {
+        self.state.lock().unwrap().in_flight = None;
+    }
+
+    /// Position and duration of the operation in flight. The operation is recorded
+    /// as stalled once it runs longer than the timeout.
+    pub fn check(&self, timeout: Duration, now: Instant) -> Option<(u64, Duration)> {
+        let mut state = self.state.lock().unwrap();
+        let (position, started) = state.in_flight?;
+        let elapsed = now.saturating_duration_since(started);
+        if elapsed > timeout {
+            state.stalled.get_or_insert(position);
+        }
+        Some((position, elapsed))
+    }
+
+    /// Position of the first operation which exceeded the timeout.
+    pub fn stalled_at(&self) -> Option<u64> {
+        self.state.lock().unwrap().stalled
+    }
+
+    /// Forgets the stalled operation, so the wipe can be resumed.
+    pub fn reset(&self) {
+        self.state.lock().unwrap().stalled = None;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_watchdog_detects_stalled_operation() {
+        let watchdog = IoWatchdog::default();
+        let start = Instant::now();
+        let timeout = Duration::from_secs(30);
+
+        assert_eq!(None, watchdog.check(timeout, start));
+
+        watchdog.begin(4096, start);
+        assert_eq!(
+            Some((4096, Duration::from_secs(10))),
+            watchdog.check(timeout, start + Duration::from_secs(10))
+        );
+        assert_eq!(None, watchdog.stalled_at());
+
+        assert_eq!(
+            Some((4096, Duration::from_secs(31))),
+            watchdog.check(timeout, start + Duration::from_secs(31))
+        );
+        assert_eq!(Some(4096), watchdog.stalled_at());
+
+        // the first stalled operation is kept
+        watchdog.begin(8192, start);
+        watchdog.check(timeout, start + Duration::from_secs(40));
+        watchdog.end();
+        assert_eq!(
+            None,
+            watchdog.check(timeout, start + Duration::from_secs(50))
+        );
+        assert_eq!(Some(4096), watchdog.stalled_at());
+
+        watchdog.reset();
+        assert_eq!(None, watchdog.stalled_at());
+    }
+}
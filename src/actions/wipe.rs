@@ -1,13 +1,22 @@
+use crate::actions::control::{CancellationToken, WipeControl};
+use crate::actions::latency::LatencyHistogram;
 use crate::actions::marker::{BlockMarker, RoaringBlockMarker};
+use crate::actions::throttle::{RateLimit, TokenBucket};
+use crate::actions::watchdog::{Clock, IoWatchdog, SystemClock};
 use crate::sanitization::mem::*;
 use crate::sanitization::*;
 use crate::storage::{StorageAccess, StorageError};
-use anyhow::Result;
+use anyhow::{Context, Result};
 use std::cell::RefCell;
 use std::fmt::{Display, Formatter};
 use std::rc::Rc;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use thiserror::Error;
 
-#[derive(Debug)]
+pub(crate) const CONTROL_POLL_MILLIS: u64 = 100;
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum Verify {
     No,
     Last,
@@ -24,12 +33,80 @@ impl Display for Verify {
     }
 }
 
+#[derive(Debug, Clone)]
+pub enum BadBlockLimit {
+    Total(u64),
+    Percent(f64),
+    Consecutive(u64),
+}
+
+impl Display for BadBlockLimit {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BadBlockLimit::Total(n) => write!(f, "more than {} bad sectors", n),
+            BadBlockLimit::Percent(p) => write!(f, "more than {}% of sectors are bad", p),
+            BadBlockLimit::Consecutive(n) => write!(f, "more than {} consecutive bad sectors", n),
+        }
+    }
+}
+
+/// Limits on the amount of bad sectors after which the device is considered not worth wiping.
+#[derive(Debug, Clone, Default)]
+pub struct BadBlockPolicy {
+    pub max_bad_blocks: Option<u64>,
+    pub max_bad_percent: Option<f64>,
+    pub max_consecutive_bad: Option<u64>,
+}
+
+impl BadBlockPolicy {
+    pub fn check(&self, total_sectors: u64, bad: u64, consecutive: u64) -> Option<BadBlockLimit> {
+        match self {
+            BadBlockPolicy {
+                max_bad_blocks: Some(n),
+                ..
+            } if bad > *n => Some(BadBlockLimit::Total(*n)),
+            BadBlockPolicy {
+                max_bad_percent: Some(p),
+                ..
+            } if bad as f64 * 100.0 > total_sectors as f64 * p => Some(BadBlockLimit::Percent(*p)),
+            BadBlockPolicy {
+                max_consecutive_bad: Some(n),
+                ..
+            } if consecutive > *n => Some(BadBlockLimit::Consecutive(*n)),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum WipeError {
+    #[error("wipe aborted, {0}")]
+    BadBlockLimitExceeded(BadBlockLimit),
+    #[error("wipe cancelled")]
+    Cancelled,
+    #[error("verification failed")]
+    VerificationFailed,
+    #[error("I/O at {0} didn't complete within the timeout")]
+    IoTimeout(u64),
+}
+
 #[derive(Debug)]
 pub struct WipeTask {
     pub scheme: Scheme,
     pub verify: Verify,
     pub total_size: u64,
     pub block_size: usize,
+    pub sector_size: usize,
+    pub bad_block_policy: BadBlockPolicy,
+    /// Operations running longer are considered stalled, which stops the wipe
+    /// once they return. Enforced by checking the `watchdog` from another thread,
+    /// which can't interrupt the operation itself.
+    pub io_timeout: Option<Duration>,
+    pub slow_io_threshold: Option<Duration>,
+    pub rate_limit: RateLimit,
+    pub control: WipeControl,
+    pub watchdog: IoWatchdog,
+    pub clock: Arc<dyn Clock>,
 }
 
 #[derive(Debug, Clone)]
@@ -39,6 +116,8 @@ pub struct WipeState {
     pub position: u64,
     pub retries_left: u32,
     pub bad_blocks: Rc<RefCell<dyn BlockMarker>>,
+    pub slow_blocks: Rc<RefCell<dyn BlockMarker>>,
+    pub latency: LatencyHistogram,
 }
 
 pub struct WipeRun<'a> {
@@ -46,6 +125,12 @@ pub struct WipeRun<'a> {
     pub task: &'a WipeTask,
     pub state: &'a mut WipeState,
     pub frontend: &'a mut dyn WipeEventReceiver,
+    pub cancellation: &'a dyn CancellationToken,
+    /// Size of the storage, less than the task's one when the storage gets full early.
+    total_size: u64,
+    io_position: Option<u64>,
+    consecutive_bad: u64,
+    throttle: TokenBucket,
 }
 
 impl Default for WipeState {
@@ -56,15 +141,24 @@ impl Default for WipeState {
             position: 0,
             retries_left: 0,
             bad_blocks: Rc::new(RefCell::new(RoaringBlockMarker::new())),
+            slow_blocks: Rc::new(RefCell::new(RoaringBlockMarker::new())),
+            latency: LatencyHistogram::default(),
         }
     }
 }
 
 impl WipeTask {
-    pub fn new(scheme: Scheme, verify: Verify, total_size: u64, block_size: usize) -> Result<Self> {
-        if total_size / block_size as u64 > 1 << 32 {
+    pub fn new(
+        scheme: Scheme,
+        verify: Verify,
+        total_size: u64,
+        block_size: usize,
+        sector_size: usize,
+    ) -> Result<Self> {
+        if sector_size == 0 || !block_size.is_multiple_of(sector_size) {
             Err(anyhow!(
-                "Number of blocks in this device is more than 2^32. Try using a bigger block size."
+                "Block size should be a multiple of the device sector size ({} bytes).",
+                sector_size
             ))?;
         }
         Ok(WipeTask {
@@ -72,8 +166,36 @@ impl WipeTask {
             verify,
             total_size,
             block_size,
+            sector_size,
+            bad_block_policy: BadBlockPolicy::default(),
+            io_timeout: None,
+            slow_io_threshold: None,
+            rate_limit: RateLimit::default(),
+            control: WipeControl::default(),
+            watchdog: IoWatchdog::default(),
+            clock: Arc::new(SystemClock),
         })
     }
+
+    fn total_sectors(&self) -> u64 {
+        let sector_size = self.sector_size as u64;
+        self.total_size.div_ceil(sector_size)
+    }
+
+    /// Number of bytes left unwiped because of the sectors marked as bad.
+    pub fn skipped_bytes(&self, state: &WipeState) -> u64 {
+        let sector_size = self.sector_size as u64;
+        let bad_blocks = state.bad_blocks.borrow();
+        let mut skipped = bad_blocks.total_marked() * sector_size;
+
+        // the last sector can be incomplete
+        let last_sector = self.total_sectors().max(1) - 1;
+        if bad_blocks.is_marked(last_sector) {
+            skipped -= (last_sector + 1) * sector_size - self.total_size;
+        }
+
+        skipped
+    }
 }
 
 #[derive(Debug)]
@@ -83,12 +205,19 @@ pub enum WipeEvent {
     StageStarted,
     Progress(u64),
     MarkedBlockAsBad(u64),
+    BadBlockLimitExceeded(BadBlockLimit),
+    SlowBlock(u64, Duration),
+    Paused(u64),
+    Resumed,
     StageCompleted(Option<Rc<anyhow::Error>>),
     Retrying,
     Completed(Option<Rc<anyhow::Error>>),
     Fatal(anyhow::Error),
 }
 
+/// Frontend of a wipe, receives every event along with the current task and state.
+/// The events are published synchronously from the wiping thread, so a slow receiver
+/// slows down the wipe.
 pub trait WipeEventReceiver {
     fn handle(&mut self, task: &WipeTask, state: &WipeState, event: WipeEvent) -> ();
 }
@@ -99,15 +228,54 @@ impl WipeTask {
         access: &mut dyn StorageAccess,
         state: &mut WipeState,
         frontend: &mut dyn WipeEventReceiver,
+    ) -> bool {
+        self.run_cancellable(access, state, frontend, &self.control)
+    }
+
+    /// Same as `run`, but also stops the wipe when the given token gets cancelled,
+    /// in addition to the task's own `control`.
+    pub fn run_cancellable(
+        &self,
+        access: &mut dyn StorageAccess,
+        state: &mut WipeState,
+        frontend: &mut dyn WipeEventReceiver,
+        cancellation: &dyn CancellationToken,
     ) -> bool {
         WipeRun {
             access,
             task: &self,
             state,
             frontend,
+            cancellation,
+            total_size: self.total_size,
+            io_position: None,
+            consecutive_bad: 0,
+            throttle: TokenBucket::new(Instant::now()),
         }
         .run()
     }
+
+    /// Reads the whole storage without writing anything, marking unreadable sectors as bad
+    /// and slow ones as slow, the same way a wipe does. The scheme is not used.
+    pub fn survey(
+        &self,
+        access: &mut dyn StorageAccess,
+        state: &mut WipeState,
+        frontend: &mut dyn WipeEventReceiver,
+    ) -> bool {
+        WipeRun {
+            access,
+            task: self,
+            state,
+            frontend,
+            cancellation: &self.control,
+            total_size: self.total_size,
+            io_position: None,
+            consecutive_bad: 0,
+            throttle: TokenBucket::new(Instant::now()),
+        }
+        .survey()
+    }
 }
 
 impl WipeRun<'_> {
@@ -116,93 +284,224 @@ impl WipeRun<'_> {
     }
 
     fn build_stream(&self, stage: &Stage) -> SanitizationStream {
-        stage.stream(
-            self.task.total_size,
-            self.task.block_size,
-            self.state.position,
-        )
+        stage.stream(self.total_size, self.task.block_size, self.state.position)
     }
 
     fn advance(&mut self, bytes: usize) {
         self.state.position += bytes as u64;
-        if self.state.position > self.task.total_size {
-            self.state.position = self.task.total_size
+        if self.state.position > self.total_size {
+            self.state.position = self.total_size
         }
         self.publish(WipeEvent::Progress(self.state.position));
     }
 
+    fn throttle(&mut self, bytes: usize) {
+        if let Some(rate) = self.task.rate_limit.get() {
+            let delay = self.throttle.take(bytes as u64, rate, Instant::now());
+            if delay > Duration::from_secs(0) {
+                std::thread::sleep(delay);
+            }
+        }
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.task.control.is_cancelled() || self.cancellation.is_cancelled()
+    }
+
+    /// Handles pause and cancellation requests, always leaving the storage in a consistent state.
+    fn check_control(&mut self) -> Result<()> {
+        if self.task.control.is_paused() && !self.is_cancelled() {
+            self.access.flush()?;
+            self.publish(WipeEvent::Paused(self.state.position));
+
+            while self.task.control.is_paused() && !self.is_cancelled() {
+                std::thread::sleep(Duration::from_millis(CONTROL_POLL_MILLIS));
+            }
+
+            self.publish(WipeEvent::Resumed);
+        }
+
+        // not flushing, the storage has just failed to respond in time
+        if let Some(position) = self.task.watchdog.stalled_at() {
+            Err(WipeError::IoTimeout(position))?;
+        }
+
+        if self.is_cancelled() {
+            self.access.flush()?;
+            Err(WipeError::Cancelled)?;
+        }
+
+        Ok(())
+    }
+
     fn at_the_end(&self) -> bool {
-        self.state.position >= self.task.total_size
+        self.state.position >= self.total_size
     }
 
-    fn current_block_number(&self) -> u32 {
-        (self.state.position / self.task.block_size as u64) as u32
+    fn sectors_of(&self, position: u64, length: usize) -> std::ops::RangeInclusive<u64> {
+        let sector_size = self.task.sector_size as u64;
+        (position / sector_size)..=((position + length as u64 - 1) / sector_size)
     }
 
-    fn is_at_bad_block(&self) -> bool {
-        self.state
-            .bad_blocks
-            .borrow()
-            .is_marked(self.current_block_number())
+    fn has_bad_sectors(&self, position: u64, length: usize) -> bool {
+        let bad_blocks = self.state.bad_blocks.borrow();
+        bad_blocks.total_marked() > 0
+            && self
+                .sectors_of(position, length)
+                .any(|s| bad_blocks.is_marked(s))
     }
 
-    fn mark_bad_block(&mut self) -> () {
-        self.state
-            .bad_blocks
-            .borrow_mut()
-            .mark(self.current_block_number());
-        self.publish(WipeEvent::MarkedBlockAsBad(self.state.position));
+    fn mark_bad_sectors(&mut self, position: u64, length: usize) -> Result<()> {
+        if self.has_bad_sectors(position, length) {
+            return Ok(());
+        }
+
+        for s in self.sectors_of(position, length) {
+            self.state.bad_blocks.borrow_mut().mark(s);
+            self.consecutive_bad += 1;
+        }
+        self.publish(WipeEvent::MarkedBlockAsBad(position));
+
+        let total_bad = self.state.bad_blocks.borrow().total_marked();
+        let exceeded = self.task.bad_block_policy.check(
+            self.task.total_sectors(),
+            total_bad,
+            self.consecutive_bad,
+        );
+
+        if let Some(limit) = exceeded {
+            self.publish(WipeEvent::BadBlockLimitExceeded(limit.clone()));
+            Err(WipeError::BadBlockLimitExceeded(limit))?;
+        }
+
+        Ok(())
     }
 
-    fn try_seek(&mut self) -> Result<bool> {
-        if self.is_at_bad_block() {
-            return Ok(false);
+    /// Splits the range into extents not containing any bad sectors.
+    fn good_extents(&self, position: u64, length: usize) -> Vec<(usize, usize)> {
+        if !self.has_bad_sectors(position, length) {
+            return vec![(0, length)];
         }
 
-        if let Err(err) = self.access.seek(self.state.position) {
-            return match underlying_storage_error(&err) {
-                Some(StorageError::BadBlock) => {
-                    self.mark_bad_block();
-                    Ok(false)
-                }
-                _ => Err(err),
-            };
+        let sector_size = self.task.sector_size;
+        let bad_blocks = self.state.bad_blocks.borrow();
+        let mut extents: Vec<(usize, usize)> = Vec::new();
+
+        for (i, s) in self.sectors_of(position, length).enumerate() {
+            if bad_blocks.is_marked(s) {
+                continue;
+            }
+
+            let offset = i * sector_size;
+            let size = std::cmp::min(sector_size, length - offset);
+            match extents.last_mut() {
+                Some((start, len)) if *start + *len == offset => *len += size,
+                _ => extents.push((offset, size)),
+            }
         }
 
-        Ok(true)
+        extents
     }
 
-    fn try_write(&mut self, chunk: &[u8]) -> Result<bool> {
-        if self.is_at_bad_block() {
-            return Ok(false);
+    fn seek_to(&mut self, position: u64) -> Result<()> {
+        if self.io_position != Some(position) {
+            self.io_position = None;
+            self.access.seek(position)?;
+            self.io_position = Some(position);
         }
+        Ok(())
+    }
 
-        if let Err(err) = self.access.write(chunk) {
-            return match underlying_storage_error(&err) {
-                Some(StorageError::BadBlock) => {
-                    self.mark_bad_block();
-                    Ok(false)
-                }
-                _ => Err(err),
-            };
+    /// Records the duration of a completed I/O operation. Completed operations are never
+    /// failed, even if they took longer than the timeout, they are reported as slow instead.
+    fn track_latency(&mut self, position: u64, length: usize, latency: Duration) {
+        self.state.latency.record(latency);
+
+        let is_slow = |threshold: Option<Duration>| matches!(threshold, Some(t) if latency > t);
+        if is_slow(self.task.slow_io_threshold) || is_slow(self.task.io_timeout) {
+            for s in self.sectors_of(position, length) {
+                self.state.slow_blocks.borrow_mut().mark(s);
+            }
+            self.publish(WipeEvent::SlowBlock(position, latency));
         }
-        Ok(true)
     }
 
-    fn seek_to_the_next_safe_position(&mut self) -> Result<()> {
-        loop {
-            if self.at_the_end() {
-                break;
+    fn write_at(&mut self, position: u64, data: &[u8]) -> Result<()> {
+        self.seek_to(position)?;
+        self.io_position = None;
+        let started = self.task.clock.now();
+        self.task.watchdog.begin(position, started);
+        let result = self.access.write(data);
+        self.task.watchdog.end();
+        result.map_err(|e| timed_out(e, position))?;
+        let latency = self.task.clock.now().saturating_duration_since(started);
+        self.track_latency(position, data.len(), latency);
+        self.io_position = Some(position + data.len() as u64);
+        Ok(())
+    }
+
+    fn read_at(&mut self, position: u64, buffer: &mut [u8]) -> Result<()> {
+        self.seek_to(position)?;
+        self.io_position = None;
+        let started = self.task.clock.now();
+        self.task.watchdog.begin(position, started);
+        let result = self.access.read(buffer);
+        self.task.watchdog.end();
+        result.map_err(|e| timed_out(e, position))?;
+        let latency = self.task.clock.now().saturating_duration_since(started);
+        self.track_latency(position, buffer.len(), latency);
+        self.io_position = Some(position + buffer.len() as u64);
+        Ok(())
+    }
+
+    /// Writes the data skipping known bad sectors. When a write fails because of a bad block,
+    /// the range is bisected down to a single sector, so only the unaccessible sectors are
+    /// left unwiped.
+    fn write_sectors(&mut self, position: u64, data: &[u8]) -> Result<()> {
+        if !self.has_bad_sectors(position, data.len()) {
+            match self.write_at(position, data) {
+                Ok(()) => {
+                    self.consecutive_bad = 0;
+                    return Ok(());
+                }
+                Err(err) if !is_bad_block(&err) => return Err(err),
+                Err(_) => (),
             }
+        }
 
-            if self.is_at_bad_block() || !self.try_seek()? {
-                self.advance(self.task.block_size);
-                continue;
+        let sectors = (data.len() - 1) / self.task.sector_size + 1;
+        if sectors <= 1 {
+            return self.mark_bad_sectors(position, data.len());
+        }
+
+        let half = sectors / 2 * self.task.sector_size;
+        self.write_sectors(position, &data[..half])?;
+        self.write_sectors(position + half as u64, &data[half..])
+    }
+
+    /// Same as `write_sectors`, but reading into the buffer.
+    /// The content of the bad sectors is left as is.
+    fn read_sectors(&mut self, position: u64, buffer: &mut [u8]) -> Result<()> {
+        if !self.has_bad_sectors(position, buffer.len()) {
+            match self.read_at(position, buffer) {
+                Ok(()) => {
+                    self.consecutive_bad = 0;
+                    return Ok(());
+                }
+                Err(err) if !is_bad_block(&err) => return Err(err),
+                Err(_) => (),
             }
+        }
 
-            break;
+        let sectors = (buffer.len() - 1) / self.task.sector_size + 1;
+        if sectors <= 1 {
+            return self.mark_bad_sectors(position, buffer.len());
         }
-        Ok(())
+
+        let half = sectors / 2 * self.task.sector_size;
+        let (head, tail) = buffer.split_at_mut(half);
+        self.read_sectors(position, head)?;
+        self.read_sectors(position + half as u64, tail)
     }
 
     fn run(&mut self) -> bool {
@@ -212,7 +511,10 @@ impl WipeRun<'_> {
 
         let mut wipe_error = None;
 
-        for (i, stage) in stages.iter().enumerate() {
+        // a state left after a cancelled run allows to resume from the same stage and position
+        let resume_stage = self.state.stage;
+
+        for (i, stage) in stages.iter().enumerate().skip(resume_stage) {
             let have_to_verify = match self.task.verify {
                 Verify::No => false,
                 Verify::Last if i + 1 == stages.len() => true,
@@ -221,40 +523,44 @@ impl WipeRun<'_> {
             };
 
             self.state.stage = i;
-            self.state.position = 0;
-            self.state.at_verification = false;
+            if i > resume_stage {
+                self.state.position = 0;
+                self.state.at_verification = false;
+            }
 
             let stage_error = loop {
                 let watermark = self.state.position;
 
-                self.publish(WipeEvent::StageStarted);
-                if let Err(err) = self.fill(stage) {
-                    let err_rc = Rc::from(err);
-                    self.publish(WipeEvent::StageCompleted(Some(Rc::clone(&err_rc))));
+                if !self.state.at_verification {
+                    self.publish(WipeEvent::StageStarted);
+                    if let Err(err) = self.fill(stage) {
+                        let err_rc = Rc::from(err);
+                        self.publish(WipeEvent::StageCompleted(Some(Rc::clone(&err_rc))));
 
-                    if self.state.retries_left > 0 {
-                        self.state.retries_left -= 1;
-                        self.publish(WipeEvent::Retrying);
-                        continue;
+                        if self.state.retries_left > 0 && !is_aborted(&err_rc) {
+                            self.state.retries_left -= 1;
+                            self.publish(WipeEvent::Retrying);
+                            continue;
+                        }
+
+                        break Some(err_rc);
                     }
+                    self.publish(WipeEvent::StageCompleted(None));
 
-                    break Some(err_rc);
-                }
-                self.publish(WipeEvent::StageCompleted(None));
+                    if !have_to_verify {
+                        break None;
+                    }
 
-                if !have_to_verify {
-                    break None;
+                    self.state.position = watermark;
+                    self.state.at_verification = true;
                 }
 
-                self.state.position = watermark;
-                self.state.at_verification = true;
-
                 self.publish(WipeEvent::StageStarted);
                 if let Err(err) = self.verify(stage) {
                     let err_rc = Rc::from(err);
                     self.publish(WipeEvent::StageCompleted(Some(Rc::clone(&err_rc))));
 
-                    if self.state.retries_left > 0 {
+                    if self.state.retries_left > 0 && !is_aborted(&err_rc) {
                         self.state.retries_left -= 1;
                         self.state.at_verification = false;
                         self.publish(WipeEvent::Retrying);
@@ -281,22 +587,29 @@ impl WipeRun<'_> {
     fn fill(&mut self, stage: &Stage) -> Result<()> {
         self.publish(WipeEvent::Progress(self.state.position));
 
-        self.seek_to_the_next_safe_position()?;
+        self.io_position = None;
 
         if self.at_the_end() {
             return Ok(());
         }
 
         let mut stream = self.build_stream(stage);
-        let mut skip_next = false;
 
         while let Some(chunk) = stream.next() {
-            if skip_next || !self.try_write(chunk)? {
-                self.advance(chunk.len());
-                skip_next = !self.try_seek()?;
-                continue;
+            self.check_control()?;
+            self.throttle(chunk.len());
+            let position = self.state.position;
+            match self.write_sectors(position, chunk) {
+                // files filling up a filesystem take less than its estimated free space
+                Err(err) if is_storage_full(&err) => {
+                    if position == 0 {
+                        Err(err).context("No free space left")?;
+                    }
+                    self.total_size = position;
+                    break;
+                }
+                result => result?,
             }
-
             self.advance(chunk.len());
         }
 
@@ -308,7 +621,7 @@ impl WipeRun<'_> {
     fn verify(&mut self, stage: &Stage) -> Result<()> {
         self.publish(WipeEvent::Progress(self.state.position));
 
-        self.seek_to_the_next_safe_position()?;
+        self.io_position = None;
 
         if self.at_the_end() {
             return Ok(());
@@ -319,18 +632,18 @@ impl WipeRun<'_> {
         let buf = AlignedBuffer::new(self.task.block_size, self.task.block_size);
 
         while let Some(chunk) = stream.next() {
-            if self.is_at_bad_block() {
-                self.advance(chunk.len());
-                self.try_seek()?;
-                continue;
-            }
+            self.check_control()?;
+            self.throttle(chunk.len());
+            let position = self.state.position;
 
-            let b = &mut buf.as_mut_slice()[..chunk.len()];
+            for (offset, length) in self.good_extents(position, chunk.len()) {
+                let b = &mut buf.as_mut_slice()[offset..offset + length];
 
-            self.access.read(b)?;
+                self.read_at(position + offset as u64, b)?;
 
-            if b != chunk {
-                Err(anyhow!("Verification failed!"))?;
+                if b != &chunk[offset..offset + length] {
+                    Err(WipeError::VerificationFailed)?;
+                }
             }
 
             self.advance(chunk.len());
@@ -338,6 +651,73 @@ impl WipeRun<'_> {
 
         Ok(())
     }
+
+    fn survey(&mut self) -> bool {
+        self.publish(WipeEvent::Started);
+
+        self.state.at_verification = true;
+        self.publish(WipeEvent::StageStarted);
+        let error = self.read_all().err().map(Rc::from);
+        self.publish(WipeEvent::StageCompleted(error.clone()));
+
+        let result = error.is_none();
+        self.publish(WipeEvent::Completed(error));
+
+        result
+    }
+
+    fn read_all(&mut self) -> Result<()> {
+        self.publish(WipeEvent::Progress(self.state.position));
+
+        self.io_position = None;
+
+        let buf = AlignedBuffer::new(self.task.block_size, self.task.block_size);
+
+        while !self.at_the_end() {
+            self.check_control()?;
+            let position = self.state.position;
+            let length = (self.total_size - position).min(self.task.block_size as u64) as usize;
+            self.throttle(length);
+            self.read_sectors(position, &mut buf.as_mut_slice()[..length])?;
+            self.advance(length);
+        }
+
+        Ok(())
+    }
+}
+
+/// Failed verification is retried like any other error, the rest of `WipeError` stops the wipe.
+fn is_aborted(error: &anyhow::Error) -> bool {
+    error.chain().any(|c| {
+        matches!(
+            c.downcast_ref::<WipeError>(),
+            Some(WipeError::BadBlockLimitExceeded(_))
+                | Some(WipeError::Cancelled)
+                | Some(WipeError::IoTimeout(_))
+        )
+    })
+}
+
+fn is_storage_full(error: &anyhow::Error) -> bool {
+    error.chain().any(|c| {
+        matches!(c.downcast_ref::<std::io::Error>(), Some(e) if e.kind() == std::io::ErrorKind::StorageFull)
+    })
+}
+
+fn is_bad_block(error: &anyhow::Error) -> bool {
+    matches!(
+        underlying_storage_error(error),
+        Some(StorageError::BadBlock)
+    )
+}
+
+/// A timeout reported by the storage stops the wipe like a stalled operation, it may be
+/// transient, so the sectors are not marked as bad.
+fn timed_out(error: anyhow::Error, position: u64) -> anyhow::Error {
+    match underlying_storage_error(&error) {
+        Some(StorageError::Timeout) => WipeError::IoTimeout(position).into(),
+        _ => error,
+    }
 }
 
 // taken directly from https://docs.rs/anyhow/1.0.9/anyhow/struct.Error.html#example
@@ -353,9 +733,13 @@ pub fn underlying_storage_error(error: &anyhow::Error) -> Option<&StorageError>
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::test_support::StubReceiver;
     use anyhow::{Context, Result};
     use assert_matches::*;
+    use std::cell::Cell;
     use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
     use WipeEvent::*;
 
     #[test]
@@ -363,10 +747,33 @@ mod test {
         let schemes = SchemeRepo::default();
         let scheme = schemes.find("zero").unwrap();
 
-        assert!(WipeTask::new(scheme.clone(), Verify::No, 1 << 32, 1).is_ok());
-        assert!(WipeTask::new(scheme.clone(), Verify::No, 1 << 35, 8).is_ok());
-        assert!(WipeTask::new(scheme.clone(), Verify::No, 1 << 33, 1).is_err());
-        assert!(WipeTask::new(scheme.clone(), Verify::No, 1 << 36, 8).is_err());
+        assert!(WipeTask::new(scheme.clone(), Verify::No, 1 << 32, 1, 1).is_ok());
+        assert!(WipeTask::new(scheme.clone(), Verify::No, 1 << 35, 8, 8).is_ok());
+        assert!(WipeTask::new(scheme.clone(), Verify::No, 1 << 33, 1, 1).is_ok());
+        assert!(WipeTask::new(scheme.clone(), Verify::No, 1 << 36, 8, 8).is_ok());
+        // a petabyte volume with 4 KiB blocks
+        assert!(WipeTask::new(scheme.clone(), Verify::No, 1 << 50, 4096, 512).is_ok());
+        assert!(WipeTask::new(scheme.clone(), Verify::No, 1 << 20, 4096, 512).is_ok());
+        assert!(WipeTask::new(scheme.clone(), Verify::No, 1 << 33, 4096, 1).is_ok());
+        assert!(WipeTask::new(scheme.clone(), Verify::No, 1 << 20, 4096, 3072).is_err());
+        assert!(WipeTask::new(scheme.clone(), Verify::No, 1 << 20, 4096, 0).is_err());
+    }
+
+    #[test]
+    fn test_skipped_bytes_beyond_32_bit_sectors() {
+        let schemes = SchemeRepo::default();
+        let scheme = schemes.find("zero").unwrap();
+        let task = WipeTask::new(scheme.clone(), Verify::No, (1 << 45) + 100, 4096, 512).unwrap();
+        let state = WipeState::default();
+
+        state.bad_blocks.borrow_mut().mark((1 << 32) - 1);
+        state.bad_blocks.borrow_mut().mark(1 << 32);
+        state.bad_blocks.borrow_mut().mark((1 << 36) + 3);
+        assert_eq!(3 * 512, task.skipped_bytes(&state));
+
+        // the last sector is incomplete
+        state.bad_blocks.borrow_mut().mark(1 << 36);
+        assert_eq!(3 * 512 + 100, task.skipped_bytes(&state));
     }
 
     #[test]
@@ -382,6 +789,7 @@ mod test {
             Verify::Last,
             storage.size as u64,
             block_size,
+            block_size,
         )
         .unwrap();
         let mut state = WipeState::default();
@@ -413,6 +821,61 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_wiping_until_storage_is_full() {
+        let schemes = SchemeRepo::default();
+        let scheme = schemes.find("random2x").unwrap();
+        let mut storage = InMemoryStorage::new(100000);
+        storage.fill_up_at(80000);
+        let block_size = 32768;
+        let mut receiver = StubReceiver::new();
+
+        let task = WipeTask::new(
+            scheme.clone(),
+            Verify::Last,
+            storage.size as u64,
+            block_size,
+            4096,
+        )
+        .unwrap();
+        let mut state = WipeState::default();
+        let result = task.run(&mut storage, &mut state, &mut receiver);
+
+        assert!(result);
+        assert_eq!(65536, state.position);
+
+        let mut e = receiver.collected.iter();
+        assert_matches!(e.next(), Some((_, Started)));
+        for verification in &[false, false, true] {
+            assert_matches!(e.next(), Some((ref s, StageStarted))
+                if s.at_verification == *verification);
+            assert_matches!(e.next(), Some((_, Progress(0))));
+            assert_matches!(e.next(), Some((_, Progress(32768))));
+            assert_matches!(e.next(), Some((_, Progress(65536))));
+            assert_matches!(e.next(), Some((_, StageCompleted(None))));
+        }
+        assert_matches!(e.next(), Some((_, Completed(None))));
+        assert_matches!(e.next(), None);
+    }
+
+    #[test]
+    fn test_wiping_full_storage() {
+        let schemes = SchemeRepo::default();
+        let scheme = schemes.find("zero").unwrap();
+        let mut storage = InMemoryStorage::new(100000);
+        storage.fill_up_at(0);
+        let mut receiver = StubReceiver::new();
+
+        let task = WipeTask::new(scheme.clone(), Verify::No, 100000, 32768, 4096).unwrap();
+        let mut state = WipeState::default();
+
+        assert!(!task.run(&mut storage, &mut state, &mut receiver));
+        assert_matches!(
+            receiver.collected.last(),
+            Some((_, Completed(Some(err)))) if err.to_string() == "No free space left"
+        );
+    }
+
     #[test]
     fn test_wiping_fill_failure() {
         let schemes = SchemeRepo::default();
@@ -428,6 +891,7 @@ mod test {
             Verify::Last,
             storage.size as u64,
             block_size,
+            block_size,
         )
         .unwrap();
         let mut state = WipeState::default();
@@ -464,6 +928,7 @@ mod test {
             Verify::Last,
             storage.size as u64,
             block_size,
+            block_size,
         )
         .unwrap();
         let mut state = WipeState::default();
@@ -516,6 +981,7 @@ mod test {
             Verify::Last,
             storage.size as u64,
             block_size,
+            block_size,
         )
         .unwrap();
         let mut state = WipeState::default();
@@ -560,6 +1026,7 @@ mod test {
             Verify::Last,
             storage.size as u64,
             block_size,
+            block_size,
         )
         .unwrap();
         let mut state = WipeState::default();
@@ -604,6 +1071,7 @@ mod test {
             Verify::Last,
             storage.size as u64,
             block_size,
+            block_size,
         )
         .unwrap();
         let mut state = WipeState::default();
@@ -650,6 +1118,7 @@ mod test {
             Verify::Last,
             storage.size as u64,
             block_size,
+            block_size,
         )
         .unwrap();
         let mut state = WipeState::default();
@@ -682,33 +1151,34 @@ mod test {
     }
 
     #[test]
-    fn test_wiping_validation_failure_without_retries() {
+    fn test_wiping_bisects_bad_block_down_to_sector() {
         let schemes = SchemeRepo::default();
-        let scheme = schemes.find("random").unwrap();
+        let scheme = schemes.find("zero").unwrap();
         let mut storage = InMemoryStorage::new(100000);
         let block_size = 32768;
         let mut receiver = StubReceiver::new();
 
-        storage.fail_after_any(150000);
+        storage.fail_at(50000);
 
         let task = WipeTask::new(
             scheme.clone(),
             Verify::Last,
             storage.size as u64,
             block_size,
+            512,
         )
         .unwrap();
         let mut state = WipeState::default();
-        state.retries_left = 0;
         let result = task.run(&mut storage, &mut state, &mut receiver);
 
-        assert!(!result);
+        assert!(result);
 
         let mut e = receiver.collected.iter();
         assert_matches!(e.next(), Some((_, Started)));
         assert_matches!(e.next(), Some((ref s, StageStarted)) if !s.at_verification);
         assert_matches!(e.next(), Some((_, Progress(0))));
         assert_matches!(e.next(), Some((_, Progress(32768))));
+        assert_matches!(e.next(), Some((_, MarkedBlockAsBad(49664))));
         assert_matches!(e.next(), Some((_, Progress(65536))));
         assert_matches!(e.next(), Some((_, Progress(98304))));
         assert_matches!(e.next(), Some((_, Progress(100000))));
@@ -716,36 +1186,650 @@ mod test {
         assert_matches!(e.next(), Some((ref s, StageStarted)) if s.at_verification);
         assert_matches!(e.next(), Some((_, Progress(0))));
         assert_matches!(e.next(), Some((_, Progress(32768))));
-        assert_matches!(e.next(), Some((_, StageCompleted(Some(_)))));
-        assert_matches!(e.next(), Some((_, Completed(Some(_)))));
-    }
+        assert_matches!(e.next(), Some((_, Progress(65536))));
+        assert_matches!(e.next(), Some((_, Progress(98304))));
+        assert_matches!(e.next(), Some((_, Progress(100000))));
+        assert_matches!(e.next(), Some((_, StageCompleted(None))));
+        assert_matches!(e.next(), Some((_, Completed(None))));
 
-    struct StubReceiver {
-        collected: Vec<(WipeState, WipeEvent)>,
+        assert_eq!(state.bad_blocks.borrow().total_marked(), 1);
+        assert_eq!(task.skipped_bytes(&state), 512);
+        assert_eq!(
+            storage.file.get_ref().iter().filter(|x| **x != 0u8).count(),
+            512
+        );
     }
 
-    impl StubReceiver {
-        pub fn new() -> Self {
-            StubReceiver {
-                collected: Vec::new(),
-            }
-        }
-    }
+    #[test]
+    fn test_wiping_skipped_bytes_of_incomplete_sector() {
+        let schemes = SchemeRepo::default();
+        let scheme = schemes.find("zero").unwrap();
+        let mut storage = InMemoryStorage::new(100000);
+        let block_size = 32768;
+        let mut receiver = StubReceiver::new();
 
-    impl WipeEventReceiver for StubReceiver {
-        fn handle(&mut self, _task: &WipeTask, state: &WipeState, event: WipeEvent) -> () {
-            println!("{:?}", event);
-            self.collected.push((state.clone(), event));
-        }
+        storage.fail_at(1000);
+        storage.fail_at(99999);
+
+        let task = WipeTask::new(
+            scheme.clone(),
+            Verify::All,
+            storage.size as u64,
+            block_size,
+            512,
+        )
+        .unwrap();
+        let mut state = WipeState::default();
+        let result = task.run(&mut storage, &mut state, &mut receiver);
+
+        assert!(result);
+        assert_eq!(state.bad_blocks.borrow().total_marked(), 2);
+        assert_eq!(task.skipped_bytes(&state), 512 + 160);
+        assert_eq!(
+            storage.file.get_ref().iter().filter(|x| **x != 0u8).count(),
+            512 + 160
+        );
     }
 
-    struct InMemoryStorage {
-        file: Cursor<Vec<u8>>,
-        size: usize,
-        total_written: usize,
-        total_read: usize,
-        failures: Vec<usize>,
-        bad_blocks: Vec<u64>,
+    #[test]
+    fn test_bad_block_policy_limits() {
+        let policy = BadBlockPolicy::default();
+        assert_matches!(policy.check(100, 100, 100), None);
+
+        let policy = BadBlockPolicy {
+            max_bad_blocks: Some(10),
+            max_bad_percent: Some(5.0),
+            max_consecutive_bad: Some(3),
+        };
+        assert_matches!(policy.check(1000, 10, 3), None);
+        assert_matches!(policy.check(1000, 11, 3), Some(BadBlockLimit::Total(10)));
+        assert_matches!(policy.check(100, 6, 3), Some(BadBlockLimit::Percent(_)));
+        assert_matches!(
+            policy.check(1000, 4, 4),
+            Some(BadBlockLimit::Consecutive(3))
+        );
+    }
+
+    #[test]
+    fn test_wiping_aborts_on_consecutive_bad_blocks() {
+        let schemes = SchemeRepo::default();
+        let scheme = schemes.find("random").unwrap();
+        let mut storage = InMemoryStorage::new(100000);
+        let block_size = 32768;
+        let mut receiver = StubReceiver::new();
+
+        storage.fail_at(0);
+        storage.fail_at(32768);
+        storage.fail_at(65536);
+        storage.fail_at(98304);
+
+        let mut task = WipeTask::new(
+            scheme.clone(),
+            Verify::Last,
+            storage.size as u64,
+            block_size,
+            block_size,
+        )
+        .unwrap();
+        task.bad_block_policy.max_consecutive_bad = Some(2);
+        let mut state = WipeState {
+            retries_left: 8,
+            ..Default::default()
+        };
+        let result = task.run(&mut storage, &mut state, &mut receiver);
+
+        assert!(!result);
+
+        let mut e = receiver.collected.iter();
+        assert_matches!(e.next(), Some((_, Started)));
+        assert_matches!(e.next(), Some((ref s, StageStarted)) if !s.at_verification);
+        assert_matches!(e.next(), Some((_, Progress(0))));
+        assert_matches!(e.next(), Some((_, MarkedBlockAsBad(0))));
+        assert_matches!(e.next(), Some((_, Progress(32768))));
+        assert_matches!(e.next(), Some((_, MarkedBlockAsBad(32768))));
+        assert_matches!(e.next(), Some((_, Progress(65536))));
+        assert_matches!(e.next(), Some((_, MarkedBlockAsBad(65536))));
+        assert_matches!(
+            e.next(),
+            Some((_, BadBlockLimitExceeded(BadBlockLimit::Consecutive(2))))
+        );
+        assert_matches!(e.next(), Some((_, StageCompleted(Some(_)))));
+        assert_matches!(e.next(), Some((_, Completed(Some(err)))) if is_aborted(err));
+        assert_matches!(e.next(), None);
+    }
+
+    #[test]
+    fn test_wiping_resets_consecutive_bad_blocks_on_success() {
+        let schemes = SchemeRepo::default();
+        let scheme = schemes.find("zero").unwrap();
+        let mut storage = InMemoryStorage::new(100000);
+        let block_size = 32768;
+        let mut receiver = StubReceiver::new();
+
+        storage.fail_at(0);
+        storage.fail_at(65536);
+
+        let mut task = WipeTask::new(
+            scheme.clone(),
+            Verify::Last,
+            storage.size as u64,
+            block_size,
+            block_size,
+        )
+        .unwrap();
+        task.bad_block_policy.max_consecutive_bad = Some(1);
+        let mut state = WipeState::default();
+        let result = task.run(&mut storage, &mut state, &mut receiver);
+
+        assert!(result);
+        assert_eq!(state.bad_blocks.borrow().total_marked(), 2);
+
+        task.bad_block_policy.max_consecutive_bad = None;
+        task.bad_block_policy.max_bad_percent = Some(25.0);
+        let mut state = WipeState::default();
+        let result = task.run(&mut storage, &mut state, &mut receiver);
+
+        assert!(!result);
+    }
+
+    #[test]
+    fn test_wiping_reports_slow_blocks() {
+        let schemes = SchemeRepo::default();
+        let scheme = schemes.find("zero").unwrap();
+        let mut storage = InMemoryStorage::new(100000);
+        let block_size = 32768;
+        let mut receiver = StubReceiver::new();
+
+        storage.delay_at(50000, Duration::from_millis(20));
+
+        let mut task = WipeTask::new(
+            scheme.clone(),
+            Verify::Last,
+            storage.size as u64,
+            block_size,
+            block_size,
+        )
+        .unwrap();
+        task.slow_io_threshold = Some(Duration::from_millis(10));
+        task.clock = storage.clock.clone();
+        let mut state = WipeState::default();
+        let result = task.run(&mut storage, &mut state, &mut receiver);
+
+        assert!(result);
+
+        let mut e = receiver.collected.iter();
+        assert_matches!(e.next(), Some((_, Started)));
+        assert_matches!(e.next(), Some((ref s, StageStarted)) if !s.at_verification);
+        assert_matches!(e.next(), Some((_, Progress(0))));
+        assert_matches!(e.next(), Some((_, Progress(32768))));
+        assert_matches!(e.next(), Some((_, SlowBlock(32768, _))));
+        assert_matches!(e.next(), Some((_, Progress(65536))));
+
+        assert_eq!(state.slow_blocks.borrow().total_marked(), 1);
+        assert_eq!(state.bad_blocks.borrow().total_marked(), 0);
+        assert_eq!(state.latency.total(), 8);
+        assert_eq!(Duration::from_millis(20), state.latency.max());
+    }
+
+    #[test]
+    fn test_wiping_keeps_late_io() {
+        let schemes = SchemeRepo::default();
+        let scheme = schemes.find("zero").unwrap();
+        let mut storage = InMemoryStorage::new(100000);
+        let block_size = 32768;
+        let mut receiver = StubReceiver::new();
+
+        storage.delay_at(50000, Duration::from_millis(50));
+
+        let mut task = WipeTask::new(
+            scheme.clone(),
+            Verify::Last,
+            storage.size as u64,
+            block_size,
+            block_size,
+        )
+        .unwrap();
+        task.io_timeout = Some(Duration::from_millis(20));
+        task.clock = storage.clock.clone();
+        let mut state = WipeState::default();
+        let result = task.run(&mut storage, &mut state, &mut receiver);
+
+        assert!(result);
+
+        let mut e = receiver.collected.iter();
+        assert_matches!(e.next(), Some((_, Started)));
+        assert_matches!(e.next(), Some((ref s, StageStarted)) if !s.at_verification);
+        assert_matches!(e.next(), Some((_, Progress(0))));
+        assert_matches!(e.next(), Some((_, Progress(32768))));
+        assert_matches!(e.next(), Some((_, SlowBlock(32768, _))));
+        assert_matches!(e.next(), Some((_, Progress(65536))));
+
+        assert_eq!(state.bad_blocks.borrow().total_marked(), 0);
+        assert_eq!(state.slow_blocks.borrow().total_marked(), 1);
+    }
+
+    #[test]
+    fn test_wiping_stops_after_stalled_io() {
+        let schemes = SchemeRepo::default();
+        let scheme = schemes.find("zero").unwrap();
+        let mut storage = InMemoryStorage::new(100000);
+        let block_size = 32768;
+        let mut receiver = StubReceiver::new();
+
+        let mut task = WipeTask::new(
+            scheme.clone(),
+            Verify::Last,
+            storage.size as u64,
+            block_size,
+            block_size,
+        )
+        .unwrap();
+        task.io_timeout = Some(Duration::from_millis(20));
+        task.clock = storage.clock.clone();
+        storage.delay_at(50000, Duration::from_millis(50));
+        storage.watch(&task.watchdog, Duration::from_millis(20));
+
+        let mut state = WipeState::default();
+        state.retries_left = 8;
+        let result = task.run(&mut storage, &mut state, &mut receiver);
+
+        assert!(!result);
+        assert!(!receiver
+            .collected
+            .iter()
+            .any(|(_, e)| matches!(e, Retrying)));
+        assert_matches!(
+            receiver.collected.last(),
+            Some((_, Completed(Some(e))))
+                if matches!(e.downcast_ref::<WipeError>(), Some(WipeError::IoTimeout(32768)))
+        );
+
+        // the stalled write completed, the wipe can be resumed after it
+        assert_eq!(65536, state.position);
+        assert_eq!(state.bad_blocks.borrow().total_marked(), 0);
+    }
+
+    #[test]
+    fn test_wiping_stops_on_storage_timeout() {
+        let schemes = SchemeRepo::default();
+        let scheme = schemes.find("zero").unwrap();
+        let mut storage = InMemoryStorage::new(100000);
+        let block_size = 32768;
+        let mut receiver = StubReceiver::new();
+
+        let task = WipeTask::new(
+            scheme.clone(),
+            Verify::Last,
+            storage.size as u64,
+            block_size,
+            512,
+        )
+        .unwrap();
+        storage.time_out_at(50000);
+
+        let mut state = WipeState::default();
+        state.retries_left = 8;
+        let result = task.run(&mut storage, &mut state, &mut receiver);
+
+        assert!(!result);
+        assert!(!receiver
+            .collected
+            .iter()
+            .any(|(_, e)| matches!(e, Retrying | MarkedBlockAsBad(_))));
+        assert_matches!(
+            receiver.collected.last(),
+            Some((_, Completed(Some(e))))
+                if matches!(e.downcast_ref::<WipeError>(), Some(WipeError::IoTimeout(32768)))
+        );
+        assert_eq!(32768, state.position);
+        assert_eq!(state.bad_blocks.borrow().total_marked(), 0);
+    }
+
+    #[test]
+    fn test_wiping_respects_rate_limit() {
+        let schemes = SchemeRepo::default();
+        let scheme = schemes.find("zero").unwrap();
+        let mut storage = InMemoryStorage::new(100000);
+        let block_size = 32768;
+        let mut receiver = StubReceiver::new();
+
+        let task = WipeTask::new(
+            scheme.clone(),
+            Verify::No,
+            storage.size as u64,
+            block_size,
+            block_size,
+        )
+        .unwrap();
+        task.rate_limit.set(Some(1_000_000));
+        let mut state = WipeState::default();
+
+        let started = Instant::now();
+        let result = task.run(&mut storage, &mut state, &mut receiver);
+
+        assert!(result);
+        assert!(started.elapsed() >= Duration::from_millis(98));
+    }
+
+    #[test]
+    fn test_wiping_pause_and_resume() {
+        let schemes = SchemeRepo::default();
+        let scheme = schemes.find("zero").unwrap();
+        let mut storage = InMemoryStorage::new(100000);
+        let block_size = 32768;
+
+        let task = WipeTask::new(
+            scheme.clone(),
+            Verify::No,
+            storage.size as u64,
+            block_size,
+            block_size,
+        )
+        .unwrap();
+
+        let control = task.control.clone();
+        let mut receiver = StubReceiver::on_progress(32768, move || {
+            control.pause();
+            let remote = control.clone();
+            std::thread::spawn(move || {
+                std::thread::sleep(Duration::from_millis(50));
+                remote.resume();
+            });
+        });
+        let mut state = WipeState::default();
+        let result = task.run(&mut storage, &mut state, &mut receiver);
+
+        assert!(result);
+
+        let mut e = receiver.collected.iter();
+        assert_matches!(e.next(), Some((_, Started)));
+        assert_matches!(e.next(), Some((_, StageStarted)));
+        assert_matches!(e.next(), Some((_, Progress(0))));
+        assert_matches!(e.next(), Some((_, Progress(32768))));
+        assert_matches!(e.next(), Some((_, Paused(32768))));
+        assert_matches!(e.next(), Some((_, Resumed)));
+        assert_matches!(e.next(), Some((_, Progress(65536))));
+        assert_matches!(e.next(), Some((_, Progress(98304))));
+        assert_matches!(e.next(), Some((_, Progress(100000))));
+        assert_matches!(e.next(), Some((_, StageCompleted(None))));
+        assert_matches!(e.next(), Some((_, Completed(None))));
+    }
+
+    #[test]
+    fn test_wiping_cancel_and_resume() {
+        let schemes = SchemeRepo::default();
+        let scheme = schemes.find("random2x").unwrap();
+        let mut storage = InMemoryStorage::new(100000);
+        let block_size = 32768;
+
+        let mut task = WipeTask::new(
+            scheme.clone(),
+            Verify::Last,
+            storage.size as u64,
+            block_size,
+            512,
+        )
+        .unwrap();
+
+        let control = task.control.clone();
+        let mut receiver = StubReceiver::on_progress(65536, move || control.cancel());
+        let mut state = WipeState {
+            retries_left: 8,
+            ..Default::default()
+        };
+        let result = task.run(&mut storage, &mut state, &mut receiver);
+
+        assert!(!result);
+
+        let mut e = receiver.collected.iter();
+        assert_matches!(e.next(), Some((_, Started)));
+        assert_matches!(e.next(), Some((_, StageStarted)));
+        assert_matches!(e.next(), Some((_, Progress(0))));
+        assert_matches!(e.next(), Some((_, Progress(32768))));
+        assert_matches!(e.next(), Some((_, Progress(65536))));
+        assert_matches!(e.next(), Some((_, StageCompleted(Some(_)))));
+        assert_matches!(e.next(), Some((_, Completed(Some(err)))) if is_aborted(err));
+        assert_matches!(e.next(), None);
+
+        assert_eq!(0, state.stage);
+        assert_eq!(65536, state.position);
+        assert!(!state.at_verification);
+
+        task.control = WipeControl::default();
+        let mut receiver = StubReceiver::new();
+        let result = task.run(&mut storage, &mut state, &mut receiver);
+
+        assert!(result);
+
+        let mut e = receiver.collected.iter();
+        assert_matches!(e.next(), Some((_, Started)));
+        assert_matches!(e.next(), Some((ref s, StageStarted)) if s.stage == 0);
+        assert_matches!(e.next(), Some((_, Progress(65536))));
+
+        let mut reference = InMemoryStorage::new(100000);
+        let mut state = WipeState::default();
+        assert!(task.run(&mut reference, &mut state, &mut StubReceiver::new()));
+
+        assert_eq!(reference.file.get_ref(), storage.file.get_ref());
+    }
+
+    #[test]
+    fn test_wiping_cancelled_by_token_during_verification() {
+        let schemes = SchemeRepo::default();
+        let scheme = schemes.find("random2x").unwrap();
+        let mut storage = InMemoryStorage::new(100000);
+        let block_size = 32768;
+
+        let task = WipeTask::new(
+            scheme.clone(),
+            Verify::Last,
+            storage.size as u64,
+            block_size,
+            block_size,
+        )
+        .unwrap();
+
+        // the third time the position is reached is the verification of the last stage
+        let token = Arc::new(AtomicBool::new(false));
+        let remote = Arc::clone(&token);
+        let reached = Cell::new(0);
+        let mut receiver = StubReceiver::on_progress(32768, move || {
+            reached.set(reached.get() + 1);
+            if reached.get() == 3 {
+                let remote = Arc::clone(&remote);
+                std::thread::spawn(move || remote.store(true, Ordering::SeqCst))
+                    .join()
+                    .unwrap();
+            }
+        });
+        let mut state = WipeState::default();
+        let result = task.run_cancellable(&mut storage, &mut state, &mut receiver, &token);
+
+        assert!(!result);
+        assert_matches!(receiver.collected.last(), Some((_, Completed(Some(err)))) if is_aborted(err));
+        assert_eq!(1, state.stage);
+        assert_eq!(32768, state.position);
+        assert!(state.at_verification);
+
+        let mut receiver = StubReceiver::new();
+        let result = task.run(&mut storage, &mut state, &mut receiver);
+
+        assert!(result);
+
+        let mut e = receiver.collected.iter();
+        assert_matches!(e.next(), Some((_, Started)));
+        assert_matches!(e.next(), Some((ref s, StageStarted)) if s.at_verification);
+        assert_matches!(e.next(), Some((_, Progress(32768))));
+        assert_matches!(e.next(), Some((_, Progress(65536))));
+        assert_matches!(e.next(), Some((_, Progress(98304))));
+        assert_matches!(e.next(), Some((_, Progress(100000))));
+        assert_matches!(e.next(), Some((_, StageCompleted(None))));
+        assert_matches!(e.next(), Some((_, Completed(None))));
+        assert_matches!(e.next(), None);
+    }
+
+    #[test]
+    fn test_wiping_validation_failure_without_retries() {
+        let schemes = SchemeRepo::default();
+        let scheme = schemes.find("random").unwrap();
+        let mut storage = InMemoryStorage::new(100000);
+        let block_size = 32768;
+        let mut receiver = StubReceiver::new();
+
+        storage.fail_after_any(150000);
+
+        let task = WipeTask::new(
+            scheme.clone(),
+            Verify::Last,
+            storage.size as u64,
+            block_size,
+            block_size,
+        )
+        .unwrap();
+        let mut state = WipeState::default();
+        state.retries_left = 0;
+        let result = task.run(&mut storage, &mut state, &mut receiver);
+
+        assert!(!result);
+
+        let mut e = receiver.collected.iter();
+        assert_matches!(e.next(), Some((_, Started)));
+        assert_matches!(e.next(), Some((ref s, StageStarted)) if !s.at_verification);
+        assert_matches!(e.next(), Some((_, Progress(0))));
+        assert_matches!(e.next(), Some((_, Progress(32768))));
+        assert_matches!(e.next(), Some((_, Progress(65536))));
+        assert_matches!(e.next(), Some((_, Progress(98304))));
+        assert_matches!(e.next(), Some((_, Progress(100000))));
+        assert_matches!(e.next(), Some((_, StageCompleted(None))));
+        assert_matches!(e.next(), Some((ref s, StageStarted)) if s.at_verification);
+        assert_matches!(e.next(), Some((_, Progress(0))));
+        assert_matches!(e.next(), Some((_, Progress(32768))));
+        assert_matches!(e.next(), Some((_, StageCompleted(Some(_)))));
+        assert_matches!(e.next(), Some((_, Completed(Some(_)))));
+    }
+
+    #[test]
+    fn test_wiping_verification_mismatch_is_retried() {
+        let schemes = SchemeRepo::default();
+        let scheme = schemes.find("zero").unwrap();
+        let mut storage = InMemoryStorage::new(100000);
+        let block_size = 32768;
+        let mut receiver = StubReceiver::new();
+
+        storage.lose_writes();
+
+        let task = WipeTask::new(
+            scheme.clone(),
+            Verify::Last,
+            storage.size as u64,
+            block_size,
+            block_size,
+        )
+        .unwrap();
+        let mut state = WipeState::default();
+        state.retries_left = 1;
+        let result = task.run(&mut storage, &mut state, &mut receiver);
+
+        assert!(!result);
+        assert!(receiver
+            .collected
+            .iter()
+            .any(|(_, e)| matches!(e, Retrying)));
+        assert_matches!(
+            receiver.collected.last(),
+            Some((_, Completed(Some(e))))
+                if matches!(e.downcast_ref::<WipeError>(), Some(WipeError::VerificationFailed))
+                    && !is_aborted(e)
+        );
+    }
+
+    #[test]
+    fn test_survey_marks_bad_sectors_without_writing() {
+        let mut storage = InMemoryStorage::new(100000);
+        let block_size = 32768;
+        let mut receiver = StubReceiver::new();
+
+        storage.fail_at(50000);
+        storage.fail_at(99999);
+
+        let scheme = Scheme {
+            description: String::from("Survey"),
+            stages: vec![],
+        };
+        let task = WipeTask::new(scheme, Verify::No, storage.size as u64, block_size, 512).unwrap();
+        let mut state = WipeState::default();
+        let result = task.survey(&mut storage, &mut state, &mut receiver);
+
+        assert!(result);
+
+        let mut e = receiver.collected.iter();
+        assert_matches!(e.next(), Some((_, Started)));
+        assert_matches!(e.next(), Some((ref s, StageStarted)) if s.at_verification);
+        assert_matches!(e.next(), Some((_, Progress(0))));
+        assert_matches!(e.next(), Some((_, Progress(32768))));
+        assert_matches!(e.next(), Some((_, MarkedBlockAsBad(49664))));
+        assert_matches!(e.next(), Some((_, Progress(65536))));
+        assert_matches!(e.next(), Some((_, Progress(98304))));
+        assert_matches!(e.next(), Some((_, MarkedBlockAsBad(99840))));
+        assert_matches!(e.next(), Some((_, Progress(100000))));
+        assert_matches!(e.next(), Some((_, StageCompleted(None))));
+        assert_matches!(e.next(), Some((_, Completed(None))));
+        assert_matches!(e.next(), None);
+
+        assert_eq!(
+            vec![97, 195],
+            state.bad_blocks.borrow().iter().collect::<Vec<_>>()
+        );
+        assert_eq!(0, storage.total_written);
+        assert!(storage.file.get_ref().iter().all(|x| *x == 0xff));
+    }
+
+    #[test]
+    fn test_survey_aborts_on_bad_block_limit() {
+        let mut storage = InMemoryStorage::new(100000);
+        let mut receiver = StubReceiver::new();
+
+        storage.fail_at(1000);
+        storage.fail_at(2000);
+
+        let scheme = Scheme {
+            description: String::from("Survey"),
+            stages: vec![],
+        };
+        let mut task = WipeTask::new(scheme, Verify::No, storage.size as u64, 32768, 512).unwrap();
+        task.bad_block_policy.max_bad_blocks = Some(1);
+        let mut state = WipeState::default();
+
+        assert!(!task.survey(&mut storage, &mut state, &mut receiver));
+        assert_matches!(
+            receiver.collected.last(),
+            Some((_, Completed(Some(e)))) if is_aborted(e)
+        );
+    }
+
+    struct InMemoryStorage {
+        file: Cursor<Vec<u8>>,
+        size: usize,
+        total_written: usize,
+        total_read: usize,
+        failures: Vec<usize>,
+        bad_blocks: Vec<u64>,
+        timeouts: Vec<u64>,
+        slow_blocks: Vec<(u64, Duration)>,
+        lost_writes: bool,
+        full_at: Option<u64>,
+        clock: Arc<ManualClock>,
+        watchdog: Option<(IoWatchdog, Duration)>,
+    }
+
+    /// Time passing only when the storage is slow.
+    #[derive(Debug)]
+    struct ManualClock(std::sync::Mutex<Instant>);
+
+    impl Clock for ManualClock {
+        fn now(&self) -> Instant {
+            *self.0.lock().unwrap()
+        }
     }
 
     impl InMemoryStorage {
@@ -757,6 +1841,12 @@ mod test {
                 total_read: 0,
                 failures: Vec::new(),
                 bad_blocks: Vec::new(),
+                timeouts: Vec::new(),
+                slow_blocks: Vec::new(),
+                lost_writes: false,
+                full_at: None,
+                clock: Arc::new(ManualClock(std::sync::Mutex::new(Instant::now()))),
+                watchdog: None,
             }
         }
 
@@ -770,9 +1860,41 @@ mod test {
             self.bad_blocks.sort();
         }
 
+        fn time_out_at(&mut self, pos: u64) -> () {
+            self.timeouts.push(pos);
+        }
+
+        fn delay_at(&mut self, pos: u64, delay: Duration) -> () {
+            self.slow_blocks.push((pos, delay));
+        }
+
+        /// Checks the watchdog while delaying, as its thread would.
+        fn watch(&mut self, watchdog: &IoWatchdog, timeout: Duration) -> () {
+            self.watchdog = Some((watchdog.clone(), timeout));
+        }
+
+        fn lose_writes(&mut self) -> () {
+            self.lost_writes = true;
+        }
+
+        fn fill_up_at(&mut self, pos: u64) -> () {
+            self.full_at = Some(pos);
+        }
+
         fn check_for_traps(&mut self, read_bytes: usize, write_bytes: usize) -> Result<()> {
             let block_start = self.file.position();
-            let block_end = block_start + write_bytes as u64;
+            let block_end = block_start + (read_bytes + write_bytes) as u64;
+
+            if let Some((_, delay)) = self
+                .slow_blocks
+                .iter()
+                .find(|(b, _)| block_start <= *b && block_end > *b)
+            {
+                *self.clock.0.lock().unwrap() += *delay;
+                if let Some((watchdog, timeout)) = &self.watchdog {
+                    watchdog.check(*timeout, self.clock.now());
+                }
+            }
             let is_bad_block = self
                 .bad_blocks
                 .iter()
@@ -783,6 +1905,14 @@ mod test {
                 return Err(StorageError::BadBlock.into());
             }
 
+            if self
+                .timeouts
+                .iter()
+                .any(|b| block_start <= *b && block_end > *b)
+            {
+                return Err(StorageError::Timeout.into());
+            }
+
             let old_total = self.total_read + self.total_written;
 
             self.total_read += read_bytes;
@@ -815,6 +1945,14 @@ mod test {
 
         fn write(&mut self, data: &[u8]) -> Result<()> {
             self.check_for_traps(0, data.len())?;
+            if matches!(self.full_at, Some(p) if self.file.position() + data.len() as u64 > p) {
+                let err = std::io::Error::from(std::io::ErrorKind::StorageFull);
+                return Err(StorageError::Other(err).into());
+            }
+            if self.lost_writes {
+                self.file.seek(SeekFrom::Current(data.len() as i64))?;
+                return Ok(());
+            }
             self.file.write_all(data).context("unexpected")
         }
 
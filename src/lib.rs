@@ -0,0 +1,83 @@
+//! Lethe is a secure, free, cross-platform and open-source drive wiping library and utility.
+//!
+//! The library exposes the wiping engine used by the `lethe` command line tool:
+//!
+//! - [`storage`] enumerates storage devices (`System::enumerate_storage_devices`) and provides
+//!   low level access to them through the [`storage::StorageAccess`] trait.
+//! - [`sanitization`] defines the wiping schemes (`SchemeRepo`) and the data streams they produce.
+//! - [`actions`] runs a [`actions::WipeTask`] against a storage, reporting the progress to a
+//!   [`actions::WipeEventReceiver`].
+//! - [`ui`] contains the console frontend, only available with the `console` feature (enabled by default).
+//!
+//! Both `StorageAccess` and `WipeEventReceiver` are meant to be implemented by library consumers,
+//! e.g. to wipe a custom storage or to report the progress to a remote service.
+//!
+//! ```
+//! use lethe::actions::*;
+//! use lethe::sanitization::SchemeRepo;
+//! use lethe::storage::StorageAccess;
+//! use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+//!
+//! struct MemoryStorage(Cursor<Vec<u8>>);
+//!
+//! impl StorageAccess for MemoryStorage {
+//!     fn position(&mut self) -> anyhow::Result<u64> {
+//!         Ok(self.0.position())
+//!     }
+//!
+//!     fn seek(&mut self, position: u64) -> anyhow::Result<u64> {
+//!         Ok(self.0.seek(SeekFrom::Start(position))?)
+//!     }
+//!
+//!     fn read(&mut self, buffer: &mut [u8]) -> anyhow::Result<usize> {
+//!         Ok(self.0.read(buffer)?)
+//!     }
+//!
+//!     fn write(&mut self, data: &[u8]) -> anyhow::Result<()> {
+//!         Ok(self.0.write_all(data)?)
+//!     }
+//!
+//!     fn flush(&mut self) -> anyhow::Result<()> {
+//!         Ok(())
+//!     }
+//! }
+//!
+//! struct Silent;
+//!
+//! impl WipeEventReceiver for Silent {
+//!     fn handle(&mut self, _task: &WipeTask, _state: &WipeState, _event: WipeEvent) {}
+//! }
+//!
+//! let scheme = SchemeRepo::default().find("random").unwrap().clone();
+//! let task = WipeTask::new(scheme, Verify::Last, 65536, 4096, 512).unwrap();
+//! let mut storage = MemoryStorage(Cursor::new(vec![0xff; 65536]));
+//! let mut state = WipeState::default();
+//!
+//! assert!(task.run(&mut storage, &mut state, &mut Silent));
+//! ```
+
+#![recursion_limit = "256"]
+
+#[macro_use]
+extern crate anyhow;
+
+#[cfg(feature = "console")]
+#[macro_use]
+extern crate prettytable;
+
+#[cfg(target_os = "macos")]
+#[macro_use]
+extern crate serde_derive;
+
+#[cfg(target_os = "macos")]
+extern crate plist;
+
+pub mod actions;
+pub mod sanitization;
+pub mod storage;
+
+#[cfg(feature = "console")]
+pub mod ui;
+
+#[cfg(test)]
+mod test_support;
@@ -1,5 +1,3 @@
-#![recursion_limit = "256"]
-
 #[macro_use]
 extern crate anyhow;
 use anyhow::{Context, Result};
@@ -11,31 +9,25 @@ use clap::{App, AppSettings, Arg, SubCommand};
 extern crate prettytable;
 use prettytable::{format, Table};
 
-#[cfg(target_os = "macos")]
-#[macro_use]
-extern crate serde_derive;
-
-#[cfg(target_os = "macos")]
-extern crate plist;
-
 use ::console::style;
 use indicatif::HumanBytes;
 
-mod storage;
-use storage::*;
-
-mod sanitization;
-use sanitization::*;
-
-mod actions;
-use actions::*;
-
-mod ui;
-use ui::*;
+use lethe::actions::*;
+use lethe::sanitization::*;
+use lethe::storage::*;
+use lethe::ui;
+use lethe::ui::*;
 
 const VERSION: &'static str = env!("CARGO_PKG_VERSION");
 
-fn main() -> Result<()> {
+fn main() {
+    if let Err(err) = run() {
+        eprintln!("Error: {:?}", err);
+        std::process::exit(cli::exit_code_of(&err));
+    }
+}
+
+fn run() -> Result<()> {
     let schemes = SchemeRepo::default();
     let scheme_keys: Vec<_> = schemes.all().keys().cloned().collect();
 
@@ -48,7 +40,199 @@ fn main() -> Result<()> {
         .setting(AppSettings::SubcommandRequiredElseHelp)
         .setting(AppSettings::UnifiedHelpMessage)
         .setting(AppSettings::VersionlessSubcommands)
-        .subcommand(SubCommand::with_name("list").about("list available storage devices"))
+        .subcommand(
+            SubCommand::with_name("list")
+                .about("list available storage devices")
+                .arg(
+                    Arg::with_name("probe")
+                        .long("probe")
+                        .help("Query the drives for health, self-encryption and hidden areas, which can be slow"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("inspect")
+                .about("Show the partition table of a storage device or a disk image")
+                .arg(
+                    Arg::with_name("device")
+                        .required(true)
+                        .index(1)
+                        .help("Storage device ID or a path to a disk image"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("health")
+                .about("Show the SMART or NVMe health data of a storage device")
+                .arg(
+                    Arg::with_name("device")
+                        .required(true)
+                        .index(1)
+                        .help("Storage device ID"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("crypto-erase")
+                .about("Destroy the key material of a LUKS encrypted device")
+                .arg(
+                    Arg::with_name("device")
+                        .required(true)
+                        .index(1)
+                        .help("Storage device ID or a path to a disk image"),
+                )
+                .arg(
+                    Arg::with_name("wipe")
+                        .long("wipe")
+                        .help("Wipe the whole device after erasing the key material"),
+                )
+                .arg(
+                    Arg::with_name("scheme")
+                        .long("scheme")
+                        .short("s")
+                        .takes_value(true)
+                        .possible_values(&scheme_keys)
+                        .default_value("random2x")
+                        .requires("wipe")
+                        .help("Data sanitization scheme for the full wipe"),
+                )
+                .arg(
+                    Arg::with_name("verify")
+                        .long("verify")
+                        .short("v")
+                        .takes_value(true)
+                        .possible_values(&["no", "last", "all"])
+                        .default_value("last")
+                        .requires("wipe")
+                        .help("Verify the full wipe after completion"),
+                )
+                .arg(
+                    Arg::with_name("blocksize")
+                        .long("blocksize")
+                        .short("b")
+                        .takes_value(true)
+                        .default_value("1m")
+                        .help("Block size"),
+                )
+                .arg(
+                    Arg::with_name("yes")
+                        .long("yes")
+                        .short("y")
+                        .help("Automatically confirm"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("opal-revert")
+                .about("Revert a TCG Opal self-encrypting drive to the factory state")
+                .arg(
+                    Arg::with_name("device")
+                        .required(true)
+                        .index(1)
+                        .help("Storage device ID"),
+                )
+                .arg(
+                    Arg::with_name("psid")
+                        .long("psid")
+                        .takes_value(true)
+                        .required(true)
+                        .help("Physical Presence SID printed on the drive label"),
+                )
+                .arg(
+                    Arg::with_name("yes")
+                        .long("yes")
+                        .short("y")
+                        .help("Automatically confirm"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("sanitize")
+                .about("Sanitize a SCSI device using its built-in SANITIZE or FORMAT UNIT command")
+                .arg(
+                    Arg::with_name("device")
+                        .required(true)
+                        .index(1)
+                        .help("Storage device ID"),
+                )
+                .arg(
+                    Arg::with_name("method")
+                        .long("method")
+                        .short("m")
+                        .takes_value(true)
+                        .possible_values(&["overwrite", "block-erase", "crypto-erase", "format"])
+                        .help("Sanitize method, the methods supported by the device are listed when omitted"),
+                )
+                .arg(
+                    Arg::with_name("yes")
+                        .long("yes")
+                        .short("y")
+                        .help("Automatically confirm"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("scan")
+                .about("Look for recognizable data left on a storage device or a disk image")
+                .arg(
+                    Arg::with_name("device")
+                        .required(true)
+                        .index(1)
+                        .help("Storage device ID or a path to a disk image"),
+                )
+                .arg(
+                    Arg::with_name("blocksize")
+                        .long("blocksize")
+                        .short("b")
+                        .takes_value(true)
+                        .default_value("1m")
+                        .help("Block size"),
+                )
+                .arg(
+                    Arg::with_name("sample")
+                        .long("sample")
+                        .takes_value(true)
+                        .default_value("100")
+                        .help("Percentage of the blocks to read, evenly spread over the device"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("survey")
+                .about("Read the whole storage device looking for bad and slow sectors, without writing")
+                .arg(
+                    Arg::with_name("device")
+                        .required(true)
+                        .index(1)
+                        .help("Storage device ID or a path to a disk image"),
+                )
+                .arg(
+                    Arg::with_name("blocksize")
+                        .long("blocksize")
+                        .short("b")
+                        .takes_value(true)
+                        .default_value("1m")
+                        .help("Block size"),
+                )
+                .arg(
+                    Arg::with_name("io-timeout")
+                        .long("io-timeout")
+                        .takes_value(true)
+                        .help("Stop when an I/O operation takes longer than this, exit without a checkpoint if it doesn't complete within twice as long (e.g. 30s)"),
+                )
+                .arg(
+                    Arg::with_name("slow-io")
+                        .long("slow-io")
+                        .takes_value(true)
+                        .default_value("500ms")
+                        .help("Report I/O operations taking longer than this as slow"),
+                )
+                .arg(
+                    Arg::with_name("max-bad-blocks")
+                        .long("max-bad-blocks")
+                        .takes_value(true)
+                        .help("Abort when the number of bad sectors exceeds this value"),
+                )
+                .arg(
+                    Arg::with_name("export")
+                        .long("export")
+                        .takes_value(true)
+                        .help("File to save the bad block map to, for use with wipe --bad-blocks"),
+                ),
+        )
         .subcommand(
             SubCommand::with_name("wipe")
                 .about("Wipe storage device")
@@ -57,11 +241,37 @@ fn main() -> Result<()> {
                     Arg::with_name("device")
                         .long("device")
                         .short("d")
-                        .required(true)
+                        .required_unless("file")
                         .takes_value(true)
                         .index(1)
                         .help("Storage device ID"),
                 )
+                .arg(
+                    Arg::with_name("file")
+                        .long("file")
+                        .short("f")
+                        .takes_value(true)
+                        .conflicts_with("device")
+                        .help("Wipe a regular file or a disk image instead of a device"),
+                )
+                .arg(
+                    Arg::with_name("deallocate")
+                        .long("deallocate")
+                        .requires("file")
+                        .help("Release the disk space of the file after wiping"),
+                )
+                .arg(
+                    Arg::with_name("unallocated")
+                        .long("unallocated")
+                        .conflicts_with_all(&["resume", "deallocate"])
+                        .help("Wipe only the space outside of partitions, keeping them intact"),
+                )
+                .arg(
+                    Arg::with_name("unlock-hidden-areas")
+                        .long("unlock-hidden-areas")
+                        .conflicts_with_all(&["file", "resume", "unallocated"])
+                        .help("Remove the Host Protected Area until the next power cycle, so it's wiped too"),
+                )
                 .arg(
                     Arg::with_name("scheme")
                         .long("scheme")
@@ -86,7 +296,7 @@ fn main() -> Result<()> {
                         .short("b")
                         .takes_value(true)
                         .default_value("1m")
-                        .help("Block size"),
+                        .help("Block size, or auto to pick the fastest one with a short benchmark, skipped when the rate is limited"),
                 )
                 .arg(
                     Arg::with_name("retries")
@@ -96,6 +306,192 @@ fn main() -> Result<()> {
                         .default_value("8")
                         .help("Maximum number of retries"),
                 )
+                .arg(
+                    Arg::with_name("max-bad-blocks")
+                        .long("max-bad-blocks")
+                        .takes_value(true)
+                        .help("Abort when the number of bad sectors exceeds this value"),
+                )
+                .arg(
+                    Arg::with_name("max-bad-percent")
+                        .long("max-bad-percent")
+                        .takes_value(true)
+                        .help("Abort when the percentage of bad sectors exceeds this value"),
+                )
+                .arg(
+                    Arg::with_name("max-consecutive-bad")
+                        .long("max-consecutive-bad")
+                        .takes_value(true)
+                        .help(
+                            "Abort when the number of consecutive bad sectors exceeds this value",
+                        ),
+                )
+                .arg(
+                    Arg::with_name("io-timeout")
+                        .long("io-timeout")
+                        .takes_value(true)
+                        .help("Stop when an I/O operation takes longer than this, exit without a checkpoint if it doesn't complete within twice as long (e.g. 30s)"),
+                )
+                .arg(
+                    Arg::with_name("slow-io")
+                        .long("slow-io")
+                        .takes_value(true)
+                        .help("Report I/O operations taking longer than this as slow (e.g. 500ms)"),
+                )
+                .arg(
+                    Arg::with_name("rate-limit")
+                        .long("rate-limit")
+                        .takes_value(true)
+                        .help("Maximum throughput per second (e.g. 200M)"),
+                )
+                .arg(
+                    Arg::with_name("rate-limit-file")
+                        .long("rate-limit-file")
+                        .takes_value(true)
+                        .help(
+                            "File to read the rate limit from while wiping, checked every second",
+                        ),
+                )
+                .arg(
+                    Arg::with_name("io-class")
+                        .long("io-class")
+                        .takes_value(true)
+                        .possible_values(&["idle", "best-effort"])
+                        .help("I/O scheduling class to limit the impact on other processes"),
+                )
+                .arg(
+                    Arg::with_name("checkpoint")
+                        .long("checkpoint")
+                        .takes_value(true)
+                        .default_value("lethe.checkpoint")
+                        .help("File to save the progress to when the wipe is cancelled"),
+                )
+                .arg(
+                    Arg::with_name("resume")
+                        .long("resume")
+                        .takes_value(true)
+                        .help("Resume a cancelled wipe from the checkpoint file"),
+                )
+                .arg(
+                    Arg::with_name("chart")
+                        .long("chart")
+                        .takes_value(true)
+                        .help("File to save the throughput chart to, SVG or HTML (by extension)"),
+                )
+                .arg(
+                    Arg::with_name("bad-blocks")
+                        .long("bad-blocks")
+                        .takes_value(true)
+                        .conflicts_with_all(&["resume", "unallocated"])
+                        .help("Skip the bad sectors found by a previous survey"),
+                )
+                .arg(
+                    Arg::with_name("yes")
+                        .long("yes")
+                        .short("y")
+                        .help("Automatically confirm"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("wipe-free")
+                .about("Wipe free space of a mounted filesystem")
+                .after_help(schemes_explanation.as_str())
+                .arg(
+                    Arg::with_name("mountpoint")
+                        .required(true)
+                        .index(1)
+                        .help("Mount point or any directory of the filesystem"),
+                )
+                .arg(
+                    Arg::with_name("scheme")
+                        .long("scheme")
+                        .short("s")
+                        .takes_value(true)
+                        .possible_values(&scheme_keys)
+                        .default_value("random2x")
+                        .help("Data sanitization scheme"),
+                )
+                .arg(
+                    Arg::with_name("verify")
+                        .long("verify")
+                        .short("v")
+                        .takes_value(true)
+                        .possible_values(&["no", "last", "all"])
+                        .default_value("last")
+                        .help("Verify after completion"),
+                )
+                .arg(
+                    Arg::with_name("blocksize")
+                        .long("blocksize")
+                        .short("b")
+                        .takes_value(true)
+                        .default_value("1m")
+                        .help("Block size"),
+                )
+                .arg(
+                    Arg::with_name("reserve")
+                        .long("reserve")
+                        .takes_value(true)
+                        .default_value("1G")
+                        .help("Free space to leave for other processes (e.g. 1G or 5%)"),
+                )
+                .arg(
+                    Arg::with_name("yes")
+                        .long("yes")
+                        .short("y")
+                        .help("Automatically confirm"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("shred")
+                .about("Securely destroy files")
+                .after_help(schemes_explanation.as_str())
+                .arg(
+                    Arg::with_name("path")
+                        .required(true)
+                        .multiple(true)
+                        .index(1)
+                        .help("Files or directories to destroy"),
+                )
+                .arg(
+                    Arg::with_name("recursive")
+                        .long("recursive")
+                        .short("r")
+                        .help("Destroy directories and their content"),
+                )
+                .arg(
+                    Arg::with_name("scheme")
+                        .long("scheme")
+                        .short("s")
+                        .takes_value(true)
+                        .possible_values(&scheme_keys)
+                        .default_value("random2x")
+                        .help("Data sanitization scheme"),
+                )
+                .arg(
+                    Arg::with_name("verify")
+                        .long("verify")
+                        .short("v")
+                        .takes_value(true)
+                        .possible_values(&["no", "last", "all"])
+                        .default_value("last")
+                        .help("Verify after completion"),
+                )
+                .arg(
+                    Arg::with_name("blocksize")
+                        .long("blocksize")
+                        .short("b")
+                        .takes_value(true)
+                        .default_value("64k")
+                        .help("Block size"),
+                )
+                .arg(
+                    Arg::with_name("renames")
+                        .long("renames")
+                        .takes_value(true)
+                        .default_value("3")
+                        .help("Number of times to rename a file before removing it"),
+                )
                 .arg(
                     Arg::with_name("yes")
                         .long("yes")
@@ -118,34 +514,75 @@ fn main() -> Result<()> {
             }
         }
 
-        std::process::exit(1);
+        std::process::exit(cli::exit_code_of(&err));
     });
     let storage_repo = storage_repo::StorageRepo::from(storage_devices);
 
     let frontend = cli::ConsoleFrontend::new();
 
     match app.subcommand() {
-        ("list", _) => {
+        ("list", Some(cmd)) => {
+            let probe = cmd.is_present("probe");
+
             let mut t = Table::new();
             t.set_format(*format::consts::FORMAT_CLEAN);
-            t.set_titles(row![
+            let mut titles = row![
                 "Device ID",
                 "Short ID",
                 "Size",
                 "Type",
                 "Label",
                 "Mount Point",
-            ]);
+                "Content",
+                "UUID",
+            ];
+            if probe {
+                titles.add_cell(cell!("Health"));
+                titles.add_cell(cell!("SED"));
+                titles.add_cell(cell!("Native Size"));
+            }
+            t.set_titles(titles);
 
             let format_device = |tt: &mut Table, x: &StorageRef, level: usize| {
-                tt.add_row(row![
+                // devices which can't be opened (e.g. without root permissions) are listed anyway
+                let content = ContentInfo::probe(x).ok().flatten();
+                let mut row = row![
                     style(format!("{}{}", " ".repeat(level * 2), &x.id)).bold(),
                     style(storage_repo.get_short_id(&x.id).unwrap_or(&"".to_owned())).bold(),
                     HumanBytes(x.details.size),
                     &x.details.storage_type,
                     (&x.details.label).as_ref().unwrap_or(&"".to_string()),
                     (&x.details.mount_point).as_ref().unwrap_or(&"".to_string()),
-                ]);
+                    content
+                        .as_ref()
+                        .map(|c| c.kind.to_string())
+                        .unwrap_or_default(),
+                    content.and_then(|c| c.uuid).unwrap_or_default(),
+                ];
+                // only whole drives talk the security protocols
+                if probe {
+                    let (health, sed, hidden) = match level {
+                        0 => (
+                            HealthReport::read(x).ok(),
+                            x.scsi_transport_read_only()
+                                .and_then(OpalDevice::discover)
+                                .ok()
+                                .flatten(),
+                            HiddenAreas::probe(x).ok().filter(|h| !h.is_empty()),
+                        ),
+                        _ => (None, None, None),
+                    };
+                    row.add_cell(cell!(health
+                        .map(|h| cli::describe_health_status(&h))
+                        .unwrap_or_default()));
+                    row.add_cell(cell!(sed
+                        .map(|d| d.discovery.to_string())
+                        .unwrap_or_default()));
+                    row.add_cell(cell!(hidden
+                        .map(|h| HumanBytes(h.native_size() + h.dco_size()).to_string())
+                        .unwrap_or_default()));
+                }
+                tt.add_row(row);
             };
 
             for x in storage_repo.devices() {
@@ -156,8 +593,323 @@ fn main() -> Result<()> {
             }
             t.printstd();
         }
+        ("inspect", Some(cmd)) => {
+            let device = &storage_repo.find_by_id_or_path(cmd.value_of("device").unwrap())?;
+
+            let table = match PartitionTable::inspect(device)? {
+                Some(table) => table,
+                None => {
+                    println!("No partition table found on {}.", device.id);
+                    return Ok(());
+                }
+            };
+
+            let mut t = Table::new();
+            t.set_format(*format::consts::FORMAT_CLEAN);
+            t.add_row(row!["Device", style(&device.id).bold()]);
+            t.add_row(row!["Size", HumanBytes(device.details.size)]);
+            t.add_row(row!["Sector size", table.sector_size]);
+            t.add_row(row!["Partition table", table.kind]);
+            if let Some(guid) = &table.disk_guid {
+                t.add_row(row!["Disk GUID", guid]);
+            }
+            t.printstd();
+            println!();
+
+            let mut t = Table::new();
+            t.set_format(*format::consts::FORMAT_CLEAN);
+            t.set_titles(row![
+                "#",
+                "Type",
+                "Start LBA",
+                "End LBA",
+                "Size",
+                "Name",
+                "GUID"
+            ]);
+            // unallocated space is listed in between the partitions
+            let mut rows = Vec::new();
+            for p in &table.partitions {
+                rows.push((
+                    p.first_lba,
+                    row![
+                        style(p.number).bold(),
+                        match p.partition_type.name() {
+                            Some(name) => format!("{} ({})", name, p.partition_type),
+                            None => p.partition_type.to_string(),
+                        },
+                        p.first_lba,
+                        p.last_lba,
+                        HumanBytes((p.last_lba - p.first_lba + 1) * table.sector_size),
+                        p.name.as_deref().unwrap_or(""),
+                        p.guid.map(|g| g.to_string()).unwrap_or_default(),
+                    ],
+                ));
+            }
+            for e in table.unallocated(device.details.size) {
+                rows.push((
+                    e.start / table.sector_size,
+                    row![
+                        "",
+                        style("Unallocated").dim(),
+                        e.start / table.sector_size,
+                        e.end() / table.sector_size - 1,
+                        HumanBytes(e.length),
+                        "",
+                        "",
+                    ],
+                ));
+            }
+            rows.sort_by_key(|(lba, _)| *lba);
+            for (_, row) in rows {
+                t.add_row(row);
+            }
+            t.printstd();
+        }
+        ("health", Some(cmd)) => {
+            let device = &storage_repo.find_by_id_or_path(cmd.value_of("device").unwrap())?;
+            let report = HealthReport::read(device)?;
+
+            let optional = |v: Option<u64>| v.map(|v| v.to_string()).unwrap_or_default();
+            let mut t = Table::new();
+            t.set_format(*format::consts::FORMAT_CLEAN);
+            t.add_row(row!["Device", style(&device.id).bold()]);
+            t.add_row(row![
+                "Source",
+                match report.source {
+                    HealthSource::AtaSmart => "ATA SMART",
+                    HealthSource::NvmeHealthLog => "NVMe health log",
+                }
+            ]);
+            t.add_row(row![
+                "Overall assessment",
+                match report.passed {
+                    Some(true) => style("Passed").green(),
+                    Some(false) => style("FAILED").red().bold(),
+                    None => style("Unknown").dim(),
+                }
+            ]);
+            t.add_row(row!["Power on hours", optional(report.power_on_hours)]);
+            t.add_row(row![
+                "Temperature",
+                report
+                    .temperature
+                    .map(|t| format!("{} °C", t))
+                    .unwrap_or_default()
+            ]);
+            t.add_row(row![
+                "Reallocated sectors",
+                optional(report.reallocated_sectors)
+            ]);
+            t.add_row(row!["Pending sectors", optional(report.pending_sectors)]);
+            t.add_row(row![
+                "Uncorrectable sectors",
+                optional(report.uncorrectable_sectors)
+            ]);
+            t.add_row(row!["Media errors", optional(report.media_errors)]);
+            t.add_row(row![
+                "Endurance used",
+                report
+                    .percentage_used
+                    .map(|p| format!("{}%", p))
+                    .unwrap_or_default()
+            ]);
+            t.printstd();
+
+            if !report.attributes.is_empty() {
+                println!();
+                let mut t = Table::new();
+                t.set_format(*format::consts::FORMAT_CLEAN);
+                t.set_titles(row!["ID", "Current", "Worst", "Raw"]);
+                for a in &report.attributes {
+                    t.add_row(row![style(a.id).bold(), a.current, a.worst, a.raw]);
+                }
+                t.printstd();
+            }
+        }
+        ("crypto-erase", Some(cmd)) => {
+            let device = &storage_repo.find_by_id_or_path(cmd.value_of("device").unwrap())?;
+            let block_size_arg = cmd.value_of("blocksize").unwrap();
+            let block_size = ui::args::parse_block_size(block_size_arg)
+                .context(format!("Invalid blocksize value: {}", block_size_arg))?;
+            let sector_size = match device.details.block_size {
+                0 => IMAGE_SECTOR_SIZE,
+                s => s,
+            };
+            // the kernel keeps the master key of an open mapping, leaving the data readable
+            ensure_unmounted(device)?;
+            ensure_not_held(device)?;
+
+            let mut access = device.access()?;
+            let header = LuksHeader::read(access.as_mut(), sector_size)?
+                .ok_or_else(|| anyhow!("No LUKS header found on {}", device.id))?;
+            let erase = CryptoErase::new(header, device.details.size, block_size, sector_size)?;
+            let mut state = WipeState::default();
+
+            let mut session = frontend.wipe_session(
+                &format!("LUKS{} key material of {}", erase.header.version, device.id),
+                cmd.is_present("yes"),
+            );
+            session.handle(&erase.task, &state, WipeEvent::Created);
+
+            ui::control::handle_signals(erase.task.control.clone())?;
+
+            if let Err(err) = erase.run(access.as_mut(), &mut state, &mut session) {
+                eprintln!("{:#}", err);
+                std::process::exit(match session.exit_code() {
+                    0 => cli::exit_code_of(&err),
+                    code => code,
+                });
+            }
+            println!("LUKS key material destroyed.");
+
+            if cmd.is_present("wipe") {
+                let scheme_id = cmd.value_of("scheme").unwrap();
+                let scheme = schemes
+                    .find(scheme_id)
+                    .ok_or(anyhow!("Unknown scheme {}", scheme_id))?;
+                let verification = match cmd.value_of("verify").unwrap() {
+                    "no" => Verify::No,
+                    "last" => Verify::Last,
+                    "all" => Verify::All,
+                    _ => Verify::Last,
+                };
+
+                let mut task = WipeTask::new(
+                    scheme.clone(),
+                    verification,
+                    device.details.size,
+                    block_size,
+                    sector_size,
+                )?;
+                // the signals are already handled for the erase
+                task.control = erase.task.control.clone();
+                let mut state = WipeState::default();
+
+                // already confirmed for the key material
+                let mut session = cli::ConsoleFrontend::new().wipe_session(&device.id, true);
+                session.handle(&task, &state, WipeEvent::Created);
+
+                task.run(access.as_mut(), &mut state, &mut session);
+                std::process::exit(session.exit_code());
+            }
+        }
+        ("opal-revert", Some(cmd)) => {
+            let device = &storage_repo.find_by_id_or_path(cmd.value_of("device").unwrap())?;
+            let psid = cmd.value_of("psid").unwrap();
+            ensure_unmounted(device)?;
+
+            let mut opal = OpalDevice::probe(device)?
+                .ok_or_else(|| anyhow!("{} is not a TCG Opal self-encrypting drive", device.id))?;
+
+            if !frontend.confirm_opal_revert(device, &opal.discovery, cmd.is_present("yes")) {
+                println!("Aborted.");
+                std::process::exit(cli::EXIT_ABORTED);
+            }
+
+            opal.revert(OpalAuthority::Psid, psid.as_bytes())?;
+            println!("Drive reverted to the factory state.");
+        }
+        ("sanitize", Some(cmd)) => {
+            let device = &storage_repo.find_by_id_or_path(cmd.value_of("device").unwrap())?;
+            let mut transport = device.scsi_transport()?;
+
+            let method = match cmd.value_of("method") {
+                Some("overwrite") => SanitizeMethod::Overwrite,
+                Some("block-erase") => SanitizeMethod::BlockErase,
+                Some("crypto-erase") => SanitizeMethod::CryptoErase,
+                Some(_) => SanitizeMethod::SecureFormat,
+                None => {
+                    let supported = SanitizeMethod::supported(transport.as_mut())?;
+                    if supported.is_empty() {
+                        println!("No sanitize methods supported by {}.", device.id);
+                    }
+                    for m in supported {
+                        println!("{}", m);
+                    }
+                    return Ok(());
+                }
+            };
+            ensure_unmounted(device)?;
+            let sector_size = match device.details.block_size {
+                0 => IMAGE_SECTOR_SIZE,
+                s => s,
+            };
+
+            let sanitize = DeviceSanitize::new(method, device.details.size, sector_size)?;
+            let mut state = WipeState::default();
+
+            let mut session = frontend.sanitize_session(&device.id, cmd.is_present("yes"));
+            session.handle(&sanitize.task, &state, WipeEvent::Created);
+
+            if !sanitize.run(transport.as_mut(), &mut state, &mut session) {
+                std::process::exit(session.exit_code());
+            }
+        }
+        ("scan", Some(cmd)) => {
+            let device = &storage_repo.find_by_id_or_path(cmd.value_of("device").unwrap())?;
+
+            let block_size_arg = cmd.value_of("blocksize").unwrap();
+            let block_size = ui::args::parse_block_size_in(block_size_arg, device_units(device))
+                .context(format!("Invalid blocksize value: {}", block_size_arg))?;
+            let sample = cmd
+                .value_of("sample")
+                .unwrap()
+                .parse()
+                .context("Invalid sample value")?;
+
+            let task = ScanTask::new(device.details.size, block_size, sample)?;
+            if !frontend.scan(&task, device)? {
+                std::process::exit(cli::EXIT_DATA_FOUND);
+            }
+        }
+        ("survey", Some(cmd)) => {
+            let device = &storage_repo.find_by_id_or_path(cmd.value_of("device").unwrap())?;
+
+            let block_size_arg = cmd.value_of("blocksize").unwrap();
+            let block_size = ui::args::parse_block_size_in(block_size_arg, device_units(device))
+                .context(format!("Invalid blocksize value: {}", block_size_arg))?;
+            // same as wipe, so the exported bad block map can be used with it
+            let sector_size = match device.details.block_size {
+                0 => block_size,
+                s => s,
+            };
+
+            let mut survey = SurveyTask::new(device.details.size, block_size, sector_size)?;
+            survey.task.io_timeout = cmd
+                .value_of("io-timeout")
+                .map(ui::args::parse_duration)
+                .transpose()
+                .context("Invalid io-timeout value")?;
+            survey.task.slow_io_threshold = cmd
+                .value_of("slow-io")
+                .map(ui::args::parse_duration)
+                .transpose()
+                .context("Invalid slow-io value")?;
+            survey.task.bad_block_policy.max_bad_blocks = cmd
+                .value_of("max-bad-blocks")
+                .map(|v| v.parse())
+                .transpose()
+                .context("Invalid max-bad-blocks value")?;
+            let mut state = WipeState::default();
+
+            let mut session = frontend.survey_session(&device.id);
+            session.handle(&survey.task, &state, WipeEvent::Created);
+
+            ui::control::handle_signals(survey.task.control.clone())?;
+            if let Some(timeout) = survey.task.io_timeout {
+                ui::control::watch_stalled_io(survey.task.watchdog.clone(), timeout);
+            }
+            survey.survey(device, &mut state, &mut session)?;
+
+            if let Some(path) = cmd.value_of("export") {
+                BadBlockMap::capture(&device.id, &survey.task, &state).save(path)?;
+                println!("Bad block map saved to {}.", path);
+            }
+            // also tells whether unreadable sectors were found
+            std::process::exit(session.exit_code());
+        }
         ("wipe", Some(cmd)) => {
-            let device_id = cmd.value_of("device").ok_or(anyhow!("Invalid device ID"))?;
             let scheme_id = cmd.value_of("scheme").unwrap();
             let verification = match cmd.value_of("verify").unwrap() {
                 "no" => Verify::No,
@@ -165,13 +917,28 @@ fn main() -> Result<()> {
                 "all" => Verify::All,
                 _ => Verify::Last,
             };
+            let file_target;
+            let device = match cmd.value_of("file") {
+                Some(path) => {
+                    file_target = StorageRef::from_file(path)?;
+                    &file_target
+                }
+                None => {
+                    let device_id = cmd.value_of("device").ok_or(anyhow!("Invalid device ID"))?;
+                    storage_repo
+                        .find_by_id(device_id)
+                        .ok_or_else(|| cli::CommandError::DeviceNotFound(device_id.to_owned()))?
+                }
+            };
             let block_size_arg = cmd.value_of("blocksize").unwrap();
-            let block_size = ui::args::parse_block_size(block_size_arg)
-                .context(format!("Invalid blocksize value: {}", block_size_arg))?;
-
-            let device = storage_repo
-                .find_by_id(device_id)
-                .ok_or(anyhow!("Unknown device {}", device_id))?;
+            let auto_block_size = block_size_arg == "auto";
+            let block_size = match auto_block_size {
+                true => None,
+                false => Some(
+                    ui::args::parse_block_size_in(block_size_arg, device_units(device))
+                        .context(format!("Invalid blocksize value: {}", block_size_arg))?,
+                ),
+            };
             let scheme = schemes
                 .find(scheme_id)
                 .ok_or(anyhow!("Unknown scheme {}", scheme_id))?;
@@ -182,36 +949,374 @@ fn main() -> Result<()> {
                 .parse()
                 .context("Invalid retries number value")?;
 
-            let task = WipeTask::new(
-                scheme.clone(),
-                verification,
-                device.details.size,
-                block_size,
-            )?;
+            let sector_size = match device.details.block_size {
+                0 => IMAGE_SECTOR_SIZE,
+                s => s,
+            };
+
+            let unallocated = if cmd.is_present("unallocated") {
+                let mut access = device.access_shared()?;
+                let table =
+                    PartitionTable::read(access.as_mut(), sector_size, device.details.size)?
+                        .ok_or_else(|| anyhow!("No partition table found on {}", device.id))?;
+                let extents = table.unallocated(device.details.size);
+                if extents.is_empty() {
+                    return Err(anyhow!("No unallocated space on {}", device.id));
+                }
+                Some((extents.clone(), ExtentsAccess::new(access, extents)))
+            } else {
+                None
+            };
+            // devices which don't accept ATA commands don't have hidden areas
+            let hidden = match cmd.value_of("file") {
+                Some(_) => None,
+                None => HiddenAreas::probe(device).ok().filter(|h| !h.is_empty()),
+            };
+            let unlock_hidden = cmd.is_present("unlock-hidden-areas");
+            if unlock_hidden && hidden.as_ref().map(|h| h.hpa_size()).unwrap_or(0) == 0 {
+                return Err(anyhow!("No Host Protected Area found on {}", device.id));
+            }
+            // unmounting the partitions while wiping is best effort, the capacity mustn't change
+            // under a filesystem still mounted
+            if unlock_hidden {
+                ensure_unmounted(device)?;
+            }
+
+            let total_size = match (&unallocated, &hidden) {
+                (Some((_, a)), _) => a.total_size(),
+                (None, Some(h)) if unlock_hidden => h.native_size(),
+                _ => device.details.size,
+            };
+
+            let bad_block_policy = BadBlockPolicy {
+                max_bad_blocks: cmd
+                    .value_of("max-bad-blocks")
+                    .map(|v| v.parse())
+                    .transpose()
+                    .context("Invalid max-bad-blocks value")?,
+                max_bad_percent: cmd
+                    .value_of("max-bad-percent")
+                    .map(|v| v.parse())
+                    .transpose()
+                    .context("Invalid max-bad-percent value")?,
+                max_consecutive_bad: cmd
+                    .value_of("max-consecutive-bad")
+                    .map(|v| v.parse())
+                    .transpose()
+                    .context("Invalid max-consecutive-bad value")?,
+            };
+
+            let (mut task, mut state) = match cmd.value_of("resume") {
+                Some(path) => {
+                    let checkpoint = Checkpoint::load(path)?;
+                    if checkpoint.device_id != device.id
+                        || checkpoint.total_size != device.details.size
+                    {
+                        return Err(cli::CommandError::AnotherDevice(
+                            "checkpoint",
+                            checkpoint.device_id,
+                        )
+                        .into());
+                    }
+                    (checkpoint.task()?, checkpoint.state())
+                }
+                None => (
+                    WipeTask::new(
+                        scheme.clone(),
+                        verification,
+                        total_size,
+                        // replaced with the benchmark result right before wiping
+                        match block_size {
+                            Some(b) => b,
+                            None => *BlockSizeBenchmark::candidates(sector_size)
+                                .last()
+                                .ok_or_else(|| {
+                                    anyhow!("No block size fits the sector size of {}", sector_size)
+                                })?,
+                        },
+                        sector_size,
+                    )?,
+                    WipeState::default(),
+                ),
+            };
+            task.bad_block_policy = bad_block_policy;
+            if let Some(path) = cmd.value_of("bad-blocks") {
+                let map = BadBlockMap::load(path)?;
+                if map.device_id != device.id || map.total_size != device.details.size {
+                    return Err(
+                        cli::CommandError::AnotherDevice("bad block map", map.device_id).into(),
+                    );
+                }
+                map.apply(&task, &state)?;
+            }
+            task.io_timeout = cmd
+                .value_of("io-timeout")
+                .map(ui::args::parse_duration)
+                .transpose()
+                .context("Invalid io-timeout value")?;
+            task.slow_io_threshold = cmd
+                .value_of("slow-io")
+                .map(ui::args::parse_duration)
+                .transpose()
+                .context("Invalid slow-io value")?;
+
+            task.rate_limit.set(
+                cmd.value_of("rate-limit")
+                    .map(ui::args::parse_rate)
+                    .transpose()
+                    .context("Invalid rate-limit value")?
+                    .flatten(),
+            );
+
+            match cmd.value_of("io-class") {
+                Some("idle") => System::set_io_class(IoClass::Idle)?,
+                Some("best-effort") => System::set_io_class(IoClass::BestEffort)?,
+                _ => (),
+            };
 
-            let mut state = WipeState::default();
             state.retries_left = retries;
 
             let mut session = frontend.wipe_session(&device.id, cmd.is_present("yes"));
+            if let Some((extents, _)) = &unallocated {
+                session = session.with_extents(extents);
+            }
+            let content: Vec<_> = std::iter::once(device)
+                .chain(device.children.iter())
+                .filter_map(|d| {
+                    ContentInfo::probe(d)
+                        .ok()
+                        .flatten()
+                        .map(|c| (d.id.clone(), c))
+                })
+                .collect();
+            session = session.with_content(&content);
+            if auto_block_size && cmd.value_of("resume").is_none() {
+                session = session.with_auto_block_size();
+            }
+            if let Some(path) = cmd.value_of("chart") {
+                session = session.with_chart(path);
+            }
+            if let Some(h) = &hidden {
+                session = session.with_hidden_areas(h, unlock_hidden);
+            }
+            // compared with the health after the wipe, so worn out drives are noticed
+            let health = match cmd.value_of("file") {
+                Some(_) => None,
+                None => HealthReport::read(device).ok(),
+            };
+            if let Some(h) = &health {
+                session = session.with_health(h);
+            }
             session.handle(&task, &state, WipeEvent::Created);
 
-            match device.access() {
+            ui::control::handle_signals(task.control.clone())?;
+            if let Some(timeout) = task.io_timeout {
+                ui::control::watch_stalled_io(task.watchdog.clone(), timeout);
+            }
+            if let Some(path) = cmd.value_of("rate-limit-file") {
+                ui::control::watch_rate_limit_file(path.into(), task.rate_limit.clone());
+            }
+
+            let access = match unallocated {
+                Some((_, access)) => Ok(Box::new(access) as Box<dyn StorageAccess>),
+                None => device.access(),
+            };
+
+            match access {
                 Ok(mut access) => {
-                    if !task.run(access.as_mut(), &mut state, &mut session) {
-                        std::process::exit(1);
+                    // the capacity is changed only once the device is unmounted by the access
+                    if let (Some(h), true) = (&hidden, unlock_hidden) {
+                        h.unlock(device.scsi_transport()?.as_mut())?;
+                        System::rescan_capacity(&device.id)?;
+                    }
+
+                    // the largest block size set in the task is kept if the benchmark is skipped
+                    if auto_block_size && cmd.value_of("resume").is_none() {
+                        // the benchmark writes at full speed, which a limited rate forbids
+                        if task.rate_limit.get().is_some() {
+                            session.report_benchmark_skipped(&task, "the rate is limited");
+                        } else {
+                            match BlockSizeBenchmark::run(
+                                access.as_mut(),
+                                task.total_size,
+                                task.sector_size,
+                            ) {
+                                Ok(benchmark) => {
+                                    task.block_size = benchmark.block_size;
+                                    session.report_benchmark(&benchmark);
+                                }
+                                Err(err) => {
+                                    session.report_benchmark_skipped(&task, &format!("{:#}", err))
+                                }
+                            }
+                        }
+                    }
+
+                    let succeeded = task.run(access.as_mut(), &mut state, &mut session);
+                    if let Some(after) = health
+                        .as_ref()
+                        .and_then(|_| HealthReport::read(device).ok())
+                    {
+                        session.report_health(&after);
+                    }
+
+                    if !succeeded {
+                        if session.is_resumable() {
+                            let path = cmd.value_of("checkpoint").unwrap();
+                            Checkpoint::capture(&device.id, &task, &state).save(path)?;
+                            eprintln!(
+                                "Progress saved to {}. Use --resume {} to continue.",
+                                path, path
+                            );
+                        }
+                        std::process::exit(session.exit_code());
+                    }
+
+                    if cmd.is_present("deallocate") {
+                        drop(access);
+                        ImageFileAccess::open(&device.id)?
+                            .deallocate()
+                            .context("Unable to deallocate the wiped file")?;
                     }
+                    // succeeded, but the unreadable sectors are left as they were
+                    std::process::exit(session.exit_code());
                 }
                 Err(err) => {
+                    let code = cli::exit_code_of(&err);
                     session.handle(&task, &state, WipeEvent::Fatal(err));
-                    std::process::exit(1);
+                    std::process::exit(code);
                 }
             }
         }
+        ("wipe-free", Some(cmd)) => {
+            let mount_point = std::path::Path::new(cmd.value_of("mountpoint").unwrap());
+            let scheme_id = cmd.value_of("scheme").unwrap();
+            let scheme = schemes
+                .find(scheme_id)
+                .ok_or(anyhow!("Unknown scheme {}", scheme_id))?;
+            let verification = match cmd.value_of("verify").unwrap() {
+                "no" => Verify::No,
+                "last" => Verify::Last,
+                "all" => Verify::All,
+                _ => Verify::Last,
+            };
+            let block_size_arg = cmd.value_of("blocksize").unwrap();
+            let block_size = ui::args::parse_block_size(block_size_arg)
+                .context(format!("Invalid blocksize value: {}", block_size_arg))?;
+            let reserve_arg = cmd.value_of("reserve").unwrap();
+            // percentages are relative to the currently free space
+            let reserve_units = ui::args::SizeUnits {
+                total_size: Some(System::free_space(mount_point)?),
+                ..Default::default()
+            };
+            let reserve = ui::args::parse_size_in(reserve_arg, reserve_units)
+                .context(format!("Invalid reserve value: {}", reserve_arg))?;
+
+            let wipe = FreeSpaceWipe::new(
+                mount_point,
+                scheme.clone(),
+                verification,
+                block_size,
+                reserve,
+            )?;
+            let mut state = WipeState::default();
+
+            let mut session = frontend.wipe_session(
+                &format!("Free space of {}", mount_point.display()),
+                cmd.is_present("yes"),
+            );
+            session.handle(&wipe.task, &state, WipeEvent::Created);
+
+            ui::control::handle_signals(wipe.task.control.clone())?;
+
+            if !wipe.run(&mut state, &mut session)? {
+                std::process::exit(session.exit_code());
+            }
+        }
+        ("shred", Some(cmd)) => {
+            let paths: Vec<std::path::PathBuf> =
+                cmd.values_of("path").unwrap().map(|p| p.into()).collect();
+            let scheme_id = cmd.value_of("scheme").unwrap();
+            let scheme = schemes
+                .find(scheme_id)
+                .ok_or(anyhow!("Unknown scheme {}", scheme_id))?;
+            let verification = match cmd.value_of("verify").unwrap() {
+                "no" => Verify::No,
+                "last" => Verify::Last,
+                "all" => Verify::All,
+                _ => Verify::Last,
+            };
+            let block_size_arg = cmd.value_of("blocksize").unwrap();
+            let block_size = ui::args::parse_block_size(block_size_arg)
+                .context(format!("Invalid blocksize value: {}", block_size_arg))?;
+            let renames = cmd
+                .value_of("renames")
+                .unwrap()
+                .parse()
+                .context("Invalid renames number value")?;
+
+            let task = ShredTask::new(scheme.clone(), verification, block_size, renames)?;
+            let targets = ShredTask::collect_targets(&paths, cmd.is_present("recursive"))?;
+
+            let mut session = frontend.shred_session(cmd.is_present("yes"));
+            if !session.confirm(&task, &targets) {
+                println!("Aborted.");
+                std::process::exit(cli::EXIT_ABORTED);
+            }
+
+            for target in &targets {
+                session.shredding(target);
+                let result = task.shred(target, &mut session);
+                session.shredded(&result);
+            }
+
+            std::process::exit(session.exit_code());
+        }
         _ => {
             println!("{}", app.usage());
-            std::process::exit(1)
+            std::process::exit(cli::EXIT_FAILURE)
         }
     }
 
     Ok(())
 }
+
+/// Commands executed by the drive itself bypass the mounted filesystems, which would
+/// keep using the erased data, so the device is refused instead.
+fn ensure_unmounted(device: &StorageRef) -> Result<()> {
+    let mounted = std::iter::once(device)
+        .chain(device.children.iter())
+        .find_map(|d| d.details.mount_point.as_ref().map(|m| (d, m)));
+    if let Some((d, mount_point)) = mounted {
+        Err(cli::CommandError::Mounted(
+            d.id.clone(),
+            mount_point.clone(),
+        ))?;
+    }
+    Ok(())
+}
+
+/// Refuses devices with other devices stacked on top of them, e.g. an open LUKS mapping
+/// whose filesystem is mounted from the mapper device instead of the device itself.
+fn ensure_not_held(device: &StorageRef) -> Result<()> {
+    if let StorageType::File = device.details.storage_type {
+        return Ok(());
+    }
+    for d in std::iter::once(device).chain(device.children.iter()) {
+        if let Some(holder) = System::holders(&d.id)?.into_iter().next() {
+            Err(cli::CommandError::InUse(d.id.clone(), holder))?;
+        }
+    }
+    Ok(())
+}
+
+/// Sizes given in sectors use the logical sector size of the device, image files have none.
+fn device_units(device: &StorageRef) -> ui::args::SizeUnits {
+    ui::args::SizeUnits {
+        sector_size: match device.details.block_size {
+            0 => None,
+            s => Some(s as u64),
+        },
+        total_size: None,
+    }
+}
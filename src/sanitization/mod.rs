@@ -20,7 +20,17 @@ impl SchemeRepo {
         SchemeRepo { schemes }
     }
 
-    pub fn default() -> SchemeRepo {
+    pub fn all(&self) -> &BTreeMap<&'static str, Scheme> {
+        &self.schemes
+    }
+
+    pub fn find(&self, name: &str) -> Option<&Scheme> {
+        self.schemes.get(name)
+    }
+}
+
+impl Default for SchemeRepo {
+    fn default() -> SchemeRepo {
         let mut schemes = BTreeMap::new();
 
         schemes.insert(
@@ -94,14 +104,6 @@ impl SchemeRepo {
 
         Self::new(schemes)
     }
-
-    pub fn all(&self) -> &BTreeMap<&'static str, Scheme> {
-        &self.schemes
-    }
-
-    pub fn find(&self, name: &str) -> Option<&Scheme> {
-        self.schemes.get(name)
-    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,220 @@
+use crate::storage::{sense_of, DataDirection, ScsiTransport, Sense};
+use anyhow::Result;
+use std::time::Duration;
+
+const ATA_PASS_THROUGH_16: u8 = 0x85;
+const ATA_TRUSTED_RECEIVE: u8 = 0x5c;
+const ATA_TRUSTED_SEND: u8 = 0x5e;
+pub const ATA_SECTOR_SIZE: usize = 512;
+const ATA_STATUS_ERR: u8 = 0x01;
+/// ATA Status Return sense data descriptor.
+const ATA_STATUS_DESCRIPTOR: u8 = 0x09;
+
+/// ATA transfer protocols, as encoded in the pass-through command.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AtaProtocol {
+    NonData = 3,
+    PioIn = 4,
+    PioOut = 5,
+}
+
+/// Register values of an ATA command sent through SCSI/ATA translation (SAT).
+#[derive(Debug, Clone, Default)]
+pub struct AtaCommand {
+    pub command: u8,
+    pub features: u16,
+    pub count: u16,
+    pub lba: u64,
+    pub device: u8,
+    /// 48-bit command, using the extended registers.
+    pub extended: bool,
+}
+
+impl AtaCommand {
+    /// Builds an ATA PASS-THROUGH (16) command. Data transfers are counted in sectors
+    /// and the length is taken from the count register.
+    pub fn pass_through_cdb(&self, protocol: AtaProtocol, check_condition: bool) -> [u8; 16] {
+        let mut cdb = [0u8; 16];
+        cdb[0] = ATA_PASS_THROUGH_16;
+        cdb[1] = ((protocol as u8) << 1) | self.extended as u8;
+        cdb[2] = match protocol {
+            AtaProtocol::NonData => 0,
+            AtaProtocol::PioIn => 0x0e,
+            AtaProtocol::PioOut => 0x06,
+        } | ((check_condition as u8) << 5);
+        cdb[3..5].copy_from_slice(&self.features.to_be_bytes());
+        cdb[5..7].copy_from_slice(&self.count.to_be_bytes());
+        let lba = self.lba.to_be_bytes();
+        cdb[7] = lba[4];
+        cdb[8] = lba[7];
+        cdb[9] = lba[3];
+        cdb[10] = lba[6];
+        cdb[11] = lba[2];
+        cdb[12] = lba[5];
+        cdb[13] = self.device;
+        cdb[14] = self.command;
+        cdb
+    }
+
+    pub fn execute(
+        &self,
+        transport: &mut dyn ScsiTransport,
+        protocol: AtaProtocol,
+        data: &mut [u8],
+        timeout: Duration,
+    ) -> Result<()> {
+        let direction = match protocol {
+            AtaProtocol::NonData => DataDirection::None,
+            AtaProtocol::PioIn => DataDirection::FromDevice,
+            AtaProtocol::PioOut => DataDirection::ToDevice,
+        };
+        transport.execute(
+            &self.pass_through_cdb(protocol, false),
+            direction,
+            data,
+            timeout,
+        )
+    }
+
+    /// Runs a non-data command and returns the registers set by the device,
+    /// e.g. the address returned by READ NATIVE MAX ADDRESS.
+    pub fn execute_with_status(
+        &self,
+        transport: &mut dyn ScsiTransport,
+        timeout: Duration,
+    ) -> Result<AtaStatus> {
+        let cdb = self.pass_through_cdb(AtaProtocol::NonData, true);
+        // the registers are returned in the sense data of the check condition
+        let err = match transport.execute(&cdb, DataDirection::None, &mut [], timeout) {
+            Ok(()) => Err(anyhow!("No ATA registers returned"))?,
+            Err(err) => err,
+        };
+        match sense_of(&err).and_then(AtaStatus::from_sense) {
+            Some(s) if s.status & ATA_STATUS_ERR != 0 => Err(anyhow!(
+                "ATA command {:#04x} aborted (error {:#04x})",
+                self.command,
+                s.error
+            )),
+            Some(s) => Ok(s),
+            None => Err(err),
+        }
+    }
+
+    /// TRUSTED RECEIVE or TRUSTED SEND, the ATA counterparts of the SCSI security protocol commands.
+    pub fn trusted(send: bool, protocol: u8, sp_specific: u16, length: usize) -> AtaCommand {
+        let sectors = (length / ATA_SECTOR_SIZE) as u64;
+        AtaCommand {
+            command: if send {
+                ATA_TRUSTED_SEND
+            } else {
+                ATA_TRUSTED_RECEIVE
+            },
+            features: protocol as u16,
+            count: (sectors & 0xff) as u16,
+            lba: ((sp_specific as u64) << 8) | (sectors >> 8),
+            ..Default::default()
+        }
+    }
+}
+
+/// ATA registers after the command completion.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AtaStatus {
+    pub error: u8,
+    pub status: u8,
+    pub count: u16,
+    pub lba: u64,
+    pub device: u8,
+}
+
+impl AtaStatus {
+    /// Parses the registers returned by SCSI/ATA translation in descriptor format sense data,
+    /// or in fixed format if the upper address bytes are zero.
+    pub fn from_sense(sense: &Sense) -> Option<AtaStatus> {
+        let raw = &sense.raw;
+        let byte = |i: usize| raw.get(i).cloned().unwrap_or(0) as u64;
+
+        match raw.first().map(|b| b & 0x7f) {
+            Some(0x72) | Some(0x73) => {
+                let end = (8 + byte(7) as usize).min(raw.len());
+                let mut offset = 8;
+                while offset + 14 <= end {
+                    if raw[offset] == ATA_STATUS_DESCRIPTOR {
+                        let d = &raw[offset..offset + 14];
+                        return Some(AtaStatus {
+                            error: d[3],
+                            status: d[13],
+                            count: u16::from_be_bytes([d[4], d[5]]),
+                            lba: u64::from_be_bytes([0, 0, d[10], d[8], d[6], d[11], d[9], d[7]]),
+                            device: d[12],
+                        });
+                    }
+                    offset += 2 + raw[offset + 1] as usize;
+                }
+                None
+            }
+            Some(0x70) | Some(0x71) if raw.len() >= 12 => {
+                // the upper bytes don't fit into the fixed format
+                if byte(8) & 0x60 != 0 {
+                    return None;
+                }
+                Some(AtaStatus {
+                    error: raw[3],
+                    status: raw[4],
+                    count: raw[6] as u16,
+                    lba: byte(9) | (byte(10) << 8) | (byte(11) << 16),
+                    device: raw[5],
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_pass_through_cdb() {
+        let cmd = AtaCommand {
+            command: 0x27,
+            lba: 0x0000_1234_5678_9abc,
+            device: 0x40,
+            extended: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            [0x85, 0x07, 0x20, 0, 0, 0, 0, 0x56, 0xbc, 0x34, 0x9a, 0x12, 0x78, 0x40, 0x27, 0],
+            cmd.pass_through_cdb(AtaProtocol::NonData, true)
+        );
+    }
+
+    #[test]
+    fn test_status_from_descriptor_sense() {
+        let raw = vec![
+            0x72, 0x01, 0x00, 0x1d, 0, 0, 0, 14, // header
+            0x09, 0x0c, 0x01, 0x00, 0x00, 0x00, 0x56, 0xbc, 0x34, 0x9a, 0x12, 0x78, 0x40, 0x50,
+        ];
+        let sense = Sense::parse(&raw).unwrap();
+        assert_eq!(
+            Some(AtaStatus {
+                error: 0,
+                status: 0x50,
+                count: 0,
+                lba: 0x0000_1234_5678_9abc,
+                device: 0x40,
+            }),
+            AtaStatus::from_sense(&sense)
+        );
+    }
+
+    #[test]
+    fn test_trusted_receive() {
+        let cmd = AtaCommand::trusted(false, 0x01, 0x07fe, 2048);
+        assert_eq!(
+            [0x85, 0x08, 0x0e, 0, 0x01, 0, 0x04, 0, 0x00, 0, 0xfe, 0, 0x07, 0, 0x5c, 0],
+            cmd.pass_through_cdb(AtaProtocol::PioIn, false)
+        );
+    }
+}
@@ -0,0 +1,181 @@
+use crate::storage::*;
+use anyhow::Result;
+
+/// Contiguous range of bytes on a storage.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Extent {
+    pub start: u64,
+    pub length: u64,
+}
+
+impl Extent {
+    pub fn end(&self) -> u64 {
+        self.start + self.length
+    }
+}
+
+/// Presents a set of extents of the underlying storage as one continuous storage,
+/// so the wipe engine can process only some parts of a device.
+/// I/O crossing the extent boundaries is split between the extents.
+pub struct ExtentsAccess {
+    inner: Box<dyn StorageAccess>,
+    extents: Vec<Extent>,
+    position: u64,
+}
+
+impl ExtentsAccess {
+    pub fn new(inner: Box<dyn StorageAccess>, extents: Vec<Extent>) -> ExtentsAccess {
+        ExtentsAccess {
+            inner,
+            extents: extents.into_iter().filter(|e| e.length > 0).collect(),
+            position: 0,
+        }
+    }
+
+    /// Total length of all extents, the size of the storage as seen by the wipe engine.
+    pub fn total_size(&self) -> u64 {
+        self.extents.iter().map(|e| e.length).sum()
+    }
+
+    /// Maps the current position to the underlying storage,
+    /// returns the physical position and the number of bytes left in the extent.
+    fn locate(&self) -> Option<(u64, u64)> {
+        let mut offset = self.position;
+        for extent in &self.extents {
+            if offset < extent.length {
+                return Some((extent.start + offset, extent.length - offset));
+            }
+            offset -= extent.length;
+        }
+        None
+    }
+}
+
+impl StorageAccess for ExtentsAccess {
+    fn position(&mut self) -> Result<u64> {
+        Ok(self.position)
+    }
+
+    fn seek(&mut self, position: u64) -> Result<u64> {
+        self.position = position;
+        Ok(position)
+    }
+
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize> {
+        match self.locate() {
+            Some((physical, left)) => {
+                let length = buffer.len().min(left as usize);
+                self.inner.seek(physical)?;
+                let read = self.inner.read(&mut buffer[..length])?;
+                self.position += read as u64;
+                Ok(read)
+            }
+            None => Ok(0),
+        }
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<()> {
+        if self.position + data.len() as u64 > self.total_size() {
+            Err(anyhow!("Writing beyond the end of the extents"))?;
+        }
+
+        let mut written = 0;
+        while let Some((physical, left)) = self.locate().filter(|_| written < data.len()) {
+            let length = (data.len() - written).min(left as usize);
+            self.inner.seek(physical)?;
+            self.inner.write(&data[written..written + length])?;
+            written += length;
+            self.position += length as u64;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_support::MemoryStorage;
+    use std::cell::RefCell;
+    use std::io::Cursor;
+    use std::rc::Rc;
+
+    struct SharedStorage(Rc<RefCell<MemoryStorage>>);
+
+    impl StorageAccess for SharedStorage {
+        fn position(&mut self) -> Result<u64> {
+            self.0.borrow_mut().position()
+        }
+
+        fn seek(&mut self, position: u64) -> Result<u64> {
+            self.0.borrow_mut().seek(position)
+        }
+
+        fn read(&mut self, buffer: &mut [u8]) -> Result<usize> {
+            self.0.borrow_mut().read(buffer)
+        }
+
+        fn write(&mut self, data: &[u8]) -> Result<()> {
+            self.0.borrow_mut().write(data)
+        }
+
+        fn flush(&mut self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    fn extents() -> Vec<Extent> {
+        vec![
+            Extent {
+                start: 10,
+                length: 20,
+            },
+            Extent {
+                start: 50,
+                length: 0,
+            },
+            Extent {
+                start: 60,
+                length: 30,
+            },
+        ]
+    }
+
+    #[test]
+    fn test_writing_across_extents() {
+        let storage = Rc::new(RefCell::new(MemoryStorage(Cursor::new(vec![0u8; 100]))));
+        let mut access = ExtentsAccess::new(Box::new(SharedStorage(storage.clone())), extents());
+
+        assert_eq!(50, access.total_size());
+
+        access.seek(5).unwrap();
+        access.write(&[1u8; 40]).unwrap();
+        assert_eq!(45, access.position().unwrap());
+        assert!(access.write(&[1u8; 10]).is_err());
+
+        let data = storage.borrow().0.get_ref().clone();
+        assert!(data[..15].iter().all(|b| *b == 0));
+        assert!(data[15..30].iter().all(|b| *b == 1));
+        assert!(data[30..60].iter().all(|b| *b == 0));
+        assert!(data[60..85].iter().all(|b| *b == 1));
+        assert!(data[85..].iter().all(|b| *b == 0));
+    }
+
+    #[test]
+    fn test_reading_across_extents() {
+        let content: Vec<u8> = (0..100).collect();
+        let storage = Rc::new(RefCell::new(MemoryStorage(Cursor::new(content))));
+        let mut access = ExtentsAccess::new(Box::new(SharedStorage(storage)), extents());
+
+        let mut buffer = [0u8; 40];
+        access.seek(15).unwrap();
+        assert_eq!(5, access.read(&mut buffer).unwrap());
+        assert_eq!(&[25, 26, 27, 28, 29], &buffer[..5]);
+        assert_eq!(30, access.read(&mut buffer).unwrap());
+        assert_eq!(60, buffer[0]);
+        assert_eq!(0, access.read(&mut buffer).unwrap());
+    }
+}
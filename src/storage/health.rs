@@ -0,0 +1,266 @@
+use crate::storage::{
+    get_log_page, AtaCommand, AtaProtocol, NvmeTransport, ScsiTransport, StorageDevice, StorageRef,
+    ATA_SECTOR_SIZE,
+};
+use anyhow::{Context, Result};
+use std::convert::TryInto;
+use std::time::Duration;
+
+const ATA_SMART: u8 = 0xb0;
+const SMART_READ_DATA: u16 = 0xd0;
+const SMART_RETURN_STATUS: u16 = 0xda;
+/// LBA mid and high registers identifying the SMART commands.
+const SMART_SIGNATURE: u64 = 0xc2_4f00;
+const SMART_THRESHOLD_EXCEEDED: u64 = 0x2c_f400;
+const SMART_ATTRIBUTES_OFFSET: usize = 2;
+const SMART_ATTRIBUTE_SIZE: usize = 12;
+const SMART_ATTRIBUTES: usize = 30;
+
+const SMART_REALLOCATED_SECTORS: u8 = 5;
+const SMART_POWER_ON_HOURS: u8 = 9;
+const SMART_TEMPERATURE: u8 = 194;
+const SMART_PENDING_SECTORS: u8 = 197;
+const SMART_OFFLINE_UNCORRECTABLE: u8 = 198;
+
+const NVME_LOG_HEALTH: u8 = 0x02;
+const NVME_LOG_HEALTH_SIZE: usize = 512;
+const KELVIN_OFFSET: i64 = 273;
+
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HealthSource {
+    AtaSmart,
+    NvmeHealthLog,
+}
+
+/// Vendor specific attribute from the ATA SMART data.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SmartAttribute {
+    pub id: u8,
+    pub current: u8,
+    pub worst: u8,
+    pub raw: u64,
+}
+
+/// Snapshot of the drive health counters. Counters not reported by the drive are `None`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HealthReport {
+    pub source: HealthSource,
+    /// Overall assessment of the drive itself.
+    pub passed: Option<bool>,
+    pub power_on_hours: Option<u64>,
+    pub temperature: Option<i64>,
+    pub reallocated_sectors: Option<u64>,
+    pub pending_sectors: Option<u64>,
+    pub uncorrectable_sectors: Option<u64>,
+    pub media_errors: Option<u64>,
+    /// Estimated percentage of the endurance used, may exceed 100.
+    pub percentage_used: Option<u8>,
+    pub attributes: Vec<SmartAttribute>,
+}
+
+impl HealthReport {
+    /// Reads the NVMe health log or the ATA SMART data, depending on the device.
+    pub fn read(storage: &StorageRef) -> Result<HealthReport> {
+        match storage.nvme_transport() {
+            Ok(mut transport) => HealthReport::read_nvme(transport.as_mut()),
+            Err(_) => HealthReport::read_ata(storage.scsi_transport_read_only()?.as_mut()),
+        }
+    }
+
+    pub fn read_ata(transport: &mut dyn ScsiTransport) -> Result<HealthReport> {
+        let mut data = vec![0u8; ATA_SECTOR_SIZE];
+        AtaCommand {
+            command: ATA_SMART,
+            features: SMART_READ_DATA,
+            count: 1,
+            lba: SMART_SIGNATURE,
+            ..Default::default()
+        }
+        .execute(transport, AtaProtocol::PioIn, &mut data, COMMAND_TIMEOUT)
+        .context("Unable to read SMART data")?;
+
+        let status = AtaCommand {
+            command: ATA_SMART,
+            features: SMART_RETURN_STATUS,
+            lba: SMART_SIGNATURE,
+            ..Default::default()
+        }
+        .execute_with_status(transport, COMMAND_TIMEOUT);
+        let passed = match status {
+            Ok(s) if s.lba & 0xff_ff00 == SMART_THRESHOLD_EXCEEDED => Some(false),
+            Ok(s) if s.lba & 0xff_ff00 == SMART_SIGNATURE => Some(true),
+            _ => None,
+        };
+
+        Ok(HealthReport::parse_ata(&data, passed))
+    }
+
+    pub fn read_nvme(transport: &mut dyn NvmeTransport) -> Result<HealthReport> {
+        let mut data = vec![0u8; NVME_LOG_HEALTH_SIZE];
+        get_log_page(transport, NVME_LOG_HEALTH, &mut data)
+            .context("Unable to read the NVMe health log")?;
+        Ok(HealthReport::parse_nvme(&data))
+    }
+
+    pub fn parse_ata(data: &[u8], passed: Option<bool>) -> HealthReport {
+        let attributes: Vec<SmartAttribute> = data[SMART_ATTRIBUTES_OFFSET
+            ..SMART_ATTRIBUTES_OFFSET + SMART_ATTRIBUTES * SMART_ATTRIBUTE_SIZE]
+            .chunks(SMART_ATTRIBUTE_SIZE)
+            .filter(|a| a[0] != 0)
+            .map(|a| SmartAttribute {
+                id: a[0],
+                current: a[3],
+                worst: a[4],
+                raw: a[5..11]
+                    .iter()
+                    .rev()
+                    .fold(0u64, |acc, b| (acc << 8) | *b as u64),
+            })
+            .collect();
+        let raw = |id: u8| attributes.iter().find(|a| a.id == id).map(|a| a.raw);
+
+        HealthReport {
+            source: HealthSource::AtaSmart,
+            passed,
+            // some drives keep minutes or milliseconds in the upper bytes
+            power_on_hours: raw(SMART_POWER_ON_HOURS).map(|r| r & 0xffff_ffff),
+            // the upper bytes hold the lowest and highest temperatures
+            temperature: raw(SMART_TEMPERATURE).map(|r| (r & 0xff) as i64),
+            reallocated_sectors: raw(SMART_REALLOCATED_SECTORS),
+            pending_sectors: raw(SMART_PENDING_SECTORS),
+            uncorrectable_sectors: raw(SMART_OFFLINE_UNCORRECTABLE),
+            media_errors: None,
+            percentage_used: None,
+            attributes,
+        }
+    }
+
+    pub fn parse_nvme(data: &[u8]) -> HealthReport {
+        // 128-bit counters, the upper half is never reached in practice
+        let counter =
+            |offset: usize| u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap());
+
+        HealthReport {
+            source: HealthSource::NvmeHealthLog,
+            passed: Some(data[0] == 0),
+            power_on_hours: Some(counter(128)),
+            temperature: Some(u16::from_le_bytes([data[1], data[2]]) as i64 - KELVIN_OFFSET),
+            reallocated_sectors: None,
+            pending_sectors: None,
+            uncorrectable_sectors: None,
+            media_errors: Some(counter(160)),
+            percentage_used: Some(data[5]),
+            attributes: vec![],
+        }
+    }
+
+    /// Error counters which increased since the earlier report, with their old and new values.
+    pub fn degraded_since(&self, before: &HealthReport) -> Vec<(&'static str, u64, u64)> {
+        let counters = |r: &HealthReport| {
+            vec![
+                ("Reallocated sectors", r.reallocated_sectors),
+                ("Pending sectors", r.pending_sectors),
+                ("Uncorrectable sectors", r.uncorrectable_sectors),
+                ("Media errors", r.media_errors),
+            ]
+        };
+
+        counters(before)
+            .into_iter()
+            .zip(counters(self))
+            .filter_map(|((name, old), (_, new))| match (old, new) {
+                (Some(old), Some(new)) if new > old => Some((name, old, new)),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn hex(dump: &str, size: usize) -> Vec<u8> {
+        let mut data: Vec<u8> = dump
+            .split_whitespace()
+            .map(|b| u8::from_str_radix(b, 16).unwrap())
+            .collect();
+        data.resize(size, 0);
+        data
+    }
+
+    /// SMART data of a SATA SSD with a few reallocated and pending sectors.
+    const ATA_SMART_DUMP: &str = "
+        10 00 05 33 00 64 64 03 00 00 00 00 00 00 09 32
+        00 60 60 39 30 00 00 00 00 00 0c 32 00 63 63 c2
+        01 00 00 00 00 00 b1 13 00 62 62 2a 00 00 00 00
+        00 00 bb 32 00 64 64 00 00 00 00 00 00 00 c2 22
+        00 43 34 21 00 14 00 30 00 00 c5 32 00 64 64 02
+        00 00 00 00 00 00 c6 30 00 64 64 01 00 00 00 00
+        00 00 c7 3e 00 64 64 00 00 00 00 00 00 00";
+
+    /// NVMe SMART/health log of a drive at 44 degrees with 3% of the endurance used.
+    const NVME_HEALTH_DUMP: &str = "
+        00 3d 01 64 0a 03 00 00 00 00 00 00 00 00 00 00
+        00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00
+        a1 86 01 00 00 00 00 00 00 00 00 00 00 00 00 00
+        40 0d 03 00 00 00 00 00 00 00 00 00 00 00 00 00
+        00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00
+        00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00
+        00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00
+        52 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00
+        fc 08 00 00 00 00 00 00 00 00 00 00 00 00 00 00
+        11 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00
+        02 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00";
+
+    #[test]
+    fn test_parsing_ata_smart() {
+        let report = HealthReport::parse_ata(&hex(ATA_SMART_DUMP, 512), Some(true));
+
+        assert_eq!(HealthSource::AtaSmart, report.source);
+        assert_eq!(9, report.attributes.len());
+        assert_eq!(Some(12345), report.power_on_hours);
+        assert_eq!(Some(33), report.temperature);
+        assert_eq!(Some(3), report.reallocated_sectors);
+        assert_eq!(Some(2), report.pending_sectors);
+        assert_eq!(Some(1), report.uncorrectable_sectors);
+        assert_eq!(None, report.media_errors);
+        assert_eq!(
+            SmartAttribute {
+                id: 194,
+                current: 0x43,
+                worst: 0x34,
+                raw: 0x30_0014_0021,
+            },
+            report.attributes[5]
+        );
+    }
+
+    #[test]
+    fn test_parsing_nvme_health_log() {
+        let report = HealthReport::parse_nvme(&hex(NVME_HEALTH_DUMP, 512));
+
+        assert_eq!(Some(true), report.passed);
+        assert_eq!(Some(44), report.temperature);
+        assert_eq!(Some(3), report.percentage_used);
+        assert_eq!(Some(0x8fc), report.power_on_hours);
+        assert_eq!(Some(2), report.media_errors);
+        assert_eq!(None, report.reallocated_sectors);
+    }
+
+    #[test]
+    fn test_degraded_counters() {
+        let before = HealthReport::parse_ata(&hex(ATA_SMART_DUMP, 512), Some(true));
+        let mut after = before.clone();
+        assert!(after.degraded_since(&before).is_empty());
+
+        after.reallocated_sectors = Some(10);
+        after.pending_sectors = Some(0);
+        assert_eq!(
+            vec![("Reallocated sectors", 3, 10)],
+            after.degraded_since(&before)
+        );
+    }
+}
@@ -0,0 +1,305 @@
+use crate::storage::{
+    AtaCommand, AtaProtocol, ScsiTransport, StorageDevice, StorageRef, ATA_SECTOR_SIZE,
+};
+use anyhow::{Context, Result};
+use std::time::Duration;
+
+const ATA_IDENTIFY_DEVICE: u8 = 0xec;
+const ATA_READ_NATIVE_MAX_ADDRESS: u8 = 0xf8;
+const ATA_READ_NATIVE_MAX_ADDRESS_EXT: u8 = 0x27;
+const ATA_SET_MAX_ADDRESS: u8 = 0xf9;
+const ATA_SET_MAX_ADDRESS_EXT: u8 = 0x37;
+const ATA_DEVICE_CONFIGURATION: u8 = 0xb1;
+const DCO_IDENTIFY: u16 = 0xc2;
+const LBA_MODE: u8 = 0x40;
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Sectors hidden from the host by the Host Protected Area and the Device Configuration Overlay
+/// of an ATA drive. Both areas follow the reported capacity, the HPA goes first.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HiddenAreas {
+    pub sector_size: u64,
+    /// Sectors visible to the host.
+    pub reported_sectors: u64,
+    /// Sectors up to the native max address, including the HPA.
+    pub native_sectors: u64,
+    /// Sectors available without the DCO, if the drive supports it.
+    pub factory_sectors: Option<u64>,
+    lba48: bool,
+}
+
+impl HiddenAreas {
+    /// Fails if the storage is not an ATA drive.
+    pub fn probe(storage: &StorageRef) -> Result<HiddenAreas> {
+        HiddenAreas::detect(storage.scsi_transport_read_only()?.as_mut())
+    }
+
+    pub fn detect(transport: &mut dyn ScsiTransport) -> Result<HiddenAreas> {
+        let mut identify = vec![0u8; ATA_SECTOR_SIZE];
+        AtaCommand {
+            command: ATA_IDENTIFY_DEVICE,
+            count: 1,
+            ..Default::default()
+        }
+        .execute(
+            transport,
+            AtaProtocol::PioIn,
+            &mut identify,
+            COMMAND_TIMEOUT,
+        )
+        .context("ATA IDENTIFY DEVICE failed")?;
+
+        let word = |n: usize| u16::from_le_bytes([identify[n * 2], identify[n * 2 + 1]]) as u64;
+        let lba48 = word(83) & (1 << 10) != 0;
+        let hpa_supported = word(82) & (1 << 10) != 0;
+        let dco_supported = word(83) & (1 << 11) != 0;
+        let reported_sectors = if lba48 {
+            word(100) | (word(101) << 16) | (word(102) << 32) | (word(103) << 48)
+        } else {
+            word(60) | (word(61) << 16)
+        };
+        // logical sector size is valid when bit 14 is set and bit 15 is cleared
+        let sector_size = match word(106) {
+            w if w & 0xc000 == 0x4000 && w & (1 << 12) != 0 => (word(117) | (word(118) << 16)) * 2,
+            _ => ATA_SECTOR_SIZE as u64,
+        };
+
+        let native_sectors = if hpa_supported {
+            read_native_max_address(transport, lba48)? + 1
+        } else {
+            reported_sectors
+        };
+
+        // drives with a frozen or disabled DCO abort the command
+        let factory_sectors = if dco_supported {
+            device_configuration_identify(transport).ok()
+        } else {
+            None
+        };
+
+        Ok(HiddenAreas {
+            sector_size,
+            reported_sectors,
+            native_sectors,
+            factory_sectors,
+            lba48,
+        })
+    }
+
+    pub fn hpa_size(&self) -> u64 {
+        self.native_sectors.saturating_sub(self.reported_sectors) * self.sector_size
+    }
+
+    pub fn dco_size(&self) -> u64 {
+        self.factory_sectors
+            .map(|s| s.saturating_sub(self.native_sectors) * self.sector_size)
+            .unwrap_or(0)
+    }
+
+    /// Capacity of the drive with the HPA removed.
+    pub fn native_size(&self) -> u64 {
+        self.native_sectors * self.sector_size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hpa_size() == 0 && self.dco_size() == 0
+    }
+
+    /// Removes the HPA until the drive is power cycled, making the whole native capacity
+    /// accessible. The DCO can only be removed permanently, so it's left intact.
+    pub fn unlock(&self, transport: &mut dyn ScsiTransport) -> Result<()> {
+        if self.hpa_size() == 0 {
+            return Ok(());
+        }
+
+        // SET MAX ADDRESS is only accepted right after READ NATIVE MAX ADDRESS
+        let max_address = read_native_max_address(transport, self.lba48)?;
+        let command = if self.lba48 {
+            AtaCommand {
+                command: ATA_SET_MAX_ADDRESS_EXT,
+                lba: max_address,
+                device: LBA_MODE,
+                extended: true,
+                ..Default::default()
+            }
+        } else {
+            AtaCommand {
+                command: ATA_SET_MAX_ADDRESS,
+                lba: max_address & 0xff_ffff,
+                device: LBA_MODE | ((max_address >> 24) & 0x0f) as u8,
+                ..Default::default()
+            }
+        };
+        // the count register is zero, so the new address is volatile
+        command
+            .execute_with_status(transport, COMMAND_TIMEOUT)
+            .context("Unable to remove the Host Protected Area")?;
+        Ok(())
+    }
+}
+
+fn read_native_max_address(transport: &mut dyn ScsiTransport, lba48: bool) -> Result<u64> {
+    let command = AtaCommand {
+        command: if lba48 {
+            ATA_READ_NATIVE_MAX_ADDRESS_EXT
+        } else {
+            ATA_READ_NATIVE_MAX_ADDRESS
+        },
+        device: LBA_MODE,
+        extended: lba48,
+        ..Default::default()
+    };
+    let status = command
+        .execute_with_status(transport, COMMAND_TIMEOUT)
+        .context("ATA READ NATIVE MAX ADDRESS failed")?;
+
+    Ok(if lba48 {
+        status.lba
+    } else {
+        (status.lba & 0xff_ffff) | (((status.device & 0x0f) as u64) << 24)
+    })
+}
+
+/// Number of sectors reported by DEVICE CONFIGURATION IDENTIFY.
+fn device_configuration_identify(transport: &mut dyn ScsiTransport) -> Result<u64> {
+    let mut data = vec![0u8; ATA_SECTOR_SIZE];
+    AtaCommand {
+        command: ATA_DEVICE_CONFIGURATION,
+        features: DCO_IDENTIFY,
+        count: 1,
+        ..Default::default()
+    }
+    .execute(transport, AtaProtocol::PioIn, &mut data, COMMAND_TIMEOUT)?;
+
+    let max_address = (3..7).rev().fold(0u64, |acc, w| {
+        (acc << 16) | u16::from_le_bytes([data[w * 2], data[w * 2 + 1]]) as u64
+    });
+    Ok(max_address + 1)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::storage::{DataDirection, ScsiError, Sense};
+    use std::collections::VecDeque;
+
+    /// Answers every command with the next recorded response,
+    /// either the transferred data or the returned ATA registers.
+    struct MockTransport {
+        sent: Vec<Vec<u8>>,
+        responses: VecDeque<std::result::Result<Vec<u8>, u64>>,
+    }
+
+    impl ScsiTransport for MockTransport {
+        fn execute(
+            &mut self,
+            cdb: &[u8],
+            _direction: DataDirection,
+            data: &mut [u8],
+            _timeout: Duration,
+        ) -> Result<()> {
+            self.sent.push(cdb.to_vec());
+            match self.responses.pop_front().expect("unexpected command") {
+                Ok(response) => {
+                    data[..response.len()].copy_from_slice(&response);
+                    Ok(())
+                }
+                Err(lba) => {
+                    let mut raw = vec![0x72, 0x01, 0x00, 0x1d, 0, 0, 0, 14, 0x09, 0x0c];
+                    let b = lba.to_be_bytes();
+                    raw.extend_from_slice(&[1, 0, 0, 0, b[4], b[7], b[3], b[6], b[2], b[5]]);
+                    raw.extend_from_slice(&[LBA_MODE, 0x50]);
+                    Err(ScsiError::CheckCondition(Sense::parse(&raw).unwrap()).into())
+                }
+            }
+        }
+    }
+
+    fn identify(sectors: u64, hpa: bool, dco: bool) -> Vec<u8> {
+        let mut data = vec![0u8; ATA_SECTOR_SIZE];
+        let mut set_word =
+            |n: usize, value: u16| data[n * 2..n * 2 + 2].copy_from_slice(&value.to_le_bytes());
+        set_word(82, if hpa { 1 << 10 } else { 0 });
+        set_word(83, (1 << 10) | if dco { 1 << 11 } else { 0 });
+        for i in 0..4 {
+            set_word(100 + i, (sectors >> (16 * i)) as u16);
+        }
+        data
+    }
+
+    fn dco_identify(max_address: u64) -> Vec<u8> {
+        let mut data = vec![0u8; ATA_SECTOR_SIZE];
+        for i in 0..4 {
+            let w = ((max_address >> (16 * i)) as u16).to_le_bytes();
+            data[6 + i * 2..8 + i * 2].copy_from_slice(&w);
+        }
+        data
+    }
+
+    #[test]
+    fn test_detecting_hidden_areas() {
+        let mut transport = MockTransport {
+            sent: vec![],
+            responses: vec![
+                Ok(identify(1_000_000, true, true)),
+                Err(1_099_999),
+                Ok(dco_identify(1_199_999)),
+            ]
+            .into(),
+        };
+
+        let hidden = HiddenAreas::detect(&mut transport).unwrap();
+        assert_eq!(100_000 * 512, hidden.hpa_size());
+        assert_eq!(100_000 * 512, hidden.dco_size());
+        assert_eq!(1_100_000 * 512, hidden.native_size());
+        assert!(!hidden.is_empty());
+        assert_eq!(ATA_READ_NATIVE_MAX_ADDRESS_EXT, transport.sent[1][14]);
+        assert_eq!(0x20, transport.sent[1][2] & 0x20);
+    }
+
+    #[test]
+    fn test_no_hidden_areas() {
+        let mut transport = MockTransport {
+            sent: vec![],
+            responses: vec![Ok(identify(1_000_000, false, false))].into(),
+        };
+
+        let hidden = HiddenAreas::detect(&mut transport).unwrap();
+        assert!(hidden.is_empty());
+        assert_eq!(1, transport.sent.len());
+
+        // nothing to unlock
+        hidden.unlock(&mut transport).unwrap();
+        assert_eq!(1, transport.sent.len());
+    }
+
+    #[test]
+    fn test_unlocking_hpa() {
+        let mut transport = MockTransport {
+            sent: vec![],
+            responses: vec![
+                Ok(identify(1_000_000, true, false)),
+                Err(1_099_999),
+                Err(1_099_999),
+                Err(1_099_999),
+            ]
+            .into(),
+        };
+
+        let hidden = HiddenAreas::detect(&mut transport).unwrap();
+        hidden.unlock(&mut transport).unwrap();
+
+        let set_max = &transport.sent[3];
+        assert_eq!(ATA_SET_MAX_ADDRESS_EXT, set_max[14]);
+        // volatile, the count register is zero
+        assert_eq!(&[0, 0], &set_max[5..7]);
+        assert_eq!(
+            &AtaCommand {
+                lba: 1_099_999,
+                ..Default::default()
+            }
+            .pass_through_cdb(AtaProtocol::NonData, true)[7..13],
+            &set_max[7..13]
+        );
+    }
+}
@@ -0,0 +1,251 @@
+use crate::storage::*;
+use anyhow::{Context, Result};
+use std::fs::{File, OpenOptions};
+use std::io::prelude::*;
+use std::io::SeekFrom;
+use std::path::Path;
+
+/// Sector size assumed for image files, the most common one for virtual disks.
+pub const IMAGE_SECTOR_SIZE: usize = 512;
+
+/// Alignment required for unbuffered I/O on most filesystems.
+#[cfg(unix)]
+const DIRECT_IO_ALIGNMENT: u64 = 4096;
+
+impl StorageRef {
+    /// Describes a regular file (e.g. a VM disk image or a loop device backing file) as a wipe target.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<StorageRef> {
+        let path = path.as_ref();
+        let metadata =
+            std::fs::metadata(path).context(format!("Unable to access file {}", path.display()))?;
+
+        if !metadata.is_file() {
+            Err(anyhow!("{} is not a regular file", path.display()))?;
+        }
+
+        Ok(StorageRef {
+            id: path.to_string_lossy().into_owned(),
+            details: StorageDetails {
+                size: metadata.len(),
+                block_size: IMAGE_SECTOR_SIZE,
+                storage_type: StorageType::File,
+                ..Default::default()
+            },
+            children: vec![],
+        })
+    }
+}
+
+/// Access to a regular file, the data is synced to the disk on every flush.
+/// The OS cache is not bypassed: O_DIRECT requires the I/O to be aligned to the logical
+/// block size of the underlying device, which can be larger than the sectors of the image.
+#[derive(Debug)]
+pub struct ImageFileAccess {
+    file: File,
+}
+
+impl ImageFileAccess {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<ImageFileAccess> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(path.as_ref())
+            .context(format!(
+                "Unable to open file {}",
+                path.as_ref().to_str().unwrap_or("?")
+            ))?;
+
+        Ok(ImageFileAccess { file })
+    }
+
+    /// Releases the disk space occupied by the file, keeping its size.
+    /// The file reads as zeroes afterwards.
+    #[cfg(target_os = "linux")]
+    pub fn deallocate(&mut self) -> Result<()> {
+        use std::os::unix::io::AsRawFd;
+
+        let size = self.file.metadata()?.len();
+        let mode = libc::FALLOC_FL_PUNCH_HOLE | libc::FALLOC_FL_KEEP_SIZE;
+        if unsafe { libc::fallocate(self.file.as_raw_fd(), mode, 0, size as libc::off_t) } != 0 {
+            Err(std::io::Error::last_os_error()).context("Unable to punch a hole in the file")?;
+        }
+        Ok(())
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn deallocate(&mut self) -> Result<()> {
+        Err(anyhow!(
+            "Deallocating files is not supported on this platform"
+        ))
+    }
+}
+
+/// Read-only access to a device or a file, used to look at the content before wiping.
+/// Writing always fails.
+pub(crate) struct ReadOnlyFileAccess {
+    file: File,
+    pub sector_size: usize,
+}
+
+impl ReadOnlyFileAccess {
+    pub(crate) fn open(storage: &StorageRef) -> Result<ReadOnlyFileAccess> {
+        let file = File::open(&storage.id).context(format!("Unable to open {}", storage.id))?;
+        Ok(ReadOnlyFileAccess {
+            file,
+            sector_size: sector_size_of(storage),
+        })
+    }
+
+    /// Same as `open`, but bypassing the OS cache like `FileAccess` does,
+    /// so the media itself is read instead of the cached data. Image files are read
+    /// through the cache if they are incomplete for O_DIRECT or their filesystem rejects it.
+    pub(crate) fn open_direct(storage: &StorageRef) -> Result<ReadOnlyFileAccess> {
+        #[cfg(unix)]
+        {
+            let is_file = matches!(storage.details.storage_type, StorageType::File);
+            let file = if !is_file {
+                Some(open_file_direct(&storage.id, false)?)
+            } else if storage.details.size.is_multiple_of(DIRECT_IO_ALIGNMENT) {
+                open_file_direct(&storage.id, false).ok()
+            } else {
+                None
+            };
+
+            if let Some(file) = file {
+                return Ok(ReadOnlyFileAccess {
+                    file,
+                    sector_size: sector_size_of(storage),
+                });
+            }
+        }
+
+        ReadOnlyFileAccess::open(storage)
+    }
+}
+
+fn sector_size_of(storage: &StorageRef) -> usize {
+    match storage.details.block_size {
+        0 => IMAGE_SECTOR_SIZE,
+        s => s,
+    }
+}
+
+impl StorageAccess for ReadOnlyFileAccess {
+    fn position(&mut self) -> Result<u64> {
+        Ok(self.file.stream_position()?)
+    }
+
+    fn seek(&mut self, position: u64) -> Result<u64> {
+        Ok(self.file.seek(SeekFrom::Start(position))?)
+    }
+
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize> {
+        self.file
+            .read(buffer)
+            .map_err(StorageError::from)
+            .context("Can't read from the storage")
+    }
+
+    fn write(&mut self, _data: &[u8]) -> Result<()> {
+        Err(anyhow!("The storage is opened read-only"))
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl StorageAccess for ImageFileAccess {
+    fn position(&mut self) -> Result<u64> {
+        self.file
+            .stream_position()
+            .map_err(StorageError::from)
+            .context("Seek failed or not supported for the file")
+    }
+
+    fn seek(&mut self, position: u64) -> Result<u64> {
+        self.file
+            .seek(SeekFrom::Start(position))
+            .map_err(StorageError::from)
+            .context("Seek failed or not supported for the file")
+    }
+
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize> {
+        self.file
+            .read(buffer)
+            .map_err(StorageError::from)
+            .context("Can't read from the file")
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<()> {
+        self.file
+            .write_all(data)
+            .map_err(StorageError::from)
+            .context("Writing to file failed")
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.file.flush().map_err(StorageError::from)?;
+        self.file.sync_data().map_err(StorageError::from)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::sanitization::mem::AlignedBuffer;
+    use crate::test_support::TempDir;
+
+    #[test]
+    fn test_image_file_details() {
+        let dir = TempDir::new("image-details");
+        let path = dir.file("image", &[1u8; 10000]);
+
+        let storage = StorageRef::from_file(&path).unwrap();
+        assert_eq!(10000, storage.details.size);
+        assert_eq!(IMAGE_SECTOR_SIZE, storage.details.block_size);
+        assert!(matches!(storage.details.storage_type, StorageType::File));
+
+        assert!(StorageRef::from_file(dir.path()).is_err());
+        assert!(StorageRef::from_file(path.with_extension("missing")).is_err());
+    }
+
+    #[test]
+    fn test_image_file_access() {
+        for size in &[8192, 10000] {
+            let dir = TempDir::new(&format!("image-access-{}", size));
+            let path = dir.file("image", &vec![1u8; *size]);
+            let mut access = ImageFileAccess::open(&path).unwrap();
+
+            let buf = AlignedBuffer::new(4096, 4096);
+            buf.as_mut_slice().fill(2);
+
+            access.seek(4096).unwrap();
+            access.write(buf.as_mut_slice()).unwrap();
+            access.flush().unwrap();
+            assert_eq!(8192, access.position().unwrap());
+
+            let content = std::fs::read(&path).unwrap();
+            assert_eq!(*size, content.len());
+            assert!(content[..4096].iter().all(|b| *b == 1));
+            assert!(content[4096..8192].iter().all(|b| *b == 2));
+            assert!(content[8192..].iter().all(|b| *b == 1));
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_image_file_deallocate() {
+        let dir = TempDir::new("image-deallocate");
+        let path = dir.file("image", &[1u8; 10000]);
+        let mut access = ImageFileAccess::open(&path).unwrap();
+
+        // not every filesystem supports punching holes
+        if access.deallocate().is_ok() {
+            let content = std::fs::read(&path).unwrap();
+            assert_eq!(10000, content.len());
+            assert!(content.iter().all(|b| *b == 0));
+        }
+    }
+}
@@ -8,6 +8,36 @@ mod windows;
 #[cfg(windows)]
 pub use windows::*;
 
+mod image;
+pub use image::*;
+
+mod extents;
+pub use extents::*;
+
+mod partitions;
+pub use partitions::*;
+
+mod probe;
+pub use probe::*;
+
+mod scsi;
+pub use scsi::*;
+
+mod ata;
+pub use ata::*;
+
+mod opal;
+pub use opal::*;
+
+mod hpa;
+pub use hpa::*;
+
+mod nvme;
+pub use nvme::*;
+
+mod health;
+pub use health::*;
+
 use anyhow::Result;
 use thiserror::Error;
 
@@ -15,11 +45,13 @@ use thiserror::Error;
 pub enum StorageError {
     #[error("bad block")]
     BadBlock,
+    #[error("i/o timeout")]
+    Timeout,
     #[error("other i/o error")]
     Other(#[from] std::io::Error),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct StorageRef {
     pub id: String,
     pub details: StorageDetails,
@@ -28,12 +60,27 @@ pub struct StorageRef {
 
 pub trait StorageDevice {
     fn access(&self) -> Result<Box<dyn StorageAccess>>;
+    /// Opens the storage for writing while keeping its partitions mounted.
+    /// Only safe when writing outside of the partitions.
+    fn access_shared(&self) -> Result<Box<dyn StorageAccess>>;
+    /// Opens the device for sending commands directly to it, bypassing the block layer.
+    fn scsi_transport(&self) -> Result<Box<dyn ScsiTransport>>;
+    /// Same as `scsi_transport`, but opened read-only, for commands only querying the device.
+    fn scsi_transport_read_only(&self) -> Result<Box<dyn ScsiTransport>>;
+    /// Opens the device for sending NVMe admin commands, fails for other kinds of devices.
+    fn nvme_transport(&self) -> Result<Box<dyn NvmeTransport>>;
 }
 
+/// Low level access to a storage, opened for writing.
+/// Implement this trait to wipe a storage not covered by the built-in enumerators.
+/// Errors wrapping `StorageError::BadBlock` are treated as localized failures, the affected
+/// sectors are skipped instead of failing the wipe. `StorageError::Timeout` stops the wipe
+/// with `WipeError::IoTimeout`, keeping the sectors for a later attempt.
 pub trait StorageAccess {
     fn position(&mut self) -> Result<u64>;
     fn seek(&mut self, position: u64) -> Result<u64>;
     fn read(&mut self, buffer: &mut [u8]) -> Result<usize>;
+    /// Writes the whole `data`, buffers are aligned to the sector size.
     fn write(&mut self, data: &[u8]) -> Result<()>;
     fn flush(&mut self) -> Result<()>;
 }
@@ -58,6 +105,22 @@ impl std::fmt::Display for StorageType {
     }
 }
 
+/// I/O scheduling class for the wiping process, used to limit the impact on other processes.
+#[derive(Clone, Copy, Debug)]
+pub enum IoClass {
+    /// Only access the storage when no other process needs it.
+    Idle,
+    /// The lowest priority among the regular processes.
+    BestEffort,
+}
+
+/// Type and mount options of the filesystem containing a file.
+#[derive(Debug, Clone, Default)]
+pub struct FilesystemInfo {
+    pub fs_type: String,
+    pub options: Vec<String>,
+}
+
 #[derive(Debug, Clone)]
 pub struct StorageDetails {
     pub size: u64,
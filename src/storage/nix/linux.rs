@@ -25,6 +25,82 @@ impl System {
 
         Ok(refs)
     }
+
+    /// Sets the I/O priority of the current process.
+    /// Only respected by the I/O schedulers supporting priorities, like BFQ.
+    pub fn set_io_class(class: IoClass) -> Result<()> {
+        const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+        const IOPRIO_CLASS_SHIFT: libc::c_int = 13;
+
+        let priority = match class {
+            IoClass::Idle => 3 << IOPRIO_CLASS_SHIFT,
+            IoClass::BestEffort => (2 << IOPRIO_CLASS_SHIFT) | 7,
+        };
+
+        match unsafe { libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, 0, priority) } {
+            0 => Ok(()),
+            _ => Err(anyhow::Error::new(std::io::Error::last_os_error())
+                .context("Failed to set I/O priority")),
+        }
+    }
+
+    /// Makes the kernel read the capacity of a SCSI or ATA device again,
+    /// after it was changed with device commands.
+    pub fn rescan_capacity(device_id: &str) -> Result<()> {
+        let name = Path::new(device_id)
+            .file_name()
+            .and_then(|n| n.to_str())
+            .ok_or_else(|| anyhow!("Invalid device {}", device_id))?;
+        std::fs::write(format!("/sys/class/block/{}/device/rescan", name), "1")
+            .context(format!("Unable to rescan {}", device_id))
+    }
+
+    /// Devices stacked on top of the device, e.g. an open LUKS or LVM mapping,
+    /// named by their mapper path if they have one.
+    pub fn holders(device_id: &str) -> Result<Vec<String>> {
+        let name = Path::new(device_id)
+            .file_name()
+            .and_then(|n| n.to_str())
+            .ok_or_else(|| anyhow!("Invalid device {}", device_id))?;
+        let entries = match std::fs::read_dir(format!("/sys/class/block/{}/holders", name)) {
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+            r => r.context(format!("Unable to list holders of {}", device_id))?,
+        };
+
+        entries
+            .map(|e| {
+                let holder = e?.file_name().to_string_lossy().into_owned();
+                Ok(
+                    match std::fs::read_to_string(format!("/sys/class/block/{}/dm/name", holder)) {
+                        Ok(mapping) => format!("/dev/mapper/{}", mapping.trim()),
+                        Err(_) => format!("/dev/{}", holder),
+                    },
+                )
+            })
+            .collect()
+    }
+
+    /// Finds the filesystem containing the path using the mount table.
+    pub fn filesystem_of(path: &Path) -> Result<FilesystemInfo> {
+        let path = path.canonicalize()?;
+        let mounts = std::fs::read_to_string("/proc/mounts")?;
+        find_filesystem(&mounts, &path)
+            .ok_or_else(|| anyhow!("No mount point found for {}", path.display()))
+    }
+}
+
+fn find_filesystem(mounts: &str, path: &Path) -> Option<FilesystemInfo> {
+    mounts
+        .lines()
+        .map(|l| l.split_whitespace().collect::<Vec<_>>())
+        .filter(|parts| parts.len() >= 4)
+        .filter(|parts| path.starts_with(parts[1].replace("\\040", " ")))
+        // the last one wins if the same mount point is used more than once
+        .max_by_key(|parts| parts[1].len())
+        .map(|parts| FilesystemInfo {
+            fs_type: parts[2].to_owned(),
+            options: parts[3].split(',').map(String::from).collect(),
+        })
 }
 
 pub fn open_file_direct<P: AsRef<Path>>(file_path: P, write_access: bool) -> Result<File> {
@@ -108,9 +184,17 @@ fn build_device_info(d: &Block) -> Option<StorageRef> {
         StorageType::Fixed
     };
 
+    // partitions share the request queue of their parent device
+    let sector_size = d
+        .parent_device()
+        .as_ref()
+        .unwrap_or(d)
+        .queue_logical_block_size()
+        .unwrap_or(SYSFS_BLOCK_SIZE);
+
     let details = StorageDetails {
         size: d.size().ok()? * SYSFS_BLOCK_SIZE,
-        block_size: stat.st_blksize as usize,
+        block_size: sector_size as usize,
         storage_type,
         mount_point: resolve_mount_point(&device_path).unwrap_or(None),
         label: resolve_fs_label(&device_path).unwrap_or(None),
@@ -132,3 +216,216 @@ pub fn unmount(path: &str) -> Result<()> {
             .context("Failed to unmount a volume")),
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_find_filesystem() {
+        let mounts = "/dev/sda2 / ext4 rw,relatime 0 0\n\
+            proc /proc proc rw,nosuid 0 0\n\
+            /dev/sda3 /home ext4 rw,data=journal 0 0\n\
+            /dev/sdb1 /mnt/my\\040disk btrfs rw,ssd 0 0\n\
+            tmpfs /home tmpfs rw 0 0\n";
+
+        let fs = find_filesystem(mounts, Path::new("/usr/bin/lethe")).unwrap();
+        assert_eq!("ext4", fs.fs_type);
+        assert_eq!(vec!["rw", "relatime"], fs.options);
+
+        let fs = find_filesystem(mounts, Path::new("/mnt/my disk/file")).unwrap();
+        assert_eq!("btrfs", fs.fs_type);
+
+        let fs = find_filesystem(mounts, Path::new("/home/user")).unwrap();
+        assert_eq!("tmpfs", fs.fs_type);
+
+        let fs = find_filesystem(mounts, Path::new("/homeless")).unwrap();
+        assert_eq!("ext4", fs.fs_type);
+        assert_eq!(vec!["rw", "relatime"], fs.options);
+
+        assert!(find_filesystem("", Path::new("/")).is_none());
+    }
+}
+
+const SG_IO: libc::c_ulong = 0x2285;
+const SG_INTERFACE_ID: libc::c_int = b'S' as libc::c_int;
+const SG_DXFER_NONE: libc::c_int = -1;
+const SG_DXFER_TO_DEV: libc::c_int = -2;
+const SG_DXFER_FROM_DEV: libc::c_int = -3;
+const SG_MAX_SENSE: usize = 64;
+const SCSI_STATUS_CHECK_CONDITION: u8 = 0x02;
+const DRIVER_SENSE: u16 = 0x08;
+
+/// `struct sg_io_hdr` from `<scsi/sg.h>`.
+#[repr(C)]
+struct SgIoHdr {
+    interface_id: libc::c_int,
+    dxfer_direction: libc::c_int,
+    cmd_len: libc::c_uchar,
+    mx_sb_len: libc::c_uchar,
+    iovec_count: libc::c_ushort,
+    dxfer_len: libc::c_uint,
+    dxferp: *mut libc::c_void,
+    cmdp: *const libc::c_uchar,
+    sbp: *mut libc::c_uchar,
+    timeout: libc::c_uint,
+    flags: libc::c_uint,
+    pack_id: libc::c_int,
+    usr_ptr: *mut libc::c_void,
+    status: libc::c_uchar,
+    masked_status: libc::c_uchar,
+    msg_status: libc::c_uchar,
+    sb_len_wr: libc::c_uchar,
+    host_status: libc::c_ushort,
+    driver_status: libc::c_ushort,
+    resid: libc::c_int,
+    duration: libc::c_uint,
+    info: libc::c_uint,
+}
+
+/// Sends SCSI commands through the SG_IO ioctl, supported by all SCSI, SATA and USB block devices.
+struct SgTransport {
+    file: File,
+}
+
+impl ScsiTransport for SgTransport {
+    fn execute(
+        &mut self,
+        cdb: &[u8],
+        direction: DataDirection,
+        data: &mut [u8],
+        timeout: std::time::Duration,
+    ) -> Result<()> {
+        let mut sense = [0u8; SG_MAX_SENSE];
+        let mut hdr = SgIoHdr {
+            interface_id: SG_INTERFACE_ID,
+            dxfer_direction: match direction {
+                DataDirection::None => SG_DXFER_NONE,
+                DataDirection::ToDevice => SG_DXFER_TO_DEV,
+                DataDirection::FromDevice => SG_DXFER_FROM_DEV,
+            },
+            cmd_len: cdb.len() as u8,
+            mx_sb_len: sense.len() as u8,
+            iovec_count: 0,
+            dxfer_len: data.len() as u32,
+            dxferp: data.as_mut_ptr() as *mut libc::c_void,
+            cmdp: cdb.as_ptr(),
+            sbp: sense.as_mut_ptr(),
+            timeout: timeout.as_millis() as u32,
+            flags: 0,
+            pack_id: 0,
+            usr_ptr: std::ptr::null_mut(),
+            status: 0,
+            masked_status: 0,
+            msg_status: 0,
+            sb_len_wr: 0,
+            host_status: 0,
+            driver_status: 0,
+            resid: 0,
+            duration: 0,
+            info: 0,
+        };
+
+        let res = unsafe { libc::ioctl(self.file.as_raw_fd(), SG_IO as _, &mut hdr) };
+        if res < 0 {
+            return Err(std::io::Error::last_os_error()).context("SG_IO request failed");
+        }
+
+        let has_sense = hdr.sb_len_wr > 0
+            && (hdr.status == SCSI_STATUS_CHECK_CONDITION || hdr.driver_status & DRIVER_SENSE != 0);
+        if has_sense {
+            if let Some(sense) = Sense::parse(&sense[..hdr.sb_len_wr as usize]) {
+                Err(ScsiError::CheckCondition(sense))?;
+            }
+        }
+        if hdr.status != 0 {
+            Err(ScsiError::Status(hdr.status))?;
+        }
+        if hdr.host_status != 0 {
+            Err(anyhow!("Host adapter error {:#x}", hdr.host_status))?;
+        }
+        Ok(())
+    }
+}
+
+pub fn scsi_transport(path: &str, writable: bool) -> Result<Box<dyn ScsiTransport>> {
+    let file = OpenOptions::new()
+        .read(true)
+        .write(writable)
+        .open(path)
+        .context(format!("Unable to open device {}", path))?;
+    Ok(Box::new(SgTransport { file }))
+}
+
+/// `_IOWR('N', 0x41, struct nvme_admin_cmd)`
+const NVME_IOCTL_ADMIN_CMD: libc::c_ulong = 0xc048_4e41;
+const NVME_COMMAND_TIMEOUT_MS: u32 = 30_000;
+
+/// `struct nvme_admin_cmd` from `<linux/nvme_ioctl.h>`.
+#[repr(C)]
+#[derive(Default)]
+struct NvmeAdminCmd {
+    opcode: u8,
+    flags: u8,
+    rsvd1: u16,
+    nsid: u32,
+    cdw2: u32,
+    cdw3: u32,
+    metadata: u64,
+    addr: u64,
+    metadata_len: u32,
+    data_len: u32,
+    cdw10: u32,
+    cdw11: u32,
+    cdw12: u32,
+    cdw13: u32,
+    cdw14: u32,
+    cdw15: u32,
+    timeout_ms: u32,
+    result: u32,
+}
+
+struct NvmeIoctlTransport {
+    file: File,
+}
+
+impl NvmeTransport for NvmeIoctlTransport {
+    fn admin_command(&mut self, opcode: u8, nsid: u32, cdw10: u32, data: &mut [u8]) -> Result<()> {
+        let mut cmd = NvmeAdminCmd {
+            opcode,
+            nsid,
+            addr: data.as_mut_ptr() as u64,
+            data_len: data.len() as u32,
+            cdw10,
+            timeout_ms: NVME_COMMAND_TIMEOUT_MS,
+            ..Default::default()
+        };
+
+        match unsafe { libc::ioctl(self.file.as_raw_fd(), NVME_IOCTL_ADMIN_CMD as _, &mut cmd) } {
+            0 => Ok(()),
+            r if r < 0 => Err(std::io::Error::last_os_error()).context("NVMe admin command failed"),
+            status => Err(anyhow!(
+                "NVMe admin command {:#04x} failed with status {:#x}",
+                opcode,
+                status
+            )),
+        }
+    }
+}
+
+pub fn nvme_transport(path: &str) -> Result<Box<dyn NvmeTransport>> {
+    let is_nvme = Path::new(path)
+        .file_name()
+        .and_then(|n| n.to_str())
+        .map(|n| n.starts_with("nvme"))
+        .unwrap_or(false);
+    if !is_nvme {
+        return Err(anyhow!("{} is not an NVMe device", path));
+    }
+
+    let file = OpenOptions::new()
+        .read(true)
+        .open(path)
+        .context(format!("Unable to open device {}", path))?;
+    Ok(Box::new(NvmeIoctlTransport { file }))
+}
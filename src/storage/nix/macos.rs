@@ -13,6 +13,60 @@ impl System {
     pub fn enumerate_storage_devices() -> Result<Vec<StorageRef>> {
         DiskUtilCli::default().get_list()
     }
+
+    /// Sets the disk I/O policy of the current process.
+    pub fn set_io_class(class: IoClass) -> Result<()> {
+        const IOPOL_TYPE_DISK: libc::c_int = 0;
+        const IOPOL_SCOPE_PROCESS: libc::c_int = 0;
+        const IOPOL_THROTTLE: libc::c_int = 3;
+        const IOPOL_UTILITY: libc::c_int = 4;
+
+        extern "C" {
+            fn setiopolicy_np(
+                iotype: libc::c_int,
+                scope: libc::c_int,
+                policy: libc::c_int,
+            ) -> libc::c_int;
+        }
+
+        let policy = match class {
+            IoClass::Idle => IOPOL_THROTTLE,
+            IoClass::BestEffort => IOPOL_UTILITY,
+        };
+
+        match unsafe { setiopolicy_np(IOPOL_TYPE_DISK, IOPOL_SCOPE_PROCESS, policy) } {
+            0 => Ok(()),
+            _ => Err(anyhow::Error::new(std::io::Error::last_os_error())
+                .context("Failed to set I/O policy")),
+        }
+    }
+
+    pub fn rescan_capacity(_device_id: &str) -> Result<()> {
+        Err(anyhow!(
+            "Rescanning devices is not supported on this platform"
+        ))
+    }
+
+    /// Stacked devices are not tracked on this platform.
+    pub fn holders(_device_id: &str) -> Result<Vec<String>> {
+        Ok(vec![])
+    }
+
+    /// Finds the filesystem containing the path. Mount options are not collected.
+    pub fn filesystem_of(path: &Path) -> Result<FilesystemInfo> {
+        let c_path = CString::new(path.to_str().ok_or_else(|| anyhow!("Invalid path"))?)?;
+        let mut stat: libc::statfs = unsafe { std::mem::zeroed() };
+        if unsafe { libc::statfs(c_path.as_ptr(), &mut stat) } != 0 {
+            return Err(std::io::Error::last_os_error())
+                .context(format!("Unable to get filesystem of {}", path.display()));
+        }
+
+        let fs_type = unsafe { std::ffi::CStr::from_ptr(stat.f_fstypename.as_ptr()) };
+        Ok(FilesystemInfo {
+            fs_type: fs_type.to_string_lossy().into_owned(),
+            options: vec![],
+        })
+    }
 }
 
 pub fn open_file_direct<P: AsRef<Path>>(file_path: P, write_access: bool) -> Result<File> {
@@ -189,3 +243,15 @@ pub fn unmount(path: &str) -> Result<()> {
             .context("Failed to unmount a volume")),
     }
 }
+
+pub fn scsi_transport(_path: &str, _writable: bool) -> Result<Box<dyn ScsiTransport>> {
+    Err(anyhow!(
+        "Sending commands to the device is not supported on this platform"
+    ))
+}
+
+pub fn nvme_transport(_path: &str) -> Result<Box<dyn NvmeTransport>> {
+    Err(anyhow!(
+        "Sending commands to the device is not supported on this platform"
+    ))
+}
@@ -20,10 +20,22 @@ mod macos;
 #[cfg(target_os = "macos")]
 use macos as os;
 
+pub(crate) use os::open_file_direct;
+
+impl System {
+    /// Space available for writing files to the filesystem containing the path.
+    pub fn free_space(path: &Path) -> Result<u64> {
+        let stat = ::nix::sys::statvfs::statvfs(path)
+            .context(format!("Unable to get free space of {}", path.display()))?;
+        Ok(stat.blocks_available() as u64 * stat.fragment_size() as u64)
+    }
+}
+
 impl StorageError {
-    fn from(err: std::io::Error) -> StorageError {
+    pub(crate) fn from(err: std::io::Error) -> StorageError {
         match err.raw_os_error() {
             Some(c) if c == libc::EIO || c == libc::ESPIPE => StorageError::BadBlock,
+            Some(c) if c == libc::ETIMEDOUT => StorageError::Timeout,
             _ => StorageError::Other(err),
         }
     }
@@ -80,6 +92,10 @@ impl StorageAccess for FileAccess {
 
 impl StorageDevice for StorageRef {
     fn access(&self) -> Result<Box<dyn StorageAccess>> {
+        if let StorageType::File = self.details.storage_type {
+            return ImageFileAccess::open(&self.id).map(|a| Box::new(a) as Box<dyn StorageAccess>);
+        }
+
         self.children
             .iter()
             .flat_map(|c| &c.details.mount_point)
@@ -94,4 +110,36 @@ impl StorageDevice for StorageRef {
 
         FileAccess::new(&self.id).map(|a| Box::new(a) as Box<dyn StorageAccess>)
     }
+
+    fn access_shared(&self) -> Result<Box<dyn StorageAccess>> {
+        if let StorageType::File = self.details.storage_type {
+            return ImageFileAccess::open(&self.id).map(|a| Box::new(a) as Box<dyn StorageAccess>);
+        }
+
+        FileAccess::new(&self.id).map(|a| Box::new(a) as Box<dyn StorageAccess>)
+    }
+
+    fn scsi_transport(&self) -> Result<Box<dyn ScsiTransport>> {
+        if let StorageType::File = self.details.storage_type {
+            return Err(anyhow!("Image files don't accept device commands"));
+        }
+
+        os::scsi_transport(&self.id, true)
+    }
+
+    fn scsi_transport_read_only(&self) -> Result<Box<dyn ScsiTransport>> {
+        if let StorageType::File = self.details.storage_type {
+            return Err(anyhow!("Image files don't accept device commands"));
+        }
+
+        os::scsi_transport(&self.id, false)
+    }
+
+    fn nvme_transport(&self) -> Result<Box<dyn NvmeTransport>> {
+        if let StorageType::File = self.details.storage_type {
+            return Err(anyhow!("Image files don't accept device commands"));
+        }
+
+        os::nvme_transport(&self.id)
+    }
 }
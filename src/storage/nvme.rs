@@ -0,0 +1,18 @@
+use anyhow::Result;
+
+const NVME_ADMIN_GET_LOG_PAGE: u8 = 0x02;
+/// Log pages applying to the whole controller use the broadcast namespace.
+const NVME_NSID_ALL: u32 = 0xffff_ffff;
+
+/// Sends admin commands to an NVMe controller.
+pub trait NvmeTransport {
+    /// Runs a command transferring data from the controller.
+    fn admin_command(&mut self, opcode: u8, nsid: u32, cdw10: u32, data: &mut [u8]) -> Result<()>;
+}
+
+/// Reads a log page, the length of `data` should be a multiple of 4 bytes.
+pub fn get_log_page(transport: &mut dyn NvmeTransport, log_id: u8, data: &mut [u8]) -> Result<()> {
+    let dwords = (data.len() / 4) as u32;
+    let cdw10 = ((dwords - 1) << 16) | log_id as u32;
+    transport.admin_command(NVME_ADMIN_GET_LOG_PAGE, NVME_NSID_ALL, cdw10, data)
+}
@@ -0,0 +1,681 @@
+use crate::storage::{
+    security_protocol_in, security_protocol_out, AtaCommand, AtaProtocol, DataDirection,
+    ScsiTransport, StorageDevice, StorageRef,
+};
+use anyhow::{Context, Result};
+use std::convert::TryInto;
+use std::fmt::{Display, Formatter};
+use std::time::{Duration, Instant};
+
+const TCG_PROTOCOL: u8 = 0x01;
+const LEVEL0_DISCOVERY_COM_ID: u16 = 0x0001;
+const LEVEL0_HEADER_SIZE: usize = 48;
+const TRANSFER_SIZE: usize = 2048;
+const TRANSFER_ALIGNMENT: usize = 512;
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(30);
+/// Reverting may take a while, the drive replaces its media encryption key.
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(120);
+const RESPONSE_POLL: Duration = Duration::from_millis(20);
+
+const FEATURE_LOCKING: u16 = 0x0002;
+
+const UID_SESSION_MANAGER: [u8; 8] = [0, 0, 0, 0, 0, 0, 0, 0xff];
+const UID_ADMIN_SP: [u8; 8] = [0, 0, 2, 5, 0, 0, 0, 1];
+const UID_AUTHORITY_SID: [u8; 8] = [0, 0, 0, 9, 0, 0, 0, 6];
+const UID_AUTHORITY_PSID: [u8; 8] = [0, 0, 0, 9, 0, 1, 0xff, 1];
+const METHOD_START_SESSION: [u8; 8] = [0, 0, 0, 0, 0, 0, 0xff, 2];
+const METHOD_SYNC_SESSION: [u8; 8] = [0, 0, 0, 0, 0, 0, 0xff, 3];
+const METHOD_REVERT: [u8; 8] = [0, 0, 0, 6, 0, 0, 2, 2];
+const HOST_SESSION_ID: u64 = 0x4c45;
+
+/// Security subsystem classes defined by the TCG storage specifications.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SecuritySubsystem {
+    Enterprise,
+    Opal1,
+    Opal2,
+    Opalite,
+    Pyrite1,
+    Pyrite2,
+    Ruby,
+}
+
+impl SecuritySubsystem {
+    fn from_feature(code: u16) -> Option<SecuritySubsystem> {
+        match code {
+            0x0100 => Some(SecuritySubsystem::Enterprise),
+            0x0200 => Some(SecuritySubsystem::Opal1),
+            0x0203 => Some(SecuritySubsystem::Opal2),
+            0x0301 => Some(SecuritySubsystem::Opalite),
+            0x0302 => Some(SecuritySubsystem::Pyrite1),
+            0x0303 => Some(SecuritySubsystem::Pyrite2),
+            0x0304 => Some(SecuritySubsystem::Ruby),
+            _ => None,
+        }
+    }
+}
+
+impl Display for SecuritySubsystem {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            SecuritySubsystem::Enterprise => "Enterprise",
+            SecuritySubsystem::Opal1 => "Opal 1.0",
+            SecuritySubsystem::Opal2 => "Opal 2.0",
+            SecuritySubsystem::Opalite => "Opalite",
+            SecuritySubsystem::Pyrite1 => "Pyrite 1.0",
+            SecuritySubsystem::Pyrite2 => "Pyrite 2.0",
+            SecuritySubsystem::Ruby => "Ruby",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Capabilities reported by the Level 0 Discovery of a self-encrypting drive.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OpalDiscovery {
+    pub subsystem: SecuritySubsystem,
+    /// The first ComID to use for sessions.
+    pub base_com_id: u16,
+    pub locking_enabled: bool,
+    pub locked: bool,
+    pub media_encryption: bool,
+}
+
+impl OpalDiscovery {
+    /// Returns `None` if no supported security subsystem is reported.
+    pub fn parse(data: &[u8]) -> Result<Option<OpalDiscovery>> {
+        if data.len() < LEVEL0_HEADER_SIZE {
+            Err(anyhow!("Level 0 discovery response is too short"))?;
+        }
+        let length = u32::from_be_bytes(data[0..4].try_into().unwrap()) as usize;
+        let end = (length + 4).min(data.len());
+
+        let mut subsystem = None;
+        let (mut locking_enabled, mut locked, mut media_encryption) = (false, false, false);
+        let mut offset = LEVEL0_HEADER_SIZE;
+        while offset + 4 <= end {
+            let code = u16::from_be_bytes([data[offset], data[offset + 1]]);
+            let feature_end = (offset + 4 + data[offset + 3] as usize).min(end);
+            let feature = &data[offset..feature_end];
+
+            if code == FEATURE_LOCKING && feature.len() > 4 {
+                locking_enabled = feature[4] & 0x02 != 0;
+                locked = feature[4] & 0x04 != 0;
+                media_encryption = feature[4] & 0x08 != 0;
+            }
+            // the first SSC found is the one the drive operates in
+            if let Some(s) = SecuritySubsystem::from_feature(code).filter(|_| feature.len() >= 6) {
+                subsystem.get_or_insert((s, u16::from_be_bytes([feature[4], feature[5]])));
+            }
+
+            offset = feature_end;
+        }
+
+        Ok(subsystem.map(|(subsystem, base_com_id)| OpalDiscovery {
+            subsystem,
+            base_com_id,
+            locking_enabled,
+            locked,
+            media_encryption,
+        }))
+    }
+}
+
+impl Display for OpalDiscovery {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.subsystem)?;
+        if self.locked {
+            f.write_str(" (locked)")
+        } else if self.locking_enabled {
+            f.write_str(" (locking enabled)")
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Credentials allowed to revert the Admin SP to the factory state.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OpalAuthority {
+    /// Physical Presence SID, printed on the drive label. Works even if the owner password is lost.
+    Psid,
+    /// The owner password.
+    Sid,
+}
+
+/// Self-encrypting drive talking the TCG storage protocol, either natively over SCSI
+/// or through ATA TRUSTED SEND/RECEIVE commands.
+pub struct OpalDevice {
+    transport: Box<dyn ScsiTransport>,
+    ata: bool,
+    pub discovery: OpalDiscovery,
+}
+
+impl OpalDevice {
+    /// Returns `None` if the storage is not a self-encrypting drive.
+    pub fn probe(storage: &StorageRef) -> Result<Option<OpalDevice>> {
+        OpalDevice::discover(storage.scsi_transport()?)
+    }
+
+    /// Returns `None` if the drive doesn't support any TCG security subsystem.
+    pub fn discover(mut transport: Box<dyn ScsiTransport>) -> Result<Option<OpalDevice>> {
+        let mut data = vec![0u8; TRANSFER_SIZE];
+        let ata = match security_receive(
+            transport.as_mut(),
+            false,
+            LEVEL0_DISCOVERY_COM_ID,
+            &mut data,
+        ) {
+            Ok(()) => false,
+            Err(_) => {
+                security_receive(transport.as_mut(), true, LEVEL0_DISCOVERY_COM_ID, &mut data)
+                    .context("Level 0 discovery is not supported")?;
+                true
+            }
+        };
+
+        Ok(OpalDiscovery::parse(&data)?.map(|discovery| OpalDevice {
+            transport,
+            ata,
+            discovery,
+        }))
+    }
+
+    /// Resets the drive to the factory state, the media encryption key is regenerated
+    /// so all the data becomes unreadable.
+    pub fn revert(&mut self, authority: OpalAuthority, credential: &[u8]) -> Result<()> {
+        let authority_uid = match authority {
+            OpalAuthority::Psid => UID_AUTHORITY_PSID,
+            OpalAuthority::Sid => UID_AUTHORITY_SID,
+        };
+
+        let mut start = Tokens::default();
+        start
+            .call(&UID_SESSION_MANAGER, &METHOD_START_SESSION)
+            .uint(HOST_SESSION_ID)
+            .bytes(&UID_ADMIN_SP)
+            .uint(1)
+            .named(0, |t| t.bytes(credential))
+            .named(3, |t| t.bytes(&authority_uid))
+            .end_call();
+        let response = self.exchange(0, 0, &start.0)?;
+
+        let tokens = decode_tokens(&response)?;
+        let sync = tokens
+            .windows(6)
+            .find(|w| {
+                w[0] == Token::Call
+                    && w[2] == Token::Bytes(METHOD_SYNC_SESSION.to_vec())
+                    && w[3] == Token::StartList
+            })
+            .ok_or_else(|| anyhow!("Unable to start a session: {}", describe_status(&tokens)))?;
+        let tper_session = match sync[5] {
+            Token::Uint(tsn) => tsn as u32,
+            _ => Err(anyhow!("Invalid session start response"))?,
+        };
+
+        let mut revert = Tokens::default();
+        revert.call(&UID_ADMIN_SP, &METHOD_REVERT).end_call();
+        let response = self.exchange(tper_session, HOST_SESSION_ID as u32, &revert.0)?;
+
+        match method_status(&decode_tokens(&response)?) {
+            Some(0) => Ok(()),
+            status => {
+                // the session is closed by the drive only when the revert succeeds
+                let _ = self.send(
+                    tper_session,
+                    HOST_SESSION_ID as u32,
+                    &[TOKEN_END_OF_SESSION],
+                );
+                Err(anyhow!("Revert failed: {}", status_name(status)))
+            }
+        }
+    }
+
+    fn send(&mut self, tper_session: u32, host_session: u32, payload: &[u8]) -> Result<()> {
+        let mut packet = com_packet(
+            self.discovery.base_com_id,
+            tper_session,
+            host_session,
+            payload,
+        );
+        security_send(
+            self.transport.as_mut(),
+            self.ata,
+            self.discovery.base_com_id,
+            &mut packet,
+        )
+    }
+
+    /// Sends a method call and waits for the response payload.
+    fn exchange(
+        &mut self,
+        tper_session: u32,
+        host_session: u32,
+        payload: &[u8],
+    ) -> Result<Vec<u8>> {
+        self.send(tper_session, host_session, payload)?;
+
+        let started = Instant::now();
+        let mut data = vec![0u8; TRANSFER_SIZE];
+        loop {
+            security_receive(
+                self.transport.as_mut(),
+                self.ata,
+                self.discovery.base_com_id,
+                &mut data,
+            )?;
+            if let Some(payload) = parse_com_packet(&data)? {
+                return Ok(payload);
+            }
+            if started.elapsed() > RESPONSE_TIMEOUT {
+                Err(anyhow!("No response from the drive"))?;
+            }
+            std::thread::sleep(RESPONSE_POLL);
+        }
+    }
+}
+
+fn security_receive(
+    transport: &mut dyn ScsiTransport,
+    ata: bool,
+    com_id: u16,
+    data: &mut [u8],
+) -> Result<()> {
+    if ata {
+        AtaCommand::trusted(false, TCG_PROTOCOL, com_id, data.len()).execute(
+            transport,
+            AtaProtocol::PioIn,
+            data,
+            COMMAND_TIMEOUT,
+        )
+    } else {
+        let cdb = security_protocol_in(TCG_PROTOCOL, com_id, data.len() as u32);
+        transport.execute(&cdb, DataDirection::FromDevice, data, COMMAND_TIMEOUT)
+    }
+}
+
+fn security_send(
+    transport: &mut dyn ScsiTransport,
+    ata: bool,
+    com_id: u16,
+    data: &mut [u8],
+) -> Result<()> {
+    if ata {
+        AtaCommand::trusted(true, TCG_PROTOCOL, com_id, data.len()).execute(
+            transport,
+            AtaProtocol::PioOut,
+            data,
+            COMMAND_TIMEOUT,
+        )
+    } else {
+        let cdb = security_protocol_out(TCG_PROTOCOL, com_id, data.len() as u32);
+        transport.execute(&cdb, DataDirection::ToDevice, data, COMMAND_TIMEOUT)
+    }
+}
+
+/// Wraps the payload into a data subpacket, a packet and a ComPacket,
+/// padded to the transfer alignment.
+fn com_packet(com_id: u16, tper_session: u32, host_session: u32, payload: &[u8]) -> Vec<u8> {
+    let subpacket_length = payload.len();
+    let packet_length = 12 + subpacket_length.div_ceil(4) * 4;
+    let com_packet_length = 24 + packet_length;
+
+    let mut data =
+        vec![0u8; (20 + com_packet_length).div_ceil(TRANSFER_ALIGNMENT) * TRANSFER_ALIGNMENT];
+    data[4..6].copy_from_slice(&com_id.to_be_bytes());
+    data[16..20].copy_from_slice(&(com_packet_length as u32).to_be_bytes());
+    data[20..24].copy_from_slice(&tper_session.to_be_bytes());
+    data[24..28].copy_from_slice(&host_session.to_be_bytes());
+    data[40..44].copy_from_slice(&(packet_length as u32).to_be_bytes());
+    data[52..56].copy_from_slice(&(subpacket_length as u32).to_be_bytes());
+    data[56..56 + payload.len()].copy_from_slice(payload);
+    data
+}
+
+/// Extracts the subpacket payload, returns `None` while the response is not ready yet.
+fn parse_com_packet(data: &[u8]) -> Result<Option<Vec<u8>>> {
+    let be = |offset: usize| {
+        data.get(offset..offset + 4)
+            .map(|b| u32::from_be_bytes(b.try_into().unwrap()) as usize)
+            .ok_or_else(|| anyhow!("Truncated response"))
+    };
+
+    if be(16)? == 0 {
+        return Ok(None);
+    }
+    let subpacket_length = be(52)?;
+    data.get(56..56 + subpacket_length)
+        .map(|p| Some(p.to_vec()))
+        .ok_or_else(|| anyhow!("Truncated response"))
+}
+
+const TOKEN_START_LIST: u8 = 0xf0;
+const TOKEN_END_LIST: u8 = 0xf1;
+const TOKEN_START_NAME: u8 = 0xf2;
+const TOKEN_END_NAME: u8 = 0xf3;
+const TOKEN_CALL: u8 = 0xf8;
+const TOKEN_END_OF_DATA: u8 = 0xf9;
+const TOKEN_END_OF_SESSION: u8 = 0xfa;
+const TOKEN_EMPTY: u8 = 0xff;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Uint(u64),
+    Bytes(Vec<u8>),
+    StartList,
+    EndList,
+    StartName,
+    EndName,
+    Call,
+    EndOfData,
+    EndOfSession,
+    Other(u8),
+}
+
+/// Encoder of the method calls.
+#[derive(Default)]
+struct Tokens(Vec<u8>);
+
+impl Tokens {
+    fn uint(&mut self, value: u64) -> &mut Self {
+        if value < 64 {
+            self.0.push(value as u8);
+        } else {
+            let bytes = value.to_be_bytes();
+            let skip = bytes.iter().take_while(|b| **b == 0).count();
+            self.0.push(0x80 | (8 - skip) as u8);
+            self.0.extend_from_slice(&bytes[skip..]);
+        }
+        self
+    }
+
+    fn bytes(&mut self, value: &[u8]) -> &mut Self {
+        if value.len() < 16 {
+            self.0.push(0xa0 | value.len() as u8);
+        } else {
+            self.0.push(0xd0 | (value.len() >> 8) as u8);
+            self.0.push(value.len() as u8);
+        }
+        self.0.extend_from_slice(value);
+        self
+    }
+
+    fn named<F: FnOnce(&mut Self) -> &mut Self>(&mut self, name: u64, value: F) -> &mut Self {
+        self.0.push(TOKEN_START_NAME);
+        self.uint(name);
+        value(self);
+        self.0.push(TOKEN_END_NAME);
+        self
+    }
+
+    /// Starts a method call, the arguments follow.
+    fn call(&mut self, invoking: &[u8; 8], method: &[u8; 8]) -> &mut Self {
+        self.0.push(TOKEN_CALL);
+        self.bytes(invoking).bytes(method);
+        self.0.push(TOKEN_START_LIST);
+        self
+    }
+
+    fn end_call(&mut self) -> &mut Self {
+        self.0
+            .extend_from_slice(&[TOKEN_END_LIST, TOKEN_END_OF_DATA]);
+        self.0
+            .extend_from_slice(&[TOKEN_START_LIST, 0, 0, 0, TOKEN_END_LIST]);
+        self
+    }
+}
+
+fn decode_tokens(data: &[u8]) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < data.len() {
+        let b = data[i];
+        let (header, length, bytes) = match b {
+            0x00..=0x7f => {
+                tokens.push(Token::Uint((b & 0x3f) as u64));
+                i += 1;
+                continue;
+            }
+            0x80..=0xbf => (1, (b & 0x0f) as usize, b & 0x20 != 0),
+            0xc0..=0xdf => {
+                let next = *data.get(i + 1).unwrap_or(&0) as usize;
+                (2, (((b & 0x07) as usize) << 8) | next, b & 0x10 != 0)
+            }
+            0xe0..=0xe3 => {
+                let len = data
+                    .get(i + 1..i + 4)
+                    .map(|l| u32::from_be_bytes([0, l[0], l[1], l[2]]) as usize)
+                    .unwrap_or(0);
+                (4, len, b & 0x02 != 0)
+            }
+            _ => {
+                tokens.push(match b {
+                    TOKEN_START_LIST => Token::StartList,
+                    TOKEN_END_LIST => Token::EndList,
+                    TOKEN_START_NAME => Token::StartName,
+                    TOKEN_END_NAME => Token::EndName,
+                    TOKEN_CALL => Token::Call,
+                    TOKEN_END_OF_DATA => Token::EndOfData,
+                    TOKEN_END_OF_SESSION => Token::EndOfSession,
+                    other => Token::Other(other),
+                });
+                i += 1;
+                continue;
+            }
+        };
+
+        let value = data
+            .get(i + header..i + header + length)
+            .ok_or_else(|| anyhow!("Truncated token in the response"))?;
+        tokens.push(if bytes {
+            Token::Bytes(value.to_vec())
+        } else {
+            Token::Uint(value.iter().fold(0u64, |acc, b| (acc << 8) | *b as u64))
+        });
+        i += header + length;
+    }
+
+    Ok(tokens
+        .into_iter()
+        .filter(|t| *t != Token::Other(TOKEN_EMPTY))
+        .collect())
+}
+
+/// The status code from the list following the end of data token.
+fn method_status(tokens: &[Token]) -> Option<u64> {
+    let end = tokens.iter().rposition(|t| *t == Token::EndOfData)?;
+    match tokens.get(end + 1..end + 3)? {
+        [Token::StartList, Token::Uint(status)] => Some(*status),
+        _ => None,
+    }
+}
+
+fn describe_status(tokens: &[Token]) -> &'static str {
+    status_name(method_status(tokens))
+}
+
+fn status_name(status: Option<u64>) -> &'static str {
+    match status {
+        Some(0x00) => "success",
+        Some(0x01) => "not authorized, check the credential",
+        Some(0x03) => "the drive is busy",
+        Some(0x12) => "authority is locked out, power cycle the drive and retry",
+        Some(0x3f) => "the drive failed to process the request",
+        Some(_) => "the drive rejected the request",
+        None => "invalid response from the drive",
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::cell::RefCell;
+    use std::collections::VecDeque;
+    use std::rc::Rc;
+
+    /// Level 0 discovery response of an Opal 2.0 drive with locking disabled,
+    /// features: TPer, Locking, Geometry, Opal SSC V2.
+    const DISCOVERY_OPAL2: &[u8] = &[
+        0x00, 0x00, 0x00, 0x7c, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, // header
+        0x00, 0x01, 0x10, 0x0c, 0x11, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, // TPer
+        0x00, 0x02, 0x10, 0x0c, 0x09, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, // Locking
+        0x00, 0x03, 0x10, 0x1c, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02,
+        0x00, 0x00, 0x00, 0x00, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, // Geometry
+        0x02, 0x03, 0x10, 0x10, 0x10, 0x01, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x09, 0x00,
+        0x01, 0x00, 0x00, 0x00, 0x00, // Opal SSC V2
+    ];
+
+    struct Recorded {
+        sent: Vec<(Vec<u8>, Vec<u8>)>,
+        responses: VecDeque<Vec<u8>>,
+    }
+
+    /// Replays the recorded responses to the receive commands.
+    struct ReplayTransport(Rc<RefCell<Recorded>>);
+
+    impl ScsiTransport for ReplayTransport {
+        fn execute(
+            &mut self,
+            cdb: &[u8],
+            direction: DataDirection,
+            data: &mut [u8],
+            _timeout: Duration,
+        ) -> Result<()> {
+            let mut recorded = self.0.borrow_mut();
+            recorded.sent.push((cdb.to_vec(), data.to_vec()));
+            if direction == DataDirection::FromDevice {
+                let response = recorded
+                    .responses
+                    .pop_front()
+                    .ok_or_else(|| anyhow!("No more responses"))?;
+                data.fill(0);
+                data[..response.len()].copy_from_slice(&response);
+            }
+            Ok(())
+        }
+    }
+
+    fn replay(responses: Vec<Vec<u8>>) -> (Rc<RefCell<Recorded>>, Box<dyn ScsiTransport>) {
+        let recorded = Rc::new(RefCell::new(Recorded {
+            sent: vec![],
+            responses: responses.into(),
+        }));
+        (recorded.clone(), Box::new(ReplayTransport(recorded)))
+    }
+
+    fn response(tper_session: u32, payload: &[u8]) -> Vec<u8> {
+        com_packet(0x1000, tper_session, HOST_SESSION_ID as u32, payload)
+    }
+
+    /// SyncSession response to StartSession, TPer session number 0x1001.
+    const SYNC_SESSION: &[u8] = &[
+        0xf8, 0xa8, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0xa8, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0xff, 0x03, 0xf0, 0x82, 0x4c, 0x45, 0x82, 0x10, 0x01, 0xf1, 0xf9, 0xf0, 0x00,
+        0x00, 0x00, 0xf1,
+    ];
+
+    #[test]
+    fn test_parsing_discovery() {
+        let discovery = OpalDiscovery::parse(DISCOVERY_OPAL2).unwrap().unwrap();
+        assert_eq!(
+            OpalDiscovery {
+                subsystem: SecuritySubsystem::Opal2,
+                base_com_id: 0x1001,
+                locking_enabled: false,
+                locked: false,
+                media_encryption: true,
+            },
+            discovery
+        );
+        assert_eq!("Opal 2.0", discovery.to_string());
+
+        let mut no_ssc = DISCOVERY_OPAL2.to_vec();
+        no_ssc[3] = 0x5c;
+        assert_eq!(None, OpalDiscovery::parse(&no_ssc).unwrap());
+        assert!(OpalDiscovery::parse(&[0u8; 10]).is_err());
+    }
+
+    #[test]
+    fn test_token_encoding() {
+        let mut tokens = Tokens::default();
+        tokens
+            .uint(5)
+            .uint(0x4c45)
+            .bytes(b"ab")
+            .named(3, |t| t.uint(1));
+        assert_eq!(
+            vec![0x05, 0x82, 0x4c, 0x45, 0xa2, b'a', b'b', 0xf2, 0x03, 0x01, 0xf3],
+            tokens.0
+        );
+
+        let long = [7u8; 32];
+        let mut tokens = Tokens::default();
+        tokens.bytes(&long);
+        assert_eq!(&[0xd0, 32], &tokens.0[..2]);
+        assert_eq!(
+            vec![Token::Bytes(long.to_vec())],
+            decode_tokens(&tokens.0).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_decoding_sync_session() {
+        let tokens = decode_tokens(SYNC_SESSION).unwrap();
+        assert_eq!(Token::Uint(0x1001), tokens[5]);
+        assert_eq!(Some(0), method_status(&tokens));
+        assert!(decode_tokens(&[0xa8, 0x00]).is_err());
+    }
+
+    #[test]
+    fn test_psid_revert() {
+        let (recorded, transport) = replay(vec![
+            DISCOVERY_OPAL2.to_vec(),
+            // the first poll returns an empty ComPacket, the response is not ready yet
+            vec![0u8; 64],
+            response(0, SYNC_SESSION),
+            response(0x1001, &[0xf9, 0xf0, 0x00, 0x00, 0x00, 0xf1]),
+        ]);
+
+        let mut device = OpalDevice::discover(transport).unwrap().unwrap();
+        assert!(!device.ata);
+        device.revert(OpalAuthority::Psid, b"PSIDPSIDPSID").unwrap();
+
+        let recorded = recorded.borrow();
+        let (cdb, start_session) = &recorded.sent[1];
+        assert_eq!(&security_protocol_out(0x01, 0x1001, 512), &cdb[..]);
+        assert_eq!(
+            0,
+            u32::from_be_bytes(start_session[20..24].try_into().unwrap())
+        );
+        let tokens = decode_tokens(&parse_com_packet(start_session).unwrap().unwrap()).unwrap();
+        assert!(tokens.contains(&Token::Bytes(b"PSIDPSIDPSID".to_vec())));
+        assert!(tokens.contains(&Token::Bytes(UID_AUTHORITY_PSID.to_vec())));
+
+        let (_, revert) = &recorded.sent[4];
+        assert_eq!(
+            0x1001,
+            u32::from_be_bytes(revert[20..24].try_into().unwrap())
+        );
+        let tokens = decode_tokens(&parse_com_packet(revert).unwrap().unwrap()).unwrap();
+        assert_eq!(Token::Bytes(METHOD_REVERT.to_vec()), tokens[2]);
+    }
+
+    #[test]
+    fn test_revert_with_wrong_psid() {
+        let (_, transport) = replay(vec![
+            DISCOVERY_OPAL2.to_vec(),
+            response(0, &[0xf9, 0xf0, 0x01, 0x00, 0x00, 0xf1]),
+        ]);
+
+        let mut device = OpalDevice::discover(transport).unwrap().unwrap();
+        let err = device.revert(OpalAuthority::Psid, b"WRONG").unwrap_err();
+        assert!(err.to_string().contains("not authorized"));
+    }
+}
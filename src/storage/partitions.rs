@@ -0,0 +1,761 @@
+use crate::sanitization::mem::AlignedBuffer;
+use crate::storage::{ContentInfo, Extent, ReadOnlyFileAccess, StorageAccess, StorageRef};
+use anyhow::{Context, Result};
+use std::convert::TryInto;
+use std::fmt::{Display, Formatter};
+
+const MBR_SIGNATURE: [u8; 2] = [0x55, 0xaa];
+const MBR_ENTRIES_OFFSET: usize = 446;
+const MBR_ENTRY_SIZE: usize = 16;
+const MBR_BOOTABLE: u8 = 0x80;
+const MBR_PROTECTIVE_TYPE: u8 = 0xee;
+const MBR_EXTENDED_TYPES: [u8; 3] = [0x05, 0x0f, 0x85];
+const MBR_MAX_LOGICAL_PARTITIONS: u32 = 128;
+
+const GPT_SIGNATURE: &[u8] = b"EFI PART";
+const GPT_MIN_HEADER_SIZE: usize = 92;
+const GPT_MIN_ENTRY_SIZE: usize = 128;
+const GPT_MAX_ENTRIES_SIZE: usize = 1 << 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Guid(pub [u8; 16]);
+
+impl Guid {
+    pub fn is_zero(&self) -> bool {
+        self.0.iter().all(|b| *b == 0)
+    }
+}
+
+/// Uses the mixed-endian text representation of GUIDs stored on disk.
+impl Display for Guid {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let b = &self.0;
+        write!(
+            f,
+            "{:08X}-{:04X}-{:04X}-{:02X}{:02X}-{:02X}{:02X}{:02X}{:02X}{:02X}{:02X}",
+            u32::from_le_bytes([b[0], b[1], b[2], b[3]]),
+            u16::from_le_bytes([b[4], b[5]]),
+            u16::from_le_bytes([b[6], b[7]]),
+            b[8],
+            b[9],
+            b[10],
+            b[11],
+            b[12],
+            b[13],
+            b[14],
+            b[15]
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PartitionTableKind {
+    Mbr,
+    Gpt,
+}
+
+impl Display for PartitionTableKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PartitionTableKind::Mbr => f.write_str("MBR"),
+            PartitionTableKind::Gpt => f.write_str("GPT"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PartitionType {
+    Mbr(u8),
+    Gpt(Guid),
+}
+
+const MBR_TYPE_NAMES: &[(u8, &str)] = &[
+    (0x01, "FAT12"),
+    (0x04, "FAT16 <32M"),
+    (0x05, "Extended"),
+    (0x06, "FAT16"),
+    (0x07, "NTFS/exFAT"),
+    (0x0b, "FAT32"),
+    (0x0c, "FAT32 (LBA)"),
+    (0x0e, "FAT16 (LBA)"),
+    (0x0f, "Extended (LBA)"),
+    (0x27, "Windows recovery"),
+    (0x82, "Linux swap"),
+    (0x83, "Linux"),
+    (0x85, "Linux extended"),
+    (0x8e, "Linux LVM"),
+    (0xa5, "FreeBSD"),
+    (0xa8, "Apple UFS"),
+    (0xaf, "Apple HFS+"),
+    (0xee, "GPT protective"),
+    (0xef, "EFI system"),
+    (0xfd, "Linux RAID"),
+];
+
+const GPT_TYPE_NAMES: &[(&str, &str)] = &[
+    ("C12A7328-F81F-11D2-BA4B-00A0C93EC93B", "EFI system"),
+    ("21686148-6449-6E6F-744E-656564454649", "BIOS boot"),
+    ("E3C9E316-0B5C-4DB8-817D-F92DF00215AE", "Microsoft reserved"),
+    (
+        "EBD0A0A2-B9E5-4433-87C0-68B6B72699C7",
+        "Microsoft basic data",
+    ),
+    ("DE94BBA4-06D1-4D40-A16A-BFD50179D6AC", "Windows recovery"),
+    ("0FC63DAF-8483-4772-8E79-3D69D8477DE4", "Linux filesystem"),
+    (
+        "4F68BCE3-E8CD-4DB1-96E7-FBCAF984B709",
+        "Linux root (x86-64)",
+    ),
+    ("933AC7E1-2EB4-4F13-B844-0E14E2AEF915", "Linux home"),
+    ("0657FD6D-A4AB-43C4-84E5-0933C84B4F4F", "Linux swap"),
+    ("E6D6D379-F507-44C2-A23C-238F2A3DF928", "Linux LVM"),
+    ("A19D880F-05FC-4D3B-A006-743F0F84911E", "Linux RAID"),
+    ("CA7D7CCB-63ED-4C53-861C-1742536059CC", "Linux LUKS"),
+    ("48465300-0000-11AA-AA11-00306543ECAC", "Apple HFS+"),
+    ("7C3457EF-0000-11AA-AA11-00306543ECAC", "Apple APFS"),
+    ("516E7CB4-6ECF-11D6-8FF8-00022D09712B", "FreeBSD data"),
+];
+
+impl PartitionType {
+    /// Human readable name of the well known partition types.
+    pub fn name(&self) -> Option<&'static str> {
+        match self {
+            PartitionType::Mbr(t) => MBR_TYPE_NAMES
+                .iter()
+                .find(|(code, _)| code == t)
+                .map(|(_, name)| *name),
+            PartitionType::Gpt(guid) => {
+                let guid = guid.to_string();
+                GPT_TYPE_NAMES
+                    .iter()
+                    .find(|(g, _)| *g == guid)
+                    .map(|(_, name)| *name)
+            }
+        }
+    }
+}
+
+impl Display for PartitionType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PartitionType::Mbr(t) => write!(f, "{:02x}", t),
+            PartitionType::Gpt(guid) => write!(f, "{}", guid),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Partition {
+    pub number: u32,
+    pub partition_type: PartitionType,
+    pub first_lba: u64,
+    /// Inclusive, as stored in the partition table.
+    pub last_lba: u64,
+    pub guid: Option<Guid>,
+    pub name: Option<String>,
+    /// MBR extended partition, containing logical partitions instead of data.
+    pub container: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct PartitionTable {
+    pub kind: PartitionTableKind,
+    pub sector_size: u64,
+    pub disk_guid: Option<Guid>,
+    pub partitions: Vec<Partition>,
+    /// Ranges of sectors occupied by the partition table itself and, for MBR, the boot loader
+    /// gap before the first partition. The end of each range is exclusive.
+    pub reserved: Vec<(u64, u64)>,
+}
+
+impl PartitionTable {
+    /// Reads the partition table of a device or an image file, opening it read-only.
+    pub fn inspect(storage: &StorageRef) -> Result<Option<PartitionTable>> {
+        let mut access = ReadOnlyFileAccess::open(storage)?;
+        let sector_size = access.sector_size;
+        PartitionTable::read(&mut access, sector_size, storage.details.size)
+    }
+
+    /// Reads the partition table from the storage without modifying it.
+    /// Returns `None` if the storage is not partitioned.
+    pub fn read(
+        access: &mut dyn StorageAccess,
+        sector_size: usize,
+        total_size: u64,
+    ) -> Result<Option<PartitionTable>> {
+        let mbr = read_sectors(access, 0, 1, sector_size)?;
+        if mbr[510..512] != MBR_SIGNATURE {
+            return Ok(None);
+        }
+
+        let entries = mbr_entries(&mbr);
+        if entries.iter().any(|e| e.0 == MBR_PROTECTIVE_TYPE) {
+            return read_gpt(access, sector_size, total_size).map(Some);
+        }
+
+        // boot sectors of unpartitioned FAT and NTFS volumes have the same signature,
+        // with the boot code in place of the partition entries
+        let mut boot_flags = (0..4).map(|i| mbr[MBR_ENTRIES_OFFSET + i * MBR_ENTRY_SIZE]);
+        if boot_flags.any(|f| f != 0 && f != MBR_BOOTABLE)
+            || ContentInfo::read(access, sector_size, total_size)?.is_some()
+        {
+            return Ok(None);
+        }
+
+        read_mbr(access, sector_size, total_size, entries).map(Some)
+    }
+
+    /// Byte ranges not covered by partitions or the partition table structures.
+    pub fn unallocated(&self, total_size: u64) -> Vec<Extent> {
+        let mut used: Vec<(u64, u64)> = self
+            .partitions
+            .iter()
+            .filter(|p| !p.container)
+            .map(|p| (p.first_lba, p.last_lba + 1))
+            .chain(self.reserved.iter().cloned())
+            .map(|(first, last)| (first * self.sector_size, last * self.sector_size))
+            .collect();
+        used.sort();
+
+        let mut extents = Vec::new();
+        let mut position = 0;
+        for (start, end) in used
+            .into_iter()
+            .chain(std::iter::once((total_size, total_size)))
+        {
+            let start = start.min(total_size);
+            if start > position {
+                extents.push(Extent {
+                    start: position,
+                    length: start - position,
+                });
+            }
+            position = position.max(end);
+        }
+
+        extents
+    }
+}
+
+pub(crate) fn read_sectors(
+    access: &mut dyn StorageAccess,
+    lba: u64,
+    count: usize,
+    sector_size: usize,
+) -> Result<Vec<u8>> {
+    let length = count * sector_size;
+    let buf = AlignedBuffer::new(length, sector_size);
+    let data = buf.as_mut_slice();
+
+    access.seek(lba * sector_size as u64)?;
+    let mut read = 0;
+    while read < length {
+        match access.read(&mut data[read..])? {
+            0 => Err(anyhow!("Unexpected end of the storage at sector {}", lba))?,
+            n => read += n,
+        }
+    }
+
+    Ok(data.to_vec())
+}
+
+pub(crate) fn u32_at(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+pub(crate) fn u64_at(data: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap())
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(data);
+    hasher.finalize()
+}
+
+pub(crate) fn guid_at(data: &[u8], offset: usize) -> Guid {
+    Guid(data[offset..offset + 16].try_into().unwrap())
+}
+
+/// Type, first sector and number of sectors of the primary entries.
+fn mbr_entries(sector: &[u8]) -> Vec<(u8, u64, u64)> {
+    (0..4)
+        .map(|i| MBR_ENTRIES_OFFSET + i * MBR_ENTRY_SIZE)
+        .map(|o| {
+            (
+                sector[o + 4],
+                u32_at(sector, o + 8) as u64,
+                u32_at(sector, o + 12) as u64,
+            )
+        })
+        .collect()
+}
+
+fn read_mbr(
+    access: &mut dyn StorageAccess,
+    sector_size: usize,
+    total_size: u64,
+    entries: Vec<(u8, u64, u64)>,
+) -> Result<PartitionTable> {
+    let mut partitions = Vec::new();
+    let mut reserved = Vec::new();
+
+    for (i, (partition_type, first, count)) in entries.into_iter().enumerate() {
+        if partition_type == 0 || count == 0 {
+            continue;
+        }
+
+        let container = MBR_EXTENDED_TYPES.contains(&partition_type);
+        partitions.push(Partition {
+            number: i as u32 + 1,
+            partition_type: PartitionType::Mbr(partition_type),
+            first_lba: first,
+            last_lba: first + count - 1,
+            guid: None,
+            name: None,
+            container,
+        });
+
+        if container {
+            read_logical_partitions(access, sector_size, first, &mut partitions, &mut reserved)?;
+        }
+    }
+
+    validate_mbr(&partitions, total_size / sector_size as u64)?;
+
+    // the gap before the first partition often holds a boot loader, e.g. GRUB's core.img
+    let first_partition = partitions.iter().map(|p| p.first_lba).min().unwrap_or(1);
+    reserved.insert(0, (0, first_partition.max(1)));
+
+    Ok(PartitionTable {
+        kind: PartitionTableKind::Mbr,
+        sector_size: sector_size as u64,
+        disk_guid: None,
+        partitions,
+        reserved,
+    })
+}
+
+/// Rejects the partitions outside of the storage and the ones overlapping each other.
+/// Logical partitions are only checked against other logical and primary data partitions,
+/// as they are nested in the extended one.
+fn validate_mbr(partitions: &[Partition], total_sectors: u64) -> Result<()> {
+    for p in partitions {
+        if p.last_lba >= total_sectors {
+            Err(anyhow!(
+                "Invalid MBR: partition {} ends past the end of the storage",
+                p.number
+            ))?;
+        }
+    }
+
+    for (i, a) in partitions.iter().enumerate() {
+        for b in &partitions[i + 1..] {
+            let nested = a.container || b.container;
+            let primary = a.number <= 4 && b.number <= 4;
+            if (primary || !nested) && a.first_lba <= b.last_lba && b.first_lba <= a.last_lba {
+                Err(anyhow!(
+                    "Invalid MBR: partitions {} and {} overlap",
+                    a.number,
+                    b.number
+                ))?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Follows the chain of extended boot records, each describing one logical partition.
+fn read_logical_partitions(
+    access: &mut dyn StorageAccess,
+    sector_size: usize,
+    extended_start: u64,
+    partitions: &mut Vec<Partition>,
+    reserved: &mut Vec<(u64, u64)>,
+) -> Result<()> {
+    let mut ebr_lba = extended_start;
+
+    for number in 5..5 + MBR_MAX_LOGICAL_PARTITIONS {
+        let ebr = read_sectors(access, ebr_lba, 1, sector_size)?;
+        if ebr[510..512] != MBR_SIGNATURE {
+            Err(anyhow!(
+                "Invalid extended boot record at sector {}",
+                ebr_lba
+            ))?;
+        }
+        reserved.push((ebr_lba, ebr_lba + 1));
+
+        let entries = mbr_entries(&ebr);
+        let (partition_type, first, count) = entries[0];
+        if partition_type != 0 && count > 0 {
+            partitions.push(Partition {
+                number,
+                partition_type: PartitionType::Mbr(partition_type),
+                first_lba: ebr_lba + first,
+                last_lba: ebr_lba + first + count - 1,
+                guid: None,
+                name: None,
+                container: false,
+            });
+        }
+
+        match entries[1] {
+            (t, next, count) if MBR_EXTENDED_TYPES.contains(&t) && count > 0 => {
+                ebr_lba = extended_start + next
+            }
+            _ => return Ok(()),
+        }
+    }
+
+    Err(anyhow!("Too many logical partitions"))
+}
+
+fn read_gpt_header(
+    access: &mut dyn StorageAccess,
+    lba: u64,
+    sector_size: usize,
+) -> Result<Vec<u8>> {
+    let header = read_sectors(access, lba, 1, sector_size)?;
+
+    if &header[0..8] != GPT_SIGNATURE {
+        Err(anyhow!("No GPT header at sector {}", lba))?;
+    }
+
+    let header_size = u32_at(&header, 12) as usize;
+    if header_size < GPT_MIN_HEADER_SIZE || header_size > sector_size {
+        Err(anyhow!("Invalid GPT header size at sector {}", lba))?;
+    }
+
+    let mut hashed = header[..header_size].to_vec();
+    hashed[16..20].copy_from_slice(&[0; 4]);
+    if crc32(&hashed) != u32_at(&header, 16) {
+        Err(anyhow!("GPT header checksum mismatch at sector {}", lba))?;
+    }
+
+    Ok(header)
+}
+
+fn read_gpt(
+    access: &mut dyn StorageAccess,
+    sector_size: usize,
+    total_size: u64,
+) -> Result<PartitionTable> {
+    let last_lba = (total_size / sector_size as u64).saturating_sub(1);
+    // the backup header is used when the primary one is damaged
+    let header = read_gpt_header(access, 1, sector_size)
+        .or_else(|_| read_gpt_header(access, last_lba, sector_size))
+        .context("Unable to read GPT header")?;
+
+    let first_usable = u64_at(&header, 40);
+    let last_usable = u64_at(&header, 48);
+    let entries_lba = u64_at(&header, 72);
+    let entries_count = u32_at(&header, 80) as usize;
+    let entry_size = u32_at(&header, 84) as usize;
+
+    let entries_size = entries_count * entry_size;
+    if entry_size < GPT_MIN_ENTRY_SIZE || entries_size > GPT_MAX_ENTRIES_SIZE {
+        Err(anyhow!("Invalid GPT partition entries"))?;
+    }
+
+    let sectors = entries_size.div_ceil(sector_size);
+    let entries = read_sectors(access, entries_lba, sectors, sector_size)?;
+    if crc32(&entries[..entries_size]) != u32_at(&header, 88) {
+        Err(anyhow!("GPT partition entries checksum mismatch"))?;
+    }
+
+    let partitions = entries[..entries_size]
+        .chunks(entry_size)
+        .enumerate()
+        .filter(|(_, e)| !guid_at(e, 0).is_zero())
+        .map(|(i, e)| {
+            let name: Vec<u16> = e[56..128]
+                .chunks(2)
+                .map(|c| u16::from_le_bytes([c[0], c[1]]))
+                .take_while(|c| *c != 0)
+                .collect();
+            Partition {
+                number: i as u32 + 1,
+                partition_type: PartitionType::Gpt(guid_at(e, 0)),
+                first_lba: u64_at(e, 32),
+                last_lba: u64_at(e, 40),
+                guid: Some(guid_at(e, 16)),
+                name: Some(String::from_utf16_lossy(&name)).filter(|n| !n.is_empty()),
+                container: false,
+            }
+        })
+        .collect();
+
+    Ok(PartitionTable {
+        kind: PartitionTableKind::Gpt,
+        sector_size: sector_size as u64,
+        disk_guid: Some(guid_at(&header, 56)),
+        partitions,
+        // protective MBR, headers and both partition entry arrays are outside of the usable range
+        reserved: vec![(0, first_usable), (last_usable + 1, last_lba + 1)],
+    })
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::*;
+    use crate::test_support::{MemoryStorage, TempDir};
+    use std::io::Cursor;
+
+    fn set_mbr_entry(data: &mut [u8], sector: u64, index: usize, entry: (u8, u32, u32)) {
+        let o = sector as usize * 512 + MBR_ENTRIES_OFFSET + index * MBR_ENTRY_SIZE;
+        data[o + 4] = entry.0;
+        data[o + 8..o + 12].copy_from_slice(&entry.1.to_le_bytes());
+        data[o + 12..o + 16].copy_from_slice(&entry.2.to_le_bytes());
+        let s = sector as usize * 512;
+        data[s + 510..s + 512].copy_from_slice(&MBR_SIGNATURE);
+    }
+
+    pub(crate) fn mbr_image() -> Vec<u8> {
+        let mut data = vec![0u8; 12288 * 512];
+        set_mbr_entry(&mut data, 0, 0, (0x83, 2048, 2048));
+        set_mbr_entry(&mut data, 0, 1, (0x05, 6144, 4096));
+        // logical partitions
+        set_mbr_entry(&mut data, 6144, 0, (0x83, 2, 2046));
+        set_mbr_entry(&mut data, 6144, 1, (0x05, 2048, 2048));
+        set_mbr_entry(&mut data, 8192, 0, (0x07, 2, 1022));
+        data
+    }
+
+    pub(crate) fn gpt_image() -> Vec<u8> {
+        let total_sectors = 8192u64;
+        let mut data = vec![0u8; total_sectors as usize * 512];
+        set_mbr_entry(&mut data, 0, 0, (0xee, 1, total_sectors as u32 - 1));
+
+        let mut entries = vec![0u8; 128 * 128];
+        let partitions: [(u8, u64, u64, &str); 2] =
+            [(0xaf, 2048, 4095, "data"), (0xeb, 6144, 8000, "")];
+        for (i, (t, first, last, name)) in partitions.iter().enumerate() {
+            let e = &mut entries[i * 128..(i + 1) * 128];
+            e[0..16].copy_from_slice(&[*t; 16]);
+            e[16..32].copy_from_slice(&[i as u8 + 1; 16]);
+            e[32..40].copy_from_slice(&first.to_le_bytes());
+            e[40..48].copy_from_slice(&last.to_le_bytes());
+            for (j, c) in name.encode_utf16().enumerate() {
+                e[56 + j * 2..58 + j * 2].copy_from_slice(&c.to_le_bytes());
+            }
+        }
+
+        let header = |current: u64, backup: u64, entries_lba: u64| {
+            let mut h = vec![0u8; 92];
+            h[0..8].copy_from_slice(GPT_SIGNATURE);
+            h[8..12].copy_from_slice(&[0, 0, 1, 0]);
+            h[12..16].copy_from_slice(&92u32.to_le_bytes());
+            h[24..32].copy_from_slice(&current.to_le_bytes());
+            h[32..40].copy_from_slice(&backup.to_le_bytes());
+            h[40..48].copy_from_slice(&34u64.to_le_bytes());
+            h[48..56].copy_from_slice(&(total_sectors - 34).to_le_bytes());
+            h[56..72].copy_from_slice(&[0x42; 16]);
+            h[72..80].copy_from_slice(&entries_lba.to_le_bytes());
+            h[80..84].copy_from_slice(&128u32.to_le_bytes());
+            h[84..88].copy_from_slice(&128u32.to_le_bytes());
+            h[88..92].copy_from_slice(&crc32(&entries).to_le_bytes());
+            let crc = crc32(&h);
+            h[16..20].copy_from_slice(&crc.to_le_bytes());
+            h
+        };
+
+        let last = total_sectors - 1;
+        data[512..512 + 92].copy_from_slice(&header(1, last, 2));
+        data[1024..1024 + entries.len()].copy_from_slice(&entries);
+        let backup_entries = (last - 32) as usize * 512;
+        data[backup_entries..backup_entries + entries.len()].copy_from_slice(&entries);
+        data[last as usize * 512..last as usize * 512 + 92].copy_from_slice(&header(
+            last,
+            1,
+            last - 32,
+        ));
+        data
+    }
+
+    fn extents(sectors: &[(u64, u64)]) -> Vec<Extent> {
+        sectors
+            .iter()
+            .map(|(first, last)| Extent {
+                start: first * 512,
+                length: (last - first) * 512,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_unpartitioned_storage() {
+        let mut storage = MemoryStorage(Cursor::new(vec![0u8; 65536]));
+        assert!(PartitionTable::read(&mut storage, 512, 65536)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_reading_mbr() {
+        let data = mbr_image();
+        let total_size = data.len() as u64;
+        let mut storage = MemoryStorage(Cursor::new(data));
+
+        let table = PartitionTable::read(&mut storage, 512, total_size)
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(PartitionTableKind::Mbr, table.kind);
+        assert!(table.disk_guid.is_none());
+
+        let layout: Vec<_> = table
+            .partitions
+            .iter()
+            .map(|p| (p.number, p.first_lba, p.last_lba, p.container))
+            .collect();
+        assert_eq!(
+            vec![
+                (1, 2048, 4095, false),
+                (2, 6144, 10239, true),
+                (5, 6146, 8191, false),
+                (6, 8194, 9215, false)
+            ],
+            layout
+        );
+        assert_eq!(PartitionType::Mbr(0x07), table.partitions[3].partition_type);
+
+        assert_eq!(
+            extents(&[(4096, 6144), (6145, 6146), (8193, 8194), (9216, 12288)]),
+            table.unallocated(total_size)
+        );
+    }
+
+    #[test]
+    fn test_fat_boot_sector_is_not_partitioned() {
+        let mut data = vec![0u8; 12288 * 512];
+        data[0..3].copy_from_slice(&[0xeb, 0x3c, 0x90]);
+        data[54..62].copy_from_slice(b"FAT16   ");
+        // boot code which happens to look like a valid entry
+        set_mbr_entry(&mut data, 0, 0, (0x0b, 2048, 2048));
+        let total_size = data.len() as u64;
+        let mut storage = MemoryStorage(Cursor::new(data));
+
+        assert!(PartitionTable::read(&mut storage, 512, total_size)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_reading_invalid_mbr() {
+        let read = |data: Vec<u8>| {
+            let total_size = data.len() as u64;
+            PartitionTable::read(&mut MemoryStorage(Cursor::new(data)), 512, total_size)
+        };
+
+        let mut data = mbr_image();
+        data[MBR_ENTRIES_OFFSET + MBR_ENTRY_SIZE] = 0x1f;
+        assert!(read(data).unwrap().is_none());
+
+        let mut data = mbr_image();
+        data[MBR_ENTRIES_OFFSET + MBR_ENTRY_SIZE] = MBR_BOOTABLE;
+        assert!(read(data).unwrap().is_some());
+
+        let mut data = mbr_image();
+        set_mbr_entry(&mut data, 0, 2, (0x83, 10240, 2049));
+        assert!(read(data).is_err());
+
+        let mut data = mbr_image();
+        set_mbr_entry(&mut data, 0, 2, (0x83, 4000, 100));
+        assert!(read(data).is_err());
+
+        // overlapping the extended partition
+        let mut data = mbr_image();
+        set_mbr_entry(&mut data, 0, 2, (0x83, 10200, 2000));
+        assert!(read(data).is_err());
+
+        let mut data = mbr_image();
+        set_mbr_entry(&mut data, 0, 2, (0x83, 10240, 2048));
+        assert!(read(data).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_reading_gpt() {
+        let data = gpt_image();
+        let total_size = data.len() as u64;
+        let mut storage = MemoryStorage(Cursor::new(data));
+
+        let table = PartitionTable::read(&mut storage, 512, total_size)
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(PartitionTableKind::Gpt, table.kind);
+        assert_eq!(Some(Guid([0x42; 16])), table.disk_guid);
+        assert_eq!(2, table.partitions.len());
+
+        let p = &table.partitions[0];
+        assert_eq!(1, p.number);
+        assert_eq!((2048, 4095), (p.first_lba, p.last_lba));
+        assert_eq!(PartitionType::Gpt(Guid([0xaf; 16])), p.partition_type);
+        assert_eq!(Some(Guid([1; 16])), p.guid);
+        assert_eq!(Some("data".to_string()), p.name);
+        assert_eq!(None, table.partitions[1].name);
+
+        assert_eq!(
+            extents(&[(34, 2048), (4096, 6144), (8001, 8159)]),
+            table.unallocated(total_size)
+        );
+    }
+
+    #[test]
+    fn test_reading_gpt_from_backup_header() {
+        let mut data = gpt_image();
+        data[512..1024].copy_from_slice(&[0; 512]);
+        let total_size = data.len() as u64;
+        let mut storage = MemoryStorage(Cursor::new(data));
+
+        let table = PartitionTable::read(&mut storage, 512, total_size)
+            .unwrap()
+            .unwrap();
+        assert_eq!(2, table.partitions.len());
+    }
+
+    #[test]
+    fn test_reading_corrupted_gpt() {
+        let mut data = gpt_image();
+        data[1024] ^= 0xff;
+        let total_size = data.len() as u64;
+        let mut storage = MemoryStorage(Cursor::new(data));
+
+        assert!(PartitionTable::read(&mut storage, 512, total_size).is_err());
+    }
+
+    #[test]
+    fn test_partition_type_names() {
+        assert_eq!(Some("Linux"), PartitionType::Mbr(0x83).name());
+        assert_eq!(None, PartitionType::Mbr(0x42).name());
+        assert_eq!("83", PartitionType::Mbr(0x83).to_string());
+
+        let esp = Guid([
+            0x28, 0x73, 0x2a, 0xc1, 0x1f, 0xf8, 0xd2, 0x11, 0xba, 0x4b, 0x00, 0xa0, 0xc9, 0x3e,
+            0xc9, 0x3b,
+        ]);
+        assert_eq!(Some("EFI system"), PartitionType::Gpt(esp).name());
+        assert_eq!(None, PartitionType::Gpt(Guid([0xaf; 16])).name());
+    }
+
+    #[test]
+    fn test_inspecting_image_file() {
+        let dir = TempDir::new("inspect");
+        let path = dir.file("mbr.img", &mbr_image());
+
+        let storage = StorageRef::from_file(&path).unwrap();
+        let table = PartitionTable::inspect(&storage).unwrap().unwrap();
+        assert_eq!(PartitionTableKind::Mbr, table.kind);
+        assert_eq!(4, table.partitions.len());
+    }
+
+    #[test]
+    fn test_guid_display() {
+        let guid = Guid([
+            0x28, 0x73, 0x2a, 0xc1, 0x1f, 0xf8, 0xd2, 0x11, 0xba, 0x4b, 0x00, 0xa0, 0xc9, 0x3e,
+            0xc9, 0x3b,
+        ]);
+        assert_eq!("C12A7328-F81F-11D2-BA4B-00A0C93EC93B", guid.to_string());
+    }
+}
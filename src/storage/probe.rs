@@ -0,0 +1,318 @@
+use crate::storage::partitions::{read_sectors, u32_at, u64_at};
+use crate::storage::{ReadOnlyFileAccess, StorageAccess, StorageRef};
+use anyhow::Result;
+use std::fmt::{Display, Formatter};
+
+/// Enough to reach the signatures of all known formats, the furthest is the ZFS uberblock array.
+const PROBE_SIZE: u64 = 256 * 1024;
+
+const EXT_SUPERBLOCK: usize = 1024;
+const EXT_MAGIC: u16 = 0xef53;
+const EXT_COMPAT_HAS_JOURNAL: u32 = 0x4;
+const EXT_INCOMPAT_EXTENTS: u32 = 0x40;
+const BTRFS_SUPERBLOCK: usize = 64 * 1024;
+const MD_MAGIC: u32 = 0xa92b_4efc;
+const ZFS_UBERBLOCKS: usize = 128 * 1024;
+const ZFS_UBERBLOCK_SIZE: usize = 1024;
+const ZFS_UBERBLOCK_MAGIC: u64 = 0x00ba_b10c;
+const SWAP_PAGE_SIZES: [usize; 2] = [4096, 65536];
+
+/// Filesystems and containers recognized by their on-disk signatures.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ContentKind {
+    Ext2,
+    Ext3,
+    Ext4,
+    Xfs,
+    Btrfs,
+    Ntfs,
+    Fat,
+    Luks1,
+    Luks2,
+    Lvm2,
+    Md,
+    Zfs,
+    Swap,
+    BitLocker,
+}
+
+impl Display for ContentKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            ContentKind::Ext2 => "ext2",
+            ContentKind::Ext3 => "ext3",
+            ContentKind::Ext4 => "ext4",
+            ContentKind::Xfs => "xfs",
+            ContentKind::Btrfs => "btrfs",
+            ContentKind::Ntfs => "NTFS",
+            ContentKind::Fat => "FAT",
+            ContentKind::Luks1 => "LUKS1",
+            ContentKind::Luks2 => "LUKS2",
+            ContentKind::Lvm2 => "LVM2 PV",
+            ContentKind::Md => "Linux RAID",
+            ContentKind::Zfs => "ZFS",
+            ContentKind::Swap => "swap",
+            ContentKind::BitLocker => "BitLocker",
+        };
+        f.write_str(name)
+    }
+}
+
+/// What was found at the beginning of a device or a partition.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContentInfo {
+    pub kind: ContentKind,
+    /// Identifier in the form used by the native tools, if the format has one.
+    pub uuid: Option<String>,
+}
+
+impl ContentInfo {
+    /// Looks for known signatures on the storage, opening it read-only.
+    /// Returns `None` if the content is not recognized.
+    pub fn probe(storage: &StorageRef) -> Result<Option<ContentInfo>> {
+        let mut access = ReadOnlyFileAccess::open(storage)?;
+        let sector_size = access.sector_size;
+        ContentInfo::read(&mut access, sector_size, storage.details.size)
+    }
+
+    pub fn read(
+        access: &mut dyn StorageAccess,
+        sector_size: usize,
+        total_size: u64,
+    ) -> Result<Option<ContentInfo>> {
+        let sectors = PROBE_SIZE.min(total_size) / sector_size as u64;
+        if sectors == 0 {
+            return Ok(None);
+        }
+        let data = read_sectors(access, 0, sectors as usize, sector_size)?;
+        Ok(detect(&data))
+    }
+}
+
+impl Display for ContentInfo {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &self.uuid {
+            Some(uuid) => write!(f, "{} {}", self.kind, uuid),
+            None => write!(f, "{}", self.kind),
+        }
+    }
+}
+
+/// Recognizes the content by the signatures found in the first sectors.
+pub fn detect(data: &[u8]) -> Option<ContentInfo> {
+    // small storages read as zeroes past the end, so all the fields below are accessible
+    let mut data = data.to_vec();
+    data.resize(data.len().max(PROBE_SIZE as usize), 0);
+    let data = data.as_slice();
+
+    let has =
+        |offset: usize, signature: &[u8]| &data[offset..offset + signature.len()] == signature;
+    let found = |kind, uuid| Some(ContentInfo { kind, uuid });
+
+    if has(0, b"LUKS\xba\xbe") {
+        let kind = match u16::from_be_bytes([data[6], data[7]]) {
+            1 => ContentKind::Luks1,
+            _ => ContentKind::Luks2,
+        };
+        return found(kind, ascii(&data[168..208]));
+    }
+
+    if has(512, b"LABELONE") && has(512 + 24, b"LVM2 001") {
+        return found(ContentKind::Lvm2, lvm_uuid(&data[512 + 32..512 + 64]));
+    }
+
+    for offset in &[0, 4096] {
+        if u32_at(data, *offset) == MD_MAGIC {
+            return found(ContentKind::Md, Some(uuid(&data[offset + 16..offset + 32])));
+        }
+    }
+
+    for page_size in &SWAP_PAGE_SIZES {
+        if has(page_size - 10, b"SWAPSPACE2") || has(page_size - 10, b"SWAP-SPACE") {
+            return found(ContentKind::Swap, Some(uuid(&data[1024 + 12..1024 + 28])));
+        }
+    }
+
+    if has(BTRFS_SUPERBLOCK + 64, b"_BHRfS_M") {
+        return found(
+            ContentKind::Btrfs,
+            Some(uuid(&data[BTRFS_SUPERBLOCK + 32..BTRFS_SUPERBLOCK + 48])),
+        );
+    }
+
+    if has(0, b"XFSB") {
+        return found(ContentKind::Xfs, Some(uuid(&data[32..48])));
+    }
+
+    if u16::from_le_bytes([data[EXT_SUPERBLOCK + 56], data[EXT_SUPERBLOCK + 57]]) == EXT_MAGIC {
+        let compat = u32_at(data, EXT_SUPERBLOCK + 92);
+        let incompat = u32_at(data, EXT_SUPERBLOCK + 96);
+        let kind = if incompat & EXT_INCOMPAT_EXTENTS != 0 {
+            ContentKind::Ext4
+        } else if compat & EXT_COMPAT_HAS_JOURNAL != 0 {
+            ContentKind::Ext3
+        } else {
+            ContentKind::Ext2
+        };
+        return found(
+            kind,
+            Some(uuid(&data[EXT_SUPERBLOCK + 104..EXT_SUPERBLOCK + 120])),
+        );
+    }
+
+    if has(3, b"-FVE-FS-") {
+        return found(ContentKind::BitLocker, None);
+    }
+
+    if has(3, b"NTFS    ") {
+        return found(
+            ContentKind::Ntfs,
+            Some(format!("{:016X}", u64_at(data, 72))),
+        );
+    }
+
+    if has(82, b"FAT32   ") {
+        return found(ContentKind::Fat, Some(fat_serial(u32_at(data, 67))));
+    }
+
+    if has(54, b"FAT12   ") || has(54, b"FAT16   ") {
+        return found(ContentKind::Fat, Some(fat_serial(u32_at(data, 39))));
+    }
+
+    // uberblocks are written in turns, so any slot may be the valid one
+    let zfs = (ZFS_UBERBLOCKS..data.len())
+        .step_by(ZFS_UBERBLOCK_SIZE)
+        .any(|o| {
+            let magic = u64_at(data, o);
+            magic == ZFS_UBERBLOCK_MAGIC || magic.swap_bytes() == ZFS_UBERBLOCK_MAGIC
+        });
+    if zfs {
+        return found(ContentKind::Zfs, None);
+    }
+
+    None
+}
+
+fn uuid(b: &[u8]) -> String {
+    format!(
+        "{:02x}{:02x}{:02x}{:02x}-{:02x}{:02x}-{:02x}{:02x}-{:02x}{:02x}-{:02x}{:02x}{:02x}{:02x}{:02x}{:02x}",
+        b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7], b[8], b[9], b[10], b[11], b[12], b[13], b[14], b[15]
+    )
+}
+
+fn ascii(b: &[u8]) -> Option<String> {
+    let s: String = b
+        .iter()
+        .take_while(|c| **c != 0 && c.is_ascii())
+        .map(|c| *c as char)
+        .collect();
+    Some(s).filter(|s| !s.is_empty())
+}
+
+/// LVM prints the identifiers of physical volumes split into groups of 6-4-4-4-4-4-6 characters.
+fn lvm_uuid(b: &[u8]) -> Option<String> {
+    let s = ascii(b)?;
+    let mut groups = Vec::new();
+    let mut rest = s.as_str();
+    for len in &[6, 4, 4, 4, 4, 4, 6] {
+        let (group, tail) = rest.split_at((*len).min(rest.len()));
+        groups.push(group);
+        rest = tail;
+    }
+    Some(groups.join("-"))
+}
+
+fn fat_serial(serial: u32) -> String {
+    format!("{:04X}-{:04X}", serial >> 16, serial & 0xffff)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn image(signatures: &[(usize, &[u8])]) -> Vec<u8> {
+        let mut data = vec![0u8; PROBE_SIZE as usize];
+        for (offset, bytes) in signatures {
+            data[*offset..offset + bytes.len()].copy_from_slice(bytes);
+        }
+        data
+    }
+
+    #[test]
+    fn test_detecting_nothing() {
+        assert_eq!(None, detect(&image(&[])));
+        assert_eq!(None, detect(&[]));
+    }
+
+    #[test]
+    fn test_detecting_ext4() {
+        let data = image(&[
+            (1024 + 56, &[0x53, 0xef]),
+            (1024 + 92, &[0x4, 0, 0, 0]),
+            (1024 + 96, &[0x40, 0, 0, 0]),
+            (1024 + 104, &[0xab; 16]),
+        ]);
+        assert_eq!(
+            Some(ContentInfo {
+                kind: ContentKind::Ext4,
+                uuid: Some("abababab-abab-abab-abab-abababababab".to_string())
+            }),
+            detect(&data)
+        );
+    }
+
+    #[test]
+    fn test_detecting_luks() {
+        let data = image(&[
+            (0, b"LUKS\xba\xbe\x00\x02"),
+            (168, b"0ed1ae93-8b9b-4b7b-a0b0-8a7c5a4d8f2e"),
+        ]);
+        let info = detect(&data).unwrap();
+        assert_eq!(ContentKind::Luks2, info.kind);
+        assert_eq!(
+            Some("0ed1ae93-8b9b-4b7b-a0b0-8a7c5a4d8f2e".to_string()),
+            info.uuid
+        );
+    }
+
+    #[test]
+    fn test_detecting_lvm() {
+        let data = image(&[
+            (512, b"LABELONE"),
+            (512 + 24, b"LVM2 001"),
+            (512 + 32, b"0123456789abcdefghijklmnopqrstuv"),
+        ]);
+        let info = detect(&data).unwrap();
+        assert_eq!(ContentKind::Lvm2, info.kind);
+        assert_eq!(
+            Some("012345-6789-abcd-efgh-ijkl-mnop-qrstuv".to_string()),
+            info.uuid
+        );
+    }
+
+    #[test]
+    fn test_detecting_other_formats() {
+        let kind = |signatures: &[(usize, &[u8])]| detect(&image(signatures)).map(|i| i.kind);
+
+        assert_eq!(Some(ContentKind::Xfs), kind(&[(0, b"XFSB")]));
+        assert_eq!(Some(ContentKind::Btrfs), kind(&[(65536 + 64, b"_BHRfS_M")]));
+        assert_eq!(Some(ContentKind::Ntfs), kind(&[(3, b"NTFS    ")]));
+        assert_eq!(Some(ContentKind::BitLocker), kind(&[(3, b"-FVE-FS-")]));
+        assert_eq!(Some(ContentKind::Fat), kind(&[(82, b"FAT32   ")]));
+        assert_eq!(Some(ContentKind::Fat), kind(&[(54, b"FAT16   ")]));
+        assert_eq!(Some(ContentKind::Swap), kind(&[(4086, b"SWAPSPACE2")]));
+        assert_eq!(
+            Some(ContentKind::Md),
+            kind(&[(4096, &[0xfc, 0x4e, 0x2b, 0xa9])])
+        );
+        assert_eq!(
+            Some(ContentKind::Zfs),
+            kind(&[(131072 + 3072, &[0x0c, 0xb1, 0xba, 0, 0, 0, 0, 0])])
+        );
+    }
+
+    #[test]
+    fn test_fat_serial() {
+        assert_eq!("1234-ABCD", fat_serial(0x1234abcd));
+    }
+}
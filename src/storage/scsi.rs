@@ -0,0 +1,215 @@
+use anyhow::Result;
+use std::time::Duration;
+use thiserror::Error;
+
+pub const SENSE_KEY_NO_SENSE: u8 = 0x00;
+pub const SENSE_KEY_RECOVERED_ERROR: u8 = 0x01;
+pub const SENSE_KEY_NOT_READY: u8 = 0x02;
+pub const SENSE_KEY_ILLEGAL_REQUEST: u8 = 0x05;
+
+pub const SCSI_FORMAT_UNIT: u8 = 0x04;
+pub const SCSI_SANITIZE: u8 = 0x48;
+const SCSI_REQUEST_SENSE: u8 = 0x03;
+const SCSI_MAINTENANCE_IN: u8 = 0xa3;
+const REPORT_SUPPORTED_OPERATION_CODES: u8 = 0x0c;
+const SECURITY_PROTOCOL_IN: u8 = 0xa2;
+const SECURITY_PROTOCOL_OUT: u8 = 0xb5;
+const SENSE_BUFFER_SIZE: usize = 252;
+const SHORT_COMMAND_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DataDirection {
+    None,
+    FromDevice,
+    ToDevice,
+}
+
+/// Sends commands directly to a device, bypassing the block layer.
+/// Used for the features not available through regular I/O, like self-encrypting drives.
+pub trait ScsiTransport {
+    /// Fails with `ScsiError::CheckCondition` when the device reports an error with sense data.
+    fn execute(
+        &mut self,
+        cdb: &[u8],
+        direction: DataDirection,
+        data: &mut [u8],
+        timeout: Duration,
+    ) -> Result<()>;
+}
+
+/// Decoded sense data, describing the outcome of a failed or a still running command.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sense {
+    pub key: u8,
+    pub asc: u8,
+    pub ascq: u8,
+    /// Progress of a long running operation in 1/65536 units, if reported.
+    pub progress: Option<u16>,
+    pub raw: Vec<u8>,
+}
+
+impl Sense {
+    /// Parses both fixed and descriptor format sense data.
+    pub fn parse(data: &[u8]) -> Option<Sense> {
+        let byte = |i: usize| data.get(i).cloned().unwrap_or(0);
+        let word = |i: usize| u16::from_be_bytes([byte(i), byte(i + 1)]);
+
+        let (key, asc, ascq, progress) = match byte(0) & 0x7f {
+            0x70 | 0x71 => {
+                let sksv = byte(15) & 0x80 != 0;
+                (
+                    byte(2) & 0x0f,
+                    byte(12),
+                    byte(13),
+                    Some(word(16)).filter(|_| sksv),
+                )
+            }
+            0x72 | 0x73 => {
+                // the sense key specific descriptor carries the progress indication
+                let end = (8 + byte(7) as usize).min(data.len());
+                let mut progress = None;
+                let mut offset = 8;
+                while offset + 1 < end {
+                    if byte(offset) == 0x02 && byte(offset + 4) & 0x80 != 0 {
+                        progress = Some(word(offset + 5));
+                    }
+                    offset += 2 + byte(offset + 1) as usize;
+                }
+                (byte(1) & 0x0f, byte(2), byte(3), progress)
+            }
+            _ => return None,
+        };
+
+        Some(Sense {
+            key,
+            asc,
+            ascq,
+            progress: progress.filter(|_| key == SENSE_KEY_NO_SENSE || key == SENSE_KEY_NOT_READY),
+            raw: data.to_vec(),
+        })
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum ScsiError {
+    #[error("check condition (sense key {:#04x}, asc {:#04x}, ascq {:#04x})", .0.key, .0.asc, .0.ascq)]
+    CheckCondition(Sense),
+    #[error("command failed with status {0:#04x}")]
+    Status(u8),
+}
+
+/// Sense data of a failed command, if the error came from the device.
+pub fn sense_of(err: &anyhow::Error) -> Option<&Sense> {
+    match err.downcast_ref::<ScsiError>() {
+        Some(ScsiError::CheckCondition(sense)) => Some(sense),
+        _ => None,
+    }
+}
+
+/// Asks the device whether it implements the command, using REPORT SUPPORTED OPERATION CODES.
+/// Devices not implementing the report itself are assumed not to support the command.
+pub fn is_command_supported(
+    transport: &mut dyn ScsiTransport,
+    opcode: u8,
+    service_action: Option<u16>,
+) -> Result<bool> {
+    let mut cdb = [0u8; 12];
+    cdb[0] = SCSI_MAINTENANCE_IN;
+    cdb[1] = REPORT_SUPPORTED_OPERATION_CODES;
+    cdb[2] = if service_action.is_some() { 0x02 } else { 0x01 };
+    cdb[3] = opcode;
+    cdb[4..6].copy_from_slice(&service_action.unwrap_or(0).to_be_bytes());
+    let mut data = [0u8; 16];
+    cdb[6..10].copy_from_slice(&(data.len() as u32).to_be_bytes());
+
+    match transport.execute(
+        &cdb,
+        DataDirection::FromDevice,
+        &mut data,
+        SHORT_COMMAND_TIMEOUT,
+    ) {
+        // 3: supported as defined in the standard, 5: in a vendor specific way
+        Ok(()) => Ok(matches!(data[1] & 0x07, 3 | 5)),
+        Err(err) if sense_of(&err).map(|s| s.key) == Some(SENSE_KEY_ILLEGAL_REQUEST) => Ok(false),
+        Err(err) => Err(err),
+    }
+}
+
+/// Sense data of the last command, used to poll the progress of long running operations.
+pub fn request_sense(transport: &mut dyn ScsiTransport) -> Result<Option<Sense>> {
+    let mut data = [0u8; SENSE_BUFFER_SIZE];
+    let cdb = [SCSI_REQUEST_SENSE, 0, 0, 0, data.len() as u8, 0];
+    transport.execute(
+        &cdb,
+        DataDirection::FromDevice,
+        &mut data,
+        SHORT_COMMAND_TIMEOUT,
+    )?;
+    Ok(Sense::parse(&data))
+}
+
+pub fn security_protocol_in(protocol: u8, sp_specific: u16, length: u32) -> [u8; 12] {
+    security_protocol_cdb(SECURITY_PROTOCOL_IN, protocol, sp_specific, length)
+}
+
+pub fn security_protocol_out(protocol: u8, sp_specific: u16, length: u32) -> [u8; 12] {
+    security_protocol_cdb(SECURITY_PROTOCOL_OUT, protocol, sp_specific, length)
+}
+
+fn security_protocol_cdb(opcode: u8, protocol: u8, sp_specific: u16, length: u32) -> [u8; 12] {
+    let mut cdb = [0u8; 12];
+    cdb[0] = opcode;
+    cdb[1] = protocol;
+    cdb[2..4].copy_from_slice(&sp_specific.to_be_bytes());
+    cdb[6..10].copy_from_slice(&length.to_be_bytes());
+    cdb
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parsing_fixed_sense() {
+        let mut data = [0u8; 18];
+        data[0] = 0x70;
+        data[2] = SENSE_KEY_NOT_READY;
+        data[12] = 0x04;
+        data[13] = 0x1b;
+        data[15] = 0x80;
+        data[16..18].copy_from_slice(&0x8000u16.to_be_bytes());
+
+        let sense = Sense::parse(&data).unwrap();
+        assert_eq!(
+            (SENSE_KEY_NOT_READY, 0x04, 0x1b, Some(0x8000)),
+            (sense.key, sense.asc, sense.ascq, sense.progress)
+        );
+    }
+
+    #[test]
+    fn test_parsing_descriptor_sense() {
+        let data = [
+            0x72, 0x02, 0x04, 0x04, 0, 0, 0, 8, // header
+            0x02, 0x06, 0, 0, 0x80, 0x40, 0x00, 0, // sense key specific
+        ];
+
+        let sense = Sense::parse(&data).unwrap();
+        assert_eq!(
+            (SENSE_KEY_NOT_READY, 0x04, 0x04, Some(0x4000)),
+            (sense.key, sense.asc, sense.ascq, sense.progress)
+        );
+
+        let mut data = data;
+        data[1] = SENSE_KEY_ILLEGAL_REQUEST;
+        assert_eq!(None, Sense::parse(&data).unwrap().progress);
+        assert_eq!(None, Sense::parse(&[0u8; 18]));
+    }
+
+    #[test]
+    fn test_security_protocol_cdb() {
+        assert_eq!(
+            [0xa2, 0x01, 0x00, 0x01, 0, 0, 0, 0, 0x02, 0x00, 0, 0],
+            security_protocol_in(0x01, 0x0001, 512)
+        );
+    }
+}
@@ -6,7 +6,8 @@ use widestring::WideCString;
 use winapi::_core::ptr::null_mut;
 use winapi::shared::minwindef::{DWORD, LPVOID};
 use winapi::shared::winerror::{
-    ERROR_CRC, ERROR_READ_FAULT, ERROR_SECTOR_NOT_FOUND, ERROR_SEEK, ERROR_WRITE_FAULT,
+    ERROR_CRC, ERROR_READ_FAULT, ERROR_SECTOR_NOT_FOUND, ERROR_SEEK, ERROR_SEM_TIMEOUT,
+    ERROR_WRITE_FAULT,
 };
 use winapi::um::fileapi::*;
 use winapi::um::handleapi::{CloseHandle, INVALID_HANDLE_VALUE};
@@ -103,7 +104,7 @@ impl DeviceFile {
 }
 
 impl StorageError {
-    fn from(err: std::io::Error) -> StorageError {
+    pub(crate) fn from(err: std::io::Error) -> StorageError {
         match err.raw_os_error() {
             Some(c)
                 if c == ERROR_CRC as i32
@@ -114,6 +115,7 @@ impl StorageError {
             {
                 StorageError::BadBlock
             }
+            Some(c) if c == ERROR_SEM_TIMEOUT as i32 => StorageError::Timeout,
             _ => StorageError::Other(err),
         }
     }
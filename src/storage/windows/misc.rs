@@ -1,14 +1,22 @@
 use super::winapi::shared::ntdef::PVOID;
+use anyhow::{Context, Result};
 use std::mem;
+use std::path::Path;
 use std::ptr::null_mut;
+use widestring::{WideCStr, WideCString};
+use winapi::shared::minwindef::{DWORD, MAX_PATH};
+use winapi::um::fileapi::{GetDiskFreeSpaceExW, GetVolumeInformationW, GetVolumePathNameW};
 use winapi::um::handleapi::CloseHandle;
 use winapi::um::processthreadsapi::GetCurrentProcess;
 use winapi::um::processthreadsapi::OpenProcessToken;
+use winapi::um::processthreadsapi::SetPriorityClass;
 use winapi::um::securitybaseapi::GetTokenInformation;
+use winapi::um::winbase::PROCESS_MODE_BACKGROUND_BEGIN;
 use winapi::um::winnt::TokenElevation;
 use winapi::um::winnt::HANDLE;
 use winapi::um::winnt::TOKEN_ELEVATION;
 use winapi::um::winnt::TOKEN_QUERY;
+use winapi::um::winnt::ULARGE_INTEGER;
 
 pub fn is_elevated() -> bool {
     let mut result = false;
@@ -37,3 +45,52 @@ pub fn is_elevated() -> bool {
     }
     result
 }
+
+pub fn enter_background_mode() -> Result<()> {
+    if unsafe { SetPriorityClass(GetCurrentProcess(), PROCESS_MODE_BACKGROUND_BEGIN) } != 0 {
+        Ok(())
+    } else {
+        Err(anyhow::Error::new(std::io::Error::last_os_error())
+            .context("Failed to enter background processing mode"))
+    }
+}
+
+pub fn get_filesystem_name(path: &Path) -> Result<String> {
+    let path = WideCString::from_os_str(path.as_os_str())?;
+    let mut volume = [0u16; MAX_PATH + 1];
+    let mut fs_name = [0u16; MAX_PATH + 1];
+
+    unsafe {
+        if GetVolumePathNameW(path.as_ptr(), volume.as_mut_ptr(), volume.len() as DWORD) == 0 {
+            return Err(std::io::Error::last_os_error()).context("Unable to find the volume");
+        }
+
+        if GetVolumeInformationW(
+            volume.as_ptr(),
+            null_mut(),
+            0,
+            null_mut(),
+            null_mut(),
+            null_mut(),
+            fs_name.as_mut_ptr(),
+            fs_name.len() as DWORD,
+        ) == 0
+        {
+            return Err(std::io::Error::last_os_error())
+                .context("Unable to get the volume information");
+        }
+
+        Ok(WideCStr::from_ptr_str(fs_name.as_ptr()).to_string_lossy())
+    }
+}
+
+pub fn get_free_space(path: &Path) -> Result<u64> {
+    let path = WideCString::from_os_str(path.as_os_str())?;
+    let mut available: ULARGE_INTEGER = unsafe { mem::zeroed() };
+
+    if unsafe { GetDiskFreeSpaceExW(path.as_ptr(), &mut available, null_mut(), null_mut()) } == 0 {
+        return Err(std::io::Error::last_os_error()).context("Unable to get the free space");
+    }
+
+    Ok(unsafe { *available.QuadPart() })
+}
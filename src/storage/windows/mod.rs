@@ -30,12 +30,71 @@ impl System {
         devices.sort_by(|a, b| a.id.cmp(&b.id));
         Ok(devices)
     }
+
+    /// Windows doesn't have I/O classes, any of them switches the process to background mode
+    /// which lowers both CPU and I/O priorities.
+    pub fn set_io_class(_class: IoClass) -> Result<()> {
+        enter_background_mode()
+    }
+
+    /// Space available for writing files to the filesystem containing the path.
+    pub fn free_space(path: &std::path::Path) -> Result<u64> {
+        get_free_space(path)
+    }
+
+    pub fn rescan_capacity(_device_id: &str) -> Result<()> {
+        Err(anyhow!(
+            "Rescanning devices is not supported on this platform"
+        ))
+    }
+
+    /// Stacked devices are not tracked on this platform.
+    pub fn holders(_device_id: &str) -> Result<Vec<String>> {
+        Ok(vec![])
+    }
+
+    /// Finds the filesystem containing the path. Mount options are not collected.
+    pub fn filesystem_of(path: &std::path::Path) -> Result<FilesystemInfo> {
+        Ok(FilesystemInfo {
+            fs_type: get_filesystem_name(path)?.to_lowercase(),
+            options: vec![],
+        })
+    }
 }
 
 impl StorageDevice for StorageRef {
     fn access(&self) -> Result<Box<dyn StorageAccess>> {
+        if let StorageType::File = self.details.storage_type {
+            return ImageFileAccess::open(&self.id).map(|a| Box::new(a) as Box<dyn StorageAccess>);
+        }
+
         CompositeStorageAccess::open(self).map(|a| Box::new(a) as Box<dyn StorageAccess>)
     }
+
+    /// Volumes stay locked by the system, so only the sectors outside of them are writable.
+    fn access_shared(&self) -> Result<Box<dyn StorageAccess>> {
+        if let StorageType::File = self.details.storage_type {
+            return ImageFileAccess::open(&self.id).map(|a| Box::new(a) as Box<dyn StorageAccess>);
+        }
+
+        DeviceFile::open(&self.id, true).map(|a| Box::new(a) as Box<dyn StorageAccess>)
+    }
+
+    fn scsi_transport(&self) -> Result<Box<dyn ScsiTransport>> {
+        Err(anyhow!(
+            "Sending commands to the device is not supported on this platform"
+        ))
+    }
+
+    fn scsi_transport_read_only(&self) -> Result<Box<dyn ScsiTransport>> {
+        self.scsi_transport()
+    }
+
+    fn nvme_transport(&self) -> Result<Box<dyn NvmeTransport>> {
+        Err(anyhow!(
+            "Sending commands to the device is not supported on this platform"
+        ))
+    }
 }
 
 /// On Windows, to work with a low level PhysicalDrive, we have to acquire locks to all partitions/volumes
@@ -0,0 +1,106 @@
+//! Fixtures shared by the unit tests.
+
+use crate::actions::{WipeEvent, WipeEventReceiver, WipeState, WipeTask};
+use crate::storage::StorageAccess;
+use anyhow::Result;
+use std::fs;
+use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/// Storage kept in memory, e.g. holding a disk image built by a test.
+pub(crate) struct MemoryStorage(pub Cursor<Vec<u8>>);
+
+impl StorageAccess for MemoryStorage {
+    fn position(&mut self) -> Result<u64> {
+        Ok(self.0.position())
+    }
+
+    fn seek(&mut self, position: u64) -> Result<u64> {
+        Ok(self.0.seek(SeekFrom::Start(position))?)
+    }
+
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize> {
+        Ok(self.0.read(buffer)?)
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<()> {
+        Ok(self.0.write_all(data)?)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Collects every event, optionally running an action when a position is reached.
+pub(crate) struct StubReceiver {
+    pub collected: Vec<(WipeState, WipeEvent)>,
+    trigger: Option<(u64, Box<dyn Fn()>)>,
+}
+
+impl StubReceiver {
+    pub fn new() -> Self {
+        StubReceiver {
+            collected: Vec::new(),
+            trigger: None,
+        }
+    }
+
+    pub fn on_progress<F: Fn() + 'static>(position: u64, action: F) -> Self {
+        StubReceiver {
+            collected: Vec::new(),
+            trigger: Some((position, Box::new(action))),
+        }
+    }
+
+    pub fn progress(&self) -> Vec<u64> {
+        self.collected
+            .iter()
+            .filter_map(|(_, e)| match e {
+                WipeEvent::Progress(p) => Some(*p),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+impl WipeEventReceiver for StubReceiver {
+    fn handle(&mut self, _task: &WipeTask, state: &WipeState, event: WipeEvent) -> () {
+        println!("{:?}", event);
+        if let (Some((position, action)), WipeEvent::Progress(p)) = (&self.trigger, &event) {
+            if position == p {
+                action();
+            }
+        }
+        self.collected.push((state.clone(), event));
+    }
+}
+
+/// Temporary directory removed with its content when dropped, also when a test panics.
+pub(crate) struct TempDir(PathBuf);
+
+impl TempDir {
+    pub fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("lethe-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        TempDir(path)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+
+    /// Creates a file with the given content in this directory.
+    pub fn file(&self, name: &str, content: &[u8]) -> PathBuf {
+        let path = self.0.join(name);
+        fs::write(&path, content).unwrap();
+        path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
@@ -1,28 +1,135 @@
 use anyhow::{Context, Result};
 use regex::Regex;
+use std::time::Duration;
+
+const SIZE_FORMAT_HELP: &str =
+    "Use a number with an optional unit (e.g. 4096, 128K, 2MiB, 500MB, 2048s or 10%).";
+
+/// What the relative sizes are measured in, units without a known reference are rejected.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SizeUnits {
+    /// Logical sector size for values in sectors (e.g. `2048s`).
+    pub sector_size: Option<u64>,
+    /// Size of the device for percentages (e.g. `10%`).
+    pub total_size: Option<u64>,
+}
+
+/// Parses a number of bytes with an optional unit. Single letters and IEC units
+/// (`K`, `KiB`) are powers of 1024, SI units (`kB`, `KB`) are powers of 1000,
+/// same as `dd`. Only percentages can be fractional.
+pub fn parse_size_in(s: &str, units: SizeUnits) -> Result<u64> {
+    let size_regex = Regex::new(r"^(\d+)(\.(\d+))? *([a-zA-Z%]*)$").unwrap();
+    let groups = size_regex
+        .captures(s.trim())
+        .ok_or_else(|| anyhow!(SIZE_FORMAT_HELP))?;
+    let unit = &groups[4];
+
+    if unit == "%" {
+        let total_size = units
+            .total_size
+            .ok_or_else(|| anyhow!("Percentages are not supported here."))?;
+        let fraction = groups.get(3).map(|m| m.as_str()).unwrap_or("");
+        let scale = 10u128
+            .checked_pow(fraction.len() as u32)
+            .ok_or_else(|| anyhow!("Too many decimal places."))?;
+        let percent: u128 = format!("{}{}", &groups[1], fraction)
+            .parse()
+            .context("The value is too big.")?;
+        if percent > 100 * scale {
+            return Err(anyhow!("Should be at most 100%."));
+        }
+        return Ok((total_size as u128 * percent / (100 * scale)) as u64);
+    }
+
+    if groups.get(2).is_some() {
+        return Err(anyhow!("Only percentages can be fractional."));
+    }
+    let value = groups[1].parse::<u64>().context("The value is too big.")?;
+
+    let unit_size = match unit {
+        "" | "b" | "B" => 1,
+        "s" | "S" => units
+            .sector_size
+            .ok_or_else(|| anyhow!("Sector units are not supported here."))?,
+        _ => {
+            let exponent = match unit.chars().next().map(|c| c.to_ascii_uppercase()) {
+                Some('K') => 1,
+                Some('M') => 2,
+                Some('G') => 3,
+                Some('T') => 4,
+                Some('P') => 5,
+                _ => return Err(unknown_unit(unit)),
+            };
+            let base: u64 = match &unit[1..] {
+                "" | "i" | "iB" | "ib" => 1024,
+                "B" | "b" => 1000,
+                _ => return Err(unknown_unit(unit)),
+            };
+            base.pow(exponent)
+        }
+    };
+
+    value
+        .checked_mul(unit_size)
+        .ok_or_else(|| anyhow!("The value is too big."))
+}
+
+fn unknown_unit(unit: &str) -> anyhow::Error {
+    anyhow!(
+        "Unknown unit {}. Use K, M, G, T, P or KiB, MiB, ... for powers of 1024, \
+        kB, MB, ... for powers of 1000, s for sectors or %.",
+        unit
+    )
+}
+
+pub fn parse_size(s: &str) -> Result<u64> {
+    parse_size_in(s, SizeUnits::default())
+}
 
 pub fn parse_block_size(s: &str) -> Result<usize> {
-    let block_size_regex = Regex::new(r"^(?i)(\d+) *(([km])b?)?$").unwrap();
-    let captures = block_size_regex.captures(s);
+    parse_block_size_in(s, SizeUnits::default())
+}
+
+/// Same as `parse_size_in`, but the size should be a power of two.
+pub fn parse_block_size_in(s: &str, units: SizeUnits) -> Result<usize> {
+    let size = parse_size_in(s, units)?;
+    if size == 0 || size & (size - 1) != 0 {
+        return Err(anyhow!("Should be a power of two."));
+    }
+    Ok(size as usize)
+}
+
+/// Parses a throughput limit in bytes per second, zero means no limit.
+pub fn parse_rate(s: &str) -> Result<Option<u64>> {
+    let trimmed = s.trim();
+    let size = match trimmed.len().checked_sub(2) {
+        Some(i) if trimmed.is_char_boundary(i) && trimmed[i..].eq_ignore_ascii_case("/s") => {
+            &trimmed[..i]
+        }
+        _ => trimmed,
+    };
+
+    match parse_size(size)? {
+        0 => Ok(None),
+        rate => Ok(Some(rate)),
+    }
+}
+
+pub fn parse_duration(s: &str) -> Result<Duration> {
+    let duration_regex = Regex::new(r"^(?i)(\d+) *(ms|s|m)?$").unwrap();
+    let captures = duration_regex.captures(s);
 
     match captures {
         Some(groups) => {
-            let units = groups[1].parse::<usize>().context("Not a number.")?;
-            let unit_size = match groups.get(3).map(|m| m.as_str().to_uppercase()) {
-                Some(ref u) if u == "K" => 1024,
-                Some(ref u) if u == "M" => 1024 * 1024,
-                _ => 1,
-            };
-
-            let bytes_length = (units * unit_size) as usize;
-            if bytes_length & (bytes_length - 1) == 0 {
-                Ok((units * unit_size) as usize)
-            } else {
-                Err(anyhow!("Should be a power of two."))
+            let units = groups[1].parse::<u64>().context("Not a number.")?;
+            match groups.get(2).map(|m| m.as_str().to_lowercase()) {
+                Some(ref u) if u == "ms" => Ok(Duration::from_millis(units)),
+                Some(ref u) if u == "m" => Ok(Duration::from_secs(units * 60)),
+                _ => Ok(Duration::from_secs(units)),
             }
         }
         _ => Err(anyhow!(
-            "Use a number of bytes with optional scale (e.g. 4096, 128k or 2M)."
+            "Use a number of seconds with optional units (e.g. 30, 500ms, 10s or 2m)."
         )),
     }
 }
@@ -31,6 +138,7 @@ pub fn parse_block_size(s: &str) -> Result<usize> {
 mod test {
     use super::*;
     use assert_matches::*;
+    use proptest::prelude::*;
 
     #[test]
     fn test_block_size_parser_good() {
@@ -52,4 +160,134 @@ mod test {
         assert_matches!(parse_block_size("4096.000"), Err(_));
         assert_matches!(parse_block_size("4095"), Err(_));
     }
+
+    #[test]
+    fn test_size_parser() {
+        assert_eq!(parse_size("0").unwrap(), 0);
+        assert_eq!(parse_size("1000").unwrap(), 1000);
+        assert_eq!(parse_size("512M").unwrap(), 512 * 1024 * 1024);
+        assert_eq!(parse_size("2gb").unwrap(), 2_000_000_000);
+        assert_eq!(parse_size("2GiB").unwrap(), 2 * 1024 * 1024 * 1024);
+        assert_eq!(parse_size("3p").unwrap(), 3 << 50);
+        assert_eq!(parse_size("3PB").unwrap(), 3_000_000_000_000_000);
+        assert_matches!(parse_size(""), Err(_));
+        assert_matches!(parse_size("-1G"), Err(_));
+        assert_matches!(parse_size("99999999999T"), Err(_));
+    }
+
+    #[test]
+    fn test_size_parser_relative_units() {
+        let units = SizeUnits {
+            sector_size: Some(4096),
+            total_size: Some(1_000_000),
+        };
+
+        assert_eq!(parse_size_in("2048s", units).unwrap(), 2048 * 4096);
+        assert_eq!(parse_size_in("10%", units).unwrap(), 100_000);
+        assert_eq!(parse_size_in("12.5%", units).unwrap(), 125_000);
+        assert_eq!(parse_size_in("100%", units).unwrap(), 1_000_000);
+        assert_eq!(parse_size_in("0.0001%", units).unwrap(), 1);
+        assert_eq!(parse_block_size_in("8s", units).unwrap(), 32768);
+        assert_matches!(parse_size_in("101%", units), Err(_));
+        assert_matches!(parse_size_in("1.5s", units), Err(_));
+        assert_matches!(parse_block_size_in("3s", units), Err(_));
+    }
+
+    #[test]
+    fn test_size_parser_errors() {
+        let message = |s: &str| format!("{}", parse_size(s).unwrap_err());
+
+        assert_eq!(message("1.5G"), "Only percentages can be fractional.");
+        assert_eq!(message("10%"), "Percentages are not supported here.");
+        assert_eq!(message("2048s"), "Sector units are not supported here.");
+        assert!(message("5x").starts_with("Unknown unit x."));
+        assert!(message("5KiBB").starts_with("Unknown unit KiBB."));
+        assert_eq!(message("k"), SIZE_FORMAT_HELP);
+        assert_eq!(message("99999999999999999999"), "The value is too big.");
+        assert_eq!(message("16384P"), "The value is too big.");
+        assert_matches!(parse_block_size("0"), Err(_));
+    }
+
+    proptest! {
+        #[test]
+        fn test_size_parser_units(value: u64, unit in 0usize..5) {
+            let (iec, si) = [("K", "kB"), ("M", "MB"), ("G", "GB"), ("T", "TB"), ("P", "PB")][unit];
+            let exponent = unit as u32 + 1;
+            let expected = |base: u64| match value.checked_mul(base.pow(exponent)) {
+                Some(size) => Ok(size),
+                None => Err(String::from("The value is too big.")),
+            };
+            let parsed = |s: String| parse_size(&s).map_err(|e| e.to_string());
+
+            prop_assert_eq!(parsed(format!("{}{}", value, iec)), expected(1024));
+            prop_assert_eq!(parsed(format!("{} {}iB", value, iec)), expected(1024));
+            prop_assert_eq!(parsed(format!("{}{}", value, si)), expected(1000));
+        }
+
+        #[test]
+        fn test_size_parser_roundtrip(value: u64) {
+            prop_assert_eq!(parse_size(&value.to_string()).unwrap(), value);
+        }
+
+        #[test]
+        fn test_percentage_within_device(percent in 0u32..=10000, total: u64) {
+            let units = SizeUnits { sector_size: None, total_size: Some(total) };
+            let size = parse_size_in(&format!("{}.{:02}%", percent / 100, percent % 100), units).unwrap();
+
+            prop_assert!(size <= total);
+            prop_assert_eq!(size == total, percent == 10000 || total == 0);
+        }
+
+        #[test]
+        fn test_block_size_parser_powers_of_two(exponent in 0u32..63) {
+            prop_assert_eq!(parse_block_size(&(1u64 << exponent).to_string()).unwrap(), 1usize << exponent);
+            if exponent > 1 {
+                prop_assert!(parse_block_size(&((1u64 << exponent) + 1).to_string()).is_err());
+            }
+        }
+
+        #[test]
+        fn test_size_parser_never_panics(s in "\\PC*") {
+            let _ = parse_size_in(&s, SizeUnits { sector_size: Some(512), total_size: Some(1 << 40) });
+            let _ = parse_rate(&s);
+        }
+    }
+
+    #[test]
+    fn test_duration_parser_good() {
+        assert_eq!(parse_duration("30").unwrap(), Duration::from_secs(30));
+        assert_eq!(parse_duration("30s").unwrap(), Duration::from_secs(30));
+        assert_eq!(parse_duration("500ms").unwrap(), Duration::from_millis(500));
+        assert_eq!(parse_duration("500MS").unwrap(), Duration::from_millis(500));
+        assert_eq!(parse_duration("2m").unwrap(), Duration::from_secs(120));
+    }
+
+    #[test]
+    fn test_duration_parser_bad() {
+        assert_matches!(parse_duration(""), Err(_));
+        assert_matches!(parse_duration("xxx"), Err(_));
+        assert_matches!(parse_duration("-1s"), Err(_));
+        assert_matches!(parse_duration("1.5s"), Err(_));
+        assert_matches!(parse_duration("1h"), Err(_));
+    }
+
+    #[test]
+    fn test_rate_parser_good() {
+        assert_eq!(parse_rate("0").unwrap(), None);
+        assert_eq!(parse_rate("4096").unwrap(), Some(4096));
+        assert_eq!(parse_rate("500k").unwrap(), Some(500 * 1024));
+        assert_eq!(parse_rate("200M").unwrap(), Some(200 * 1024 * 1024));
+        assert_eq!(parse_rate("200MB/s").unwrap(), Some(200_000_000));
+        assert_eq!(parse_rate("200MiB/s").unwrap(), Some(200 * 1024 * 1024));
+        assert_eq!(parse_rate("1g").unwrap(), Some(1024 * 1024 * 1024));
+    }
+
+    #[test]
+    fn test_rate_parser_bad() {
+        assert_matches!(parse_rate(""), Err(_));
+        assert_matches!(parse_rate("fast"), Err(_));
+        assert_matches!(parse_rate("-1M"), Err(_));
+        assert_matches!(parse_rate("1.5M"), Err(_));
+        assert_matches!(parse_rate("99999999999T"), Err(_));
+    }
 }
@@ -0,0 +1,189 @@
+use crate::actions::ThroughputProfile;
+use anyhow::{Context, Result};
+use indicatif::HumanBytes;
+use std::path::Path;
+
+const SPARKLINE_LEVELS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+const CHART_WIDTH: f64 = 800.0;
+const CHART_HEIGHT: f64 = 300.0;
+const CHART_MARGIN: f64 = 60.0;
+const CHART_COLORS: [&str; 6] = [
+    "#d62728", "#1f77b4", "#2ca02c", "#ff7f0e", "#9467bd", "#8c564b",
+];
+
+/// One character per bucket, scaled to the fastest bucket. Buckets without
+/// measurements are left blank.
+pub fn sparkline(profile: &ThroughputProfile) -> String {
+    let max = profile.range().map(|(_, max)| max).unwrap_or(0.0);
+
+    profile
+        .buckets()
+        .iter()
+        .map(|(_, _, throughput)| match throughput {
+            Some(t) if max > 0.0 => {
+                let level = (t / max * (SPARKLINE_LEVELS.len() - 1) as f64).round() as usize;
+                SPARKLINE_LEVELS[level.min(SPARKLINE_LEVELS.len() - 1)]
+            }
+            _ => ' ',
+        })
+        .collect()
+}
+
+/// Line chart of the throughput over the LBA range, one line per labeled profile.
+pub fn render_svg(series: &[(String, ThroughputProfile)]) -> String {
+    let total_size = series
+        .iter()
+        .flat_map(|(_, p)| p.buckets().last().map(|b| b.1))
+        .max()
+        .unwrap_or(0)
+        .max(1);
+    let max = series
+        .iter()
+        .filter_map(|(_, p)| p.range())
+        .map(|(_, max)| max)
+        .fold(0.0, f64::max);
+    let plot_width = CHART_WIDTH - CHART_MARGIN * 2.0;
+    let plot_height = CHART_HEIGHT - CHART_MARGIN * 2.0;
+
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\" font-family=\"sans-serif\" font-size=\"12\">\n",
+        w = CHART_WIDTH,
+        h = CHART_HEIGHT
+    );
+    svg.push_str(&format!(
+        "<rect x=\"{m}\" y=\"{m}\" width=\"{}\" height=\"{}\" fill=\"none\" stroke=\"#999\"/>\n",
+        plot_width,
+        plot_height,
+        m = CHART_MARGIN
+    ));
+    svg.push_str(&format!(
+        "<text x=\"{}\" y=\"{}\" text-anchor=\"end\">{}/s</text>\n",
+        CHART_MARGIN - 4.0,
+        CHART_MARGIN + 4.0,
+        HumanBytes(max as u64)
+    ));
+    svg.push_str(&format!(
+        "<text x=\"{}\" y=\"{}\" text-anchor=\"end\">0</text>\n",
+        CHART_MARGIN - 4.0,
+        CHART_HEIGHT - CHART_MARGIN + 4.0
+    ));
+    svg.push_str(&format!(
+        "<text x=\"{}\" y=\"{}\" text-anchor=\"end\">{}</text>\n",
+        CHART_WIDTH - CHART_MARGIN,
+        CHART_HEIGHT - CHART_MARGIN + 16.0,
+        HumanBytes(total_size)
+    ));
+
+    for (i, (label, profile)) in series.iter().enumerate() {
+        let color = CHART_COLORS[i % CHART_COLORS.len()];
+
+        // unmeasured buckets break the line
+        let mut segments: Vec<Vec<String>> = vec![vec![]];
+        for (start, end, throughput) in profile.buckets() {
+            match throughput {
+                Some(t) if max > 0.0 => {
+                    let x =
+                        CHART_MARGIN + (start + end) as f64 / 2.0 / total_size as f64 * plot_width;
+                    let y = CHART_HEIGHT - CHART_MARGIN - t / max * plot_height;
+                    segments
+                        .last_mut()
+                        .unwrap()
+                        .push(format!("{:.1},{:.1}", x, y));
+                }
+                _ => segments.push(vec![]),
+            }
+        }
+        for points in segments.iter().filter(|s| !s.is_empty()) {
+            svg.push_str(&format!(
+                "<polyline points=\"{}\" fill=\"none\" stroke=\"{}\" stroke-width=\"2\"/>\n",
+                points.join(" "),
+                color
+            ));
+        }
+
+        svg.push_str(&format!(
+            "<text x=\"{}\" y=\"{}\" fill=\"{}\">{}</text>\n",
+            CHART_MARGIN + (i % 3) as f64 * plot_width / 3.0,
+            CHART_MARGIN / 2.0 + (i / 3) as f64 * 14.0 - 6.0,
+            color,
+            escape(label)
+        ));
+    }
+
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// Saves the chart as an HTML page if the file name ends with `.html`, otherwise as SVG.
+pub fn save_chart<P: AsRef<Path>>(
+    path: P,
+    title: &str,
+    series: &[(String, ThroughputProfile)],
+) -> Result<()> {
+    let svg = render_svg(series);
+    let is_html = path
+        .as_ref()
+        .extension()
+        .map(|e| e.eq_ignore_ascii_case("html") || e.eq_ignore_ascii_case("htm"))
+        .unwrap_or(false);
+
+    let content = if is_html {
+        format!(
+            "<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>{t}</title></head>\n<body>\n<h3>{t}</h3>\n{}</body>\n</html>\n",
+            svg,
+            t = escape(title)
+        )
+    } else {
+        svg
+    };
+
+    std::fs::write(path, content).context("Unable to save the throughput chart")
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    fn profile(durations: &[u64]) -> ThroughputProfile {
+        let mut profile = ThroughputProfile::new(durations.len() as u64 * 1000, durations.len());
+        let mut at = Instant::now();
+        profile.record(0, at);
+        for (i, d) in durations.iter().enumerate() {
+            at += Duration::from_millis(*d);
+            if *d == 0 {
+                profile.interrupt();
+            }
+            profile.record((i as u64 + 1) * 1000, at);
+        }
+        profile
+    }
+
+    #[test]
+    fn test_sparkline() {
+        assert_eq!("█▅▂", sparkline(&profile(&[100, 200, 800])));
+        assert_eq!("    ", sparkline(&ThroughputProfile::new(4000, 4)));
+    }
+
+    #[test]
+    fn test_svg_chart() {
+        let series = vec![
+            (String::from("Stage 1/2 <write>"), profile(&[100, 200, 800])),
+            (String::from("Stage 2/2"), profile(&[100, 0, 100])),
+        ];
+        let svg = render_svg(&series);
+
+        assert!(svg.starts_with("<svg"));
+        assert!(svg.contains("Stage 1/2 &lt;write&gt;"));
+        // the unmeasured bucket splits the second line
+        assert_eq!(3, svg.matches("<polyline").count());
+        assert!(svg.contains("<polyline points=\"173.3,60.0 "));
+    }
+}
@@ -1,17 +1,62 @@
 use std::io::ErrorKind;
+use std::rc::Rc;
 use std::time::Instant;
+use thiserror::Error;
 
 use indicatif::{HumanBytes, HumanDuration, ProgressBar, ProgressStyle};
 
-use crate::actions::{WipeEvent, WipeEventReceiver, WipeState, WipeTask};
+use crate::actions::{
+    BlockSizeBenchmark, ChunkClass, LatencyHistogram, ScanTask, ShredTarget, ShredTargetKind,
+    ShredTask, ThroughputProfile, WipeError, WipeEvent, WipeEventReceiver, WipeState, WipeTask,
+    SCAN_CHUNK_SIZE,
+};
+use crate::sanitization::Stage;
 use crate::sanitization::{Scheme, SchemeRepo};
-use crate::stage::Stage;
+use crate::storage::{
+    ContentInfo, Extent, HealthReport, HiddenAreas, OpalDiscovery, ScsiError, StorageError,
+    StorageRef,
+};
+use crate::ui::chart;
 use prettytable::format::FormatBuilder;
 use prettytable::Table;
 use std::thread::sleep;
 
 const RETRY_BACKOFF_SECONDS: u32 = 3;
 
+pub const EXIT_FAILURE: i32 = 1;
+pub const EXIT_BAD_BLOCK_LIMIT: i32 = 2;
+pub const EXIT_CANCELLED: i32 = 3;
+/// Completed, but some sectors were unreadable and left as they were.
+pub const EXIT_SKIPPED_BLOCKS: i32 = 4;
+pub const EXIT_VERIFICATION_FAILED: i32 = 5;
+pub const EXIT_IO_ERROR: i32 = 6;
+pub const EXIT_PERMISSION_DENIED: i32 = 7;
+/// The confirmation was declined.
+pub const EXIT_ABORTED: i32 = 8;
+/// Nothing was done because it wasn't safe, e.g. the device is in use.
+pub const EXIT_REFUSED: i32 = 9;
+pub const EXIT_DEVICE_NOT_FOUND: i32 = 10;
+/// `scan` found data left on the device.
+pub const EXIT_DATA_FOUND: i32 = 11;
+
+/// Errors of the command itself, told apart from I/O failures by the exit code.
+#[derive(Error, Debug)]
+pub enum CommandError {
+    #[error("Unknown device {0}")]
+    DeviceNotFound(String),
+    #[error("The {0} was created for another device {1}")]
+    AnotherDevice(&'static str, String),
+    #[error("{0} is mounted at {1}, unmount it first")]
+    Mounted(String, String),
+    #[error("{0} is in use by {1}, close it first")]
+    InUse(String, String),
+}
+
+const SURVEY_REGIONS: usize = 16;
+const THROUGHPUT_REGIONS: usize = 32;
+const SURVEY_RANGES_SHOWN: usize = 20;
+
+#[derive(Default)]
 pub struct ConsoleFrontend {}
 
 impl ConsoleFrontend {
@@ -26,7 +71,137 @@ impl ConsoleFrontend {
             pb: None,
             session_started: None,
             stage_started: None,
+            stage_start_position: 0,
+            failure: None,
+            extents: vec![],
+            content: vec![],
+            hidden: None,
+            health: None,
+            throughput: vec![],
+            chart: None,
+            auto_block_size: false,
+            benchmark: None,
+            skipped: false,
+        }
+    }
+
+    pub fn sanitize_session(self, device_id: &str, auto_confirm: bool) -> ConsoleSanitizeSession {
+        ConsoleSanitizeSession {
+            device_id: String::from(device_id),
+            auto_confirm,
+            pb: None,
+            started: None,
+            failure: None,
+        }
+    }
+
+    pub fn survey_session(self, device_id: &str) -> ConsoleSurveySession {
+        ConsoleSurveySession {
+            device_id: String::from(device_id),
+            pb: None,
+            started: None,
+            profile: None,
+            failure: None,
+            unreadable: false,
+        }
+    }
+
+    pub fn shred_session(self, auto_confirm: bool) -> ConsoleShredSession {
+        ConsoleShredSession {
+            auto_confirm,
+            current: String::new(),
+            pb: None,
+            failure: None,
+        }
+    }
+
+    /// Describes the drive to be reverted to the factory state and asks for confirmation.
+    pub fn confirm_opal_revert(
+        self,
+        storage: &StorageRef,
+        discovery: &OpalDiscovery,
+        auto_confirm: bool,
+    ) -> bool {
+        let mut t = Table::new();
+        let indent_table_format = FormatBuilder::new().padding(4, 1).build();
+        t.set_format(indent_table_format);
+        t.add_row(row!["Device", storage.id]);
+        t.add_row(row!["Size", HumanBytes(storage.details.size)]);
+        t.add_row(row!["Security", discovery]);
+        print!("Reverting to factory state:\n{}", t);
+        eprintln!("⚠ The encryption key is regenerated, all data on the drive will be lost.");
+
+        auto_confirm || ask_for_confirmation()
+    }
+
+    /// Scans the storage with a progress bar and prints the report.
+    /// Returns `true` if no recognizable data was found.
+    pub fn scan(self, task: &ScanTask, storage: &StorageRef) -> anyhow::Result<bool> {
+        let pb = create_progress_bar(task.total_size);
+        let report = task.scan(storage, &mut |position| pb.set_position(position))?;
+        pb.finish_and_clear();
+
+        let mut t = Table::new();
+        let indent_table_format = FormatBuilder::new().padding(4, 1).build();
+        t.set_format(indent_table_format);
+        t.add_row(row!["Device", storage.id]);
+        t.add_row(row![
+            "Scanned",
+            format!(
+                "{} ({}%)",
+                HumanBytes(report.scanned * SCAN_CHUNK_SIZE as u64),
+                task.sample_percent
+            )
+        ]);
+        for class in &[
+            ChunkClass::Constant,
+            ChunkClass::HighEntropy,
+            ChunkClass::Data,
+            ChunkClass::Unreadable,
+        ] {
+            let count = report.count(*class);
+            t.add_row(row![
+                class,
+                format!(
+                    "{} ({:.2}%)",
+                    count,
+                    count as f64 * 100.0 / report.scanned.max(1) as f64
+                )
+            ]);
         }
+        print!(
+            "Scan results ({} chunks):\n{}",
+            HumanBytes(SCAN_CHUNK_SIZE as u64),
+            t
+        );
+
+        if !report.data_positions.is_empty() {
+            println!("Data found at:");
+            for position in &report.data_positions {
+                println!("    {}", position);
+            }
+            if report.data > report.data_positions.len() as u64 {
+                println!("    ...");
+            }
+        }
+
+        if !report.signatures.is_empty() {
+            println!("File signatures found:");
+            for (position, name) in &report.signatures {
+                println!("    {} at {}", name, position);
+            }
+            if report.signatures_found > report.signatures.len() as u64 {
+                println!("    ...");
+            }
+        }
+
+        if report.is_clean() {
+            println!("No recognizable data found.");
+        } else {
+            println!("Recognizable data found.");
+        }
+
+        Ok(report.is_clean())
     }
 
     pub fn explain_schemes(schemes: &SchemeRepo) -> String {
@@ -64,10 +239,212 @@ pub struct ConsoleWipeSession {
     pb: Option<ProgressBar>,
     session_started: Option<Instant>,
     stage_started: Option<Instant>,
+    stage_start_position: u64,
+    failure: Option<Rc<anyhow::Error>>,
+    extents: Vec<Extent>,
+    content: Vec<(String, ContentInfo)>,
+    hidden: Option<(HiddenAreas, bool)>,
+    health: Option<HealthReport>,
+    throughput: Vec<(String, ThroughputProfile)>,
+    chart: Option<String>,
+    auto_block_size: bool,
+    benchmark: Option<BlockSizeBenchmark>,
+    skipped: bool,
+}
+
+impl ConsoleWipeSession {
+    /// Lists the wiped ranges in the confirmation table, when only parts of the device are wiped.
+    pub fn with_extents(mut self, extents: &[Extent]) -> Self {
+        self.extents = extents.to_vec();
+        self
+    }
+
+    /// Lists the filesystems and containers found on the device and its partitions
+    /// in the confirmation table.
+    pub fn with_content(mut self, content: &[(String, ContentInfo)]) -> Self {
+        self.content = content.to_vec();
+        self
+    }
+
+    /// Lists the areas hidden by the drive in the confirmation table,
+    /// with a warning if they are not going to be wiped.
+    pub fn with_hidden_areas(mut self, hidden: &HiddenAreas, unlocked: bool) -> Self {
+        self.hidden = Some((hidden.clone(), unlocked));
+        self
+    }
+
+    /// Shows the drive health in the confirmation table,
+    /// the counters are compared with `report_health` after the wipe.
+    pub fn with_health(mut self, health: &HealthReport) -> Self {
+        self.health = Some(health.clone());
+        self
+    }
+
+    /// Saves the throughput of every stage over the LBA range to an SVG or HTML file
+    /// when the wipe ends.
+    pub fn with_chart(mut self, path: &str) -> Self {
+        self.chart = Some(path.to_owned());
+        self
+    }
+
+    /// Shows in the confirmation table that the block size is going to be benchmarked.
+    pub fn with_auto_block_size(mut self) -> Self {
+        self.auto_block_size = true;
+        self
+    }
+
+    /// Prints the measurements of the block size benchmark, the chosen size
+    /// is repeated in the summary.
+    pub fn report_benchmark(&mut self, benchmark: &BlockSizeBenchmark) {
+        let mut t = Table::new();
+        let indent_table_format = FormatBuilder::new().padding(4, 1).build();
+        t.set_format(indent_table_format);
+        for (block_size, throughput) in &benchmark.results {
+            t.add_row(row![
+                HumanBytes(*block_size as u64),
+                format!(
+                    "{}/s{}",
+                    HumanBytes(*throughput as u64),
+                    if *block_size == benchmark.block_size {
+                        " (chosen)"
+                    } else {
+                        ""
+                    }
+                )
+            ]);
+        }
+        print!("Block size benchmark:\n{}", t);
+        self.benchmark = Some(benchmark.clone());
+    }
+
+    pub fn report_benchmark_skipped(&self, task: &WipeTask, reason: &str) {
+        eprintln!(
+            "⚠ Block size benchmark skipped ({}), using {} blocks.",
+            reason,
+            HumanBytes(task.block_size as u64)
+        );
+    }
+
+    /// Prints the drive health after the wipe and warns about the error counters
+    /// which increased while wiping.
+    pub fn report_health(&self, after: &HealthReport) {
+        println!("Health: {}", describe_health(after));
+        if let Some(before) = &self.health {
+            for (name, old, new) in after.degraded_since(before) {
+                eprintln!("⚠ {} increased during the wipe: {} -> {}", name, old, new);
+            }
+        }
+        if after.passed == Some(false) {
+            eprintln!("⚠ The drive reports it's failing.");
+        }
+    }
+
+    /// The wipe was stopped in a consistent state and can be continued from a checkpoint.
+    pub fn is_resumable(&self) -> bool {
+        matches!(
+            self.failure.as_ref().and_then(|e| wipe_error(e)),
+            Some(WipeError::Cancelled) | Some(WipeError::IoTimeout(_))
+        )
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        match &self.failure {
+            Some(e) => exit_code_of(e),
+            None if self.skipped => EXIT_SKIPPED_BLOCKS,
+            None => 0,
+        }
+    }
+}
+
+/// The device is mounted or opened exclusively by someone else.
+#[cfg(unix)]
+fn is_busy(error: &std::io::Error) -> bool {
+    error.raw_os_error() == Some(libc::EBUSY)
+}
+
+/// The device is mounted or opened exclusively by someone else.
+#[cfg(windows)]
+fn is_busy(error: &std::io::Error) -> bool {
+    use winapi::shared::winerror::{ERROR_LOCK_VIOLATION, ERROR_SHARING_VIOLATION};
+    matches!(
+        error.raw_os_error(),
+        Some(code) if code == ERROR_SHARING_VIOLATION as i32 || code == ERROR_LOCK_VIOLATION as i32
+    )
+}
+
+/// What to do next, matching the exit code of the failure.
+fn describe_wipe_failure(error: &WipeError) -> &'static str {
+    match error {
+        WipeError::BadBlockLimitExceeded(_) => {
+            "The device is failing, consider physical destruction."
+        }
+        WipeError::VerificationFailed => {
+            "The data read back doesn't match the written pattern. Check the connection and the block size, then wipe again."
+        }
+        WipeError::IoTimeout(_) => {
+            "The device stopped responding. Check the connection and the power supply, then try again."
+        }
+        WipeError::Cancelled => "The wipe was cancelled.",
+    }
+}
+
+fn wipe_error(error: &anyhow::Error) -> Option<&WipeError> {
+    error.chain().find_map(|c| c.downcast_ref::<WipeError>())
+}
+
+/// Exit code for the class of the failure, found by the first known error in the chain.
+pub fn exit_code_of(error: &anyhow::Error) -> i32 {
+    let io_exit_code = |e: &std::io::Error| match e.kind() {
+        ErrorKind::PermissionDenied => EXIT_PERMISSION_DENIED,
+        _ if is_busy(e) => EXIT_REFUSED,
+        _ => EXIT_IO_ERROR,
+    };
+
+    for cause in error.chain() {
+        if let Some(e) = cause.downcast_ref::<WipeError>() {
+            return match e {
+                WipeError::BadBlockLimitExceeded(_) => EXIT_BAD_BLOCK_LIMIT,
+                WipeError::Cancelled => EXIT_CANCELLED,
+                WipeError::VerificationFailed => EXIT_VERIFICATION_FAILED,
+                WipeError::IoTimeout(_) => EXIT_IO_ERROR,
+            };
+        }
+        if let Some(e) = cause.downcast_ref::<CommandError>() {
+            return match e {
+                CommandError::DeviceNotFound(_) => EXIT_DEVICE_NOT_FOUND,
+                CommandError::AnotherDevice(_, _) => EXIT_REFUSED,
+                CommandError::Mounted(_, _) => EXIT_REFUSED,
+                CommandError::InUse(_, _) => EXIT_REFUSED,
+            };
+        }
+        if let Some(e) = cause.downcast_ref::<StorageError>() {
+            return match e {
+                StorageError::Other(ioe) => io_exit_code(ioe),
+                _ => EXIT_IO_ERROR,
+            };
+        }
+        if let Some(e) = cause.downcast_ref::<std::io::Error>() {
+            return io_exit_code(e);
+        }
+        if cause.downcast_ref::<ScsiError>().is_some() {
+            return EXIT_IO_ERROR;
+        }
+    }
+
+    EXIT_FAILURE
 }
 
 impl WipeEventReceiver for ConsoleWipeSession {
     fn handle(&mut self, task: &WipeTask, state: &WipeState, event: WipeEvent) -> () {
+        // saved even if the wipe failed, the chart may show where the drive struggled
+        if let (WipeEvent::Completed(_), Some(path)) = (&event, &self.chart) {
+            let title = format!("Throughput of {}", self.device_id);
+            match chart::save_chart(path, &title, &self.throughput) {
+                Ok(()) => println!("Throughput chart saved to {}.", path),
+                Err(err) => eprintln!("⚠ {:#}", err),
+            }
+        }
+
         match event {
             WipeEvent::Created => {
                 let mut t = Table::new();
@@ -75,17 +452,83 @@ impl WipeEventReceiver for ConsoleWipeSession {
                 t.set_format(indent_table_format);
                 t.add_row(row!["Device", self.device_id]);
                 t.add_row(row!["Size", HumanBytes(task.total_size)]);
+                if !self.content.is_empty() {
+                    let content: Vec<String> = self
+                        .content
+                        .iter()
+                        .map(|(id, c)| format!("{}: {}", id, c))
+                        .collect();
+                    t.add_row(row!["Content", content.join("\n")]);
+                }
+                if let Some(health) = &self.health {
+                    t.add_row(row!["Health", describe_health(health)]);
+                }
+                if let Some((hidden, unlocked)) = &self.hidden {
+                    let mut areas = vec![];
+                    if hidden.hpa_size() > 0 {
+                        areas.push(format!(
+                            "HPA {}{}",
+                            HumanBytes(hidden.hpa_size()),
+                            if *unlocked { " (unlocked)" } else { "" }
+                        ));
+                    }
+                    if hidden.dco_size() > 0 {
+                        areas.push(format!("DCO {}", HumanBytes(hidden.dco_size())));
+                    }
+                    t.add_row(row!["Hidden areas", areas.join("\n")]);
+                }
+                if !self.extents.is_empty() {
+                    let extents: Vec<String> = self
+                        .extents
+                        .iter()
+                        .map(|e| format!("{}-{} ({})", e.start, e.end() - 1, HumanBytes(e.length)))
+                        .collect();
+                    t.add_row(row!["Extents", extents.join("\n")]);
+                }
                 t.add_row(row![
                     "Scheme",
                     ConsoleFrontend::describe_scheme(&task.scheme)
                 ]);
-                t.add_row(row!["Block size", HumanBytes(task.block_size as u64)]);
+                if self.auto_block_size {
+                    t.add_row(row!["Block size", "auto (benchmarked before wiping)"]);
+                } else {
+                    t.add_row(row!["Block size", HumanBytes(task.block_size as u64)]);
+                }
+                t.add_row(row!["Sector size", HumanBytes(task.sector_size as u64)]);
                 t.add_row(row!["Verification", task.verify]);
+                if state.stage > 0 || state.position > 0 {
+                    t.add_row(row![
+                        "Resume from",
+                        format!(
+                            "Stage {}/{}{} at {}",
+                            state.stage + 1,
+                            task.scheme.stages.len(),
+                            if state.at_verification {
+                                " (verification)"
+                            } else {
+                                ""
+                            },
+                            HumanBytes(state.position)
+                        )
+                    ]);
+                }
+                if let Some(limit) = task.rate_limit.get() {
+                    t.add_row(row!["Rate limit", format!("{}/s", HumanBytes(limit))]);
+                }
                 print!("Wiping:\n{}", t);
 
+                if let Some((hidden, unlocked)) = &self.hidden {
+                    if hidden.hpa_size() > 0 && !unlocked {
+                        eprintln!("⚠ The Host Protected Area is not going to be wiped. Use --unlock-hidden-areas to include it.");
+                    }
+                    if hidden.dco_size() > 0 {
+                        eprintln!("⚠ The Device Configuration Overlay is not going to be wiped, it can only be removed permanently.");
+                    }
+                }
+
                 if !self.auto_confirm && !ask_for_confirmation() {
                     println!("Aborted.");
-                    std::process::exit(0);
+                    std::process::exit(EXIT_ABORTED);
                 }
             }
             WipeEvent::Started => {
@@ -116,15 +559,91 @@ impl WipeEventReceiver for ConsoleWipeSession {
 
                 self.pb = Some(pb);
                 self.stage_started = Some(Instant::now());
+                self.stage_start_position = state.position;
+
+                // a retried stage continues the same measurements
+                let label = if !state.at_verification {
+                    format!("{} {}", stage_num, stage_description)
+                } else {
+                    format!("{} Verification", stage_num)
+                };
+                match self.throughput.last_mut() {
+                    Some((l, profile)) if *l == label => profile.interrupt(),
+                    _ => self.throughput.push((
+                        label,
+                        ThroughputProfile::new(task.total_size, THROUGHPUT_REGIONS),
+                    )),
+                }
+                if let Some((_, profile)) = self.throughput.last_mut() {
+                    profile.record(state.position, Instant::now());
+                }
             }
             WipeEvent::Progress(position) => {
+                if let Some((_, profile)) = self.throughput.last_mut() {
+                    profile.record(position, Instant::now());
+                }
                 if let Some(pb) = &self.pb {
                     pb.set_position(position);
+
+                    if let Some(s) = self.stage_started {
+                        let elapsed = s.elapsed().as_secs_f64();
+                        if elapsed >= 1.0 {
+                            let action = if state.at_verification {
+                                "Checking"
+                            } else {
+                                "Writing"
+                            };
+                            let throughput = (position.saturating_sub(self.stage_start_position))
+                                as f64
+                                / elapsed;
+                            let message = match task.rate_limit.get() {
+                                Some(limit) => format!(
+                                    "{} {}/s (limit {}/s)",
+                                    action,
+                                    HumanBytes(throughput as u64),
+                                    HumanBytes(limit)
+                                ),
+                                None => format!("{} {}/s", action, HumanBytes(throughput as u64)),
+                            };
+                            pb.set_message(&message);
+                        }
+                    }
                 }
             }
             WipeEvent::MarkedBlockAsBad(block) => {
                 if let Some(pb) = &self.pb {
-                    pb.println(format!("Unable to access block at {}. Skipping.", block));
+                    pb.println(format!("Unable to access sector at {}. Skipping.", block));
+                }
+            }
+            WipeEvent::SlowBlock(position, latency) => {
+                if let Some(pb) = &self.pb {
+                    pb.println(format!(
+                        "Slow I/O at {} ({} ms).",
+                        position,
+                        latency.as_millis()
+                    ));
+                }
+            }
+            WipeEvent::Paused(position) => {
+                if let Some((_, profile)) = self.throughput.last_mut() {
+                    profile.interrupt();
+                }
+                if let Some(pb) = &self.pb {
+                    pb.println(format!(
+                        "Paused at {}. Send SIGUSR2 or SIGCONT to resume.",
+                        position
+                    ));
+                    pb.set_message("Paused");
+                }
+            }
+            WipeEvent::Resumed => {
+                if let Some(pb) = &self.pb {
+                    pb.println("Resumed.");
+                }
+            }
+            WipeEvent::BadBlockLimitExceeded(limit) => {
+                if let Some(pb) = &self.pb {
+                    pb.println(format!("Bad block limit exceeded: {}.", limit));
                 }
             }
             WipeEvent::StageCompleted(result) => {
@@ -159,41 +678,528 @@ impl WipeEventReceiver for ConsoleWipeSession {
                         println!("✔ Total time: {}", elapsed);
                     }
                     let total_blocks = task.total_size / task.block_size as u64;
-                    let bad_blocks = state.bad_blocks.borrow_mut().total_marked();
+                    let bad_sectors = state.bad_blocks.borrow().total_marked();
+                    let skipped_bytes = task.skipped_bytes(state);
+                    self.skipped = skipped_bytes > 0;
 
                     let mut t = Table::new();
                     let indent_table_format = FormatBuilder::new().padding(4, 1).build();
                     t.set_format(indent_table_format);
                     t.add_row(row!["Total device size", HumanBytes(task.total_size)]);
                     t.add_row(row!["Total blocks", total_blocks]);
+                    if self.benchmark.is_some() {
+                        t.add_row(row![
+                            "Block size",
+                            format!("{} (auto)", HumanBytes(task.block_size as u64))
+                        ]);
+                    }
+                    t.add_row(row!["Skipped sectors", bad_sectors]);
                     t.add_row(row![
-                        "Skipped blocks",
+                        "Unwiped bytes",
                         format!(
-                            "{} ({}%)",
-                            bad_blocks,
-                            bad_blocks * 100 / total_blocks as u32
+                            "{} ({:.2}%)",
+                            skipped_bytes,
+                            skipped_bytes as f64 * 100.0 / task.total_size.max(1) as f64
                         )
                     ]);
 
+                    t.add_row(row![
+                        "Slow sectors",
+                        state.slow_blocks.borrow().total_marked()
+                    ]);
+                    t.add_row(row!["I/O latency", describe_latency(&state.latency)]);
+                    if !self.throughput.is_empty() {
+                        t.add_row(row![
+                            "Throughput",
+                            describe_stage_throughput(&self.throughput)
+                        ]);
+                    }
+
                     print!("{}", t);
                 }
+                Some(e) if matches!(wipe_error(&e), Some(WipeError::Cancelled)) => {
+                    eprintln!(
+                        "Wipe cancelled at stage {}/{}, position {}.",
+                        state.stage + 1,
+                        task.scheme.stages.len(),
+                        state.position
+                    );
+                    self.failure = Some(e);
+                }
+                Some(e) if wipe_error(&e).is_some() => {
+                    eprintln!("❌ {:#}", e);
+                    if let Some(advice) = wipe_error(&e).map(describe_wipe_failure) {
+                        eprintln!("{}", advice);
+                    }
+                    self.failure = Some(e);
+                }
                 Some(e) => {
                     eprintln!("❌ Unexpected error: {:#}", e);
 
                     if let Some(ioe) = e.downcast_ref::<std::io::Error>() {
-                        if ioe.kind() == ErrorKind::Other && ioe.raw_os_error() == Some(16) {
+                        if is_busy(ioe) {
                             eprintln!("Make sure the drive is not mounted.")
                         }
                     };
+                    self.failure = Some(e);
+                }
+            },
+            WipeEvent::Fatal(err) => {
+                eprintln!("❌ Fatal: {:#}", err);
+            }
+        }
+    }
+}
+
+/// Frontend of the sanitize operations performed by the device itself.
+pub struct ConsoleSanitizeSession {
+    device_id: String,
+    auto_confirm: bool,
+    pb: Option<ProgressBar>,
+    started: Option<Instant>,
+    failure: Option<Rc<anyhow::Error>>,
+}
+
+impl ConsoleSanitizeSession {
+    pub fn exit_code(&self) -> i32 {
+        match &self.failure {
+            Some(e) => exit_code_of(e),
+            None => 0,
+        }
+    }
+}
+
+impl WipeEventReceiver for ConsoleSanitizeSession {
+    fn handle(&mut self, task: &WipeTask, _state: &WipeState, event: WipeEvent) {
+        match event {
+            WipeEvent::Created => {
+                let mut t = Table::new();
+                let indent_table_format = FormatBuilder::new().padding(4, 1).build();
+                t.set_format(indent_table_format);
+                t.add_row(row!["Device", self.device_id]);
+                t.add_row(row!["Size", HumanBytes(task.total_size)]);
+                t.add_row(row!["Method", task.scheme.description]);
+                print!("Sanitizing:\n{}", t);
+                eprintln!("⚠ The operation can't be stopped once started, the device stays unusable until it completes.");
+
+                if !self.auto_confirm && !ask_for_confirmation() {
+                    println!("Aborted.");
+                    std::process::exit(EXIT_ABORTED);
+                }
+            }
+            WipeEvent::StageStarted => {
+                let pb = create_progress_bar(task.total_size);
+                pb.set_message("Sanitizing");
+                self.pb = Some(pb);
+                self.started = Some(Instant::now());
+            }
+            WipeEvent::Progress(position) => {
+                if let Some(pb) = &self.pb {
+                    pb.set_position(position);
+                }
+            }
+            WipeEvent::StageCompleted(_) => {
+                if let Some(pb) = self.pb.take() {
+                    pb.finish_and_clear();
+                }
+            }
+            WipeEvent::Completed(result) => match result {
+                None => {
+                    if let Some(s) = self.started {
+                        println!("✔ Completed in {}", HumanDuration(s.elapsed()));
+                    }
+                }
+                Some(e) => {
+                    eprintln!("❌ {:#}", e);
+                    self.failure = Some(e);
                 }
             },
+            _ => (),
+        }
+    }
+}
+
+/// Frontend of a read-only survey, reports the bad and slow sectors
+/// and the throughput over the LBA range.
+pub struct ConsoleSurveySession {
+    device_id: String,
+    pb: Option<ProgressBar>,
+    started: Option<Instant>,
+    profile: Option<ThroughputProfile>,
+    failure: Option<Rc<anyhow::Error>>,
+    unreadable: bool,
+}
+
+impl ConsoleSurveySession {
+    pub fn exit_code(&self) -> i32 {
+        match &self.failure {
+            Some(e) => exit_code_of(e),
+            None if self.unreadable => EXIT_SKIPPED_BLOCKS,
+            None => 0,
+        }
+    }
+}
+
+impl WipeEventReceiver for ConsoleSurveySession {
+    fn handle(&mut self, task: &WipeTask, state: &WipeState, event: WipeEvent) {
+        match event {
+            WipeEvent::Created => {
+                let mut t = Table::new();
+                let indent_table_format = FormatBuilder::new().padding(4, 1).build();
+                t.set_format(indent_table_format);
+                t.add_row(row!["Device", self.device_id]);
+                t.add_row(row!["Size", HumanBytes(task.total_size)]);
+                t.add_row(row!["Block size", HumanBytes(task.block_size as u64)]);
+                t.add_row(row!["Sector size", HumanBytes(task.sector_size as u64)]);
+                if let Some(threshold) = task.slow_io_threshold {
+                    t.add_row(row!["Slow I/O", format!("{:?}", threshold)]);
+                }
+                print!("Surveying:\n{}", t);
+            }
+            WipeEvent::StageStarted => {
+                let pb = create_progress_bar(task.total_size);
+                pb.set_message("Reading");
+                self.pb = Some(pb);
+                self.started = Some(Instant::now());
+                let mut profile = ThroughputProfile::new(task.total_size, SURVEY_REGIONS);
+                profile.record(state.position, Instant::now());
+                self.profile = Some(profile);
+            }
+            WipeEvent::Progress(position) => {
+                if let Some(profile) = &mut self.profile {
+                    profile.record(position, Instant::now());
+                }
+                if let Some(pb) = &self.pb {
+                    pb.set_position(position);
+                }
+            }
+            WipeEvent::MarkedBlockAsBad(block) => {
+                if let Some(pb) = &self.pb {
+                    pb.println(format!("Unable to read sector at {}.", block));
+                }
+            }
+            WipeEvent::SlowBlock(position, latency) => {
+                if let Some(pb) = &self.pb {
+                    pb.println(format!(
+                        "Slow I/O at {} ({} ms).",
+                        position,
+                        latency.as_millis()
+                    ));
+                }
+            }
+            WipeEvent::Paused(position) => {
+                if let Some(profile) = &mut self.profile {
+                    profile.interrupt();
+                }
+                if let Some(pb) = &self.pb {
+                    pb.println(format!(
+                        "Paused at {}. Send SIGUSR2 or SIGCONT to resume.",
+                        position
+                    ));
+                }
+            }
+            WipeEvent::BadBlockLimitExceeded(limit) => {
+                if let Some(pb) = &self.pb {
+                    pb.println(format!("Bad block limit exceeded: {}.", limit));
+                }
+            }
+            WipeEvent::StageCompleted(_) => {
+                if let Some(pb) = self.pb.take() {
+                    pb.finish_and_clear();
+                }
+            }
+            WipeEvent::Completed(result) => {
+                if let Some(s) = self.started {
+                    println!("Surveyed in {}", HumanDuration(s.elapsed()));
+                }
+                let unreadable = task.skipped_bytes(state);
+                self.unreadable = unreadable > 0;
+
+                let mut t = Table::new();
+                let indent_table_format = FormatBuilder::new().padding(4, 1).build();
+                t.set_format(indent_table_format);
+                t.add_row(row!["Surveyed", HumanBytes(state.position)]);
+                t.add_row(row![
+                    "Bad sectors",
+                    describe_sector_ranges(
+                        &state.bad_blocks.borrow().iter().collect::<Vec<_>>(),
+                        task.sector_size
+                    )
+                ]);
+                t.add_row(row![
+                    "Unreadable bytes",
+                    format!(
+                        "{} ({:.2}%)",
+                        unreadable,
+                        unreadable as f64 * 100.0 / task.total_size.max(1) as f64
+                    )
+                ]);
+                t.add_row(row![
+                    "Slow sectors",
+                    describe_sector_ranges(
+                        &state.slow_blocks.borrow().iter().collect::<Vec<_>>(),
+                        task.sector_size
+                    )
+                ]);
+                t.add_row(row!["I/O latency", describe_latency(&state.latency)]);
+                if let Some(profile) = &self.profile {
+                    t.add_row(row!["Throughput", describe_throughput(profile)]);
+                }
+                print!("{}", t);
+
+                match result {
+                    None => println!("✔ Completed"),
+                    Some(e) => {
+                        eprintln!("❌ {:#}", e);
+                        self.failure = Some(e);
+                    }
+                }
+            }
             WipeEvent::Fatal(err) => {
                 eprintln!("❌ Fatal: {:#}", err);
             }
+            _ => (),
         }
     }
 }
 
+const SHRED_TARGETS_SHOWN: usize = 20;
+
+pub struct ConsoleShredSession {
+    auto_confirm: bool,
+    current: String,
+    pb: Option<ProgressBar>,
+    failure: Option<i32>,
+}
+
+impl ConsoleShredSession {
+    pub fn confirm(&self, task: &ShredTask, targets: &[ShredTarget]) -> bool {
+        let files = targets
+            .iter()
+            .filter(|t| t.kind == ShredTargetKind::File)
+            .count();
+        let total_size: u64 = targets.iter().map(|t| t.size).sum();
+
+        let mut t = Table::new();
+        let indent_table_format = FormatBuilder::new().padding(4, 1).build();
+        t.set_format(indent_table_format);
+        for target in targets.iter().take(SHRED_TARGETS_SHOWN) {
+            t.add_row(row![
+                target.path.display(),
+                match target.kind {
+                    ShredTargetKind::File => HumanBytes(target.size).to_string(),
+                    ShredTargetKind::Directory => String::from("directory"),
+                    ShredTargetKind::Link => String::from("link"),
+                }
+            ]);
+        }
+        if targets.len() > SHRED_TARGETS_SHOWN {
+            t.add_row(row![format!(
+                "... and {} more",
+                targets.len() - SHRED_TARGETS_SHOWN
+            )]);
+        }
+        print!("Shredding:\n{}", t);
+
+        let mut t = Table::new();
+        t.set_format(indent_table_format);
+        t.add_row(row![
+            "Files",
+            format!("{} ({})", files, HumanBytes(total_size))
+        ]);
+        t.add_row(row![
+            "Scheme",
+            ConsoleFrontend::describe_scheme(&task.scheme)
+        ]);
+        t.add_row(row!["Verification", task.verify]);
+        t.add_row(row!["Renames", task.renames]);
+        print!("{}", t);
+
+        let mut warnings: Vec<_> = targets.iter().filter_map(|t| t.warning).collect();
+        warnings.dedup();
+        for w in warnings {
+            eprintln!("⚠ {} The data may be recoverable.", w);
+        }
+
+        self.auto_confirm || ask_for_confirmation()
+    }
+
+    pub fn shredding(&mut self, target: &ShredTarget) {
+        self.current = target.path.display().to_string();
+    }
+
+    pub fn shredded(&mut self, result: &anyhow::Result<()>) {
+        match result {
+            Ok(()) => println!("✔ {}", self.current),
+            Err(err) => {
+                eprintln!("❌ {}: {:#}", self.current, err);
+                self.failure.get_or_insert(exit_code_of(err));
+            }
+        }
+    }
+
+    /// Exit code of the first failed target, if any.
+    pub fn exit_code(&self) -> i32 {
+        self.failure.unwrap_or(0)
+    }
+}
+
+impl WipeEventReceiver for ConsoleShredSession {
+    fn handle(&mut self, task: &WipeTask, state: &WipeState, event: WipeEvent) {
+        match event {
+            WipeEvent::StageStarted => {
+                let pb = create_progress_bar(task.total_size);
+                pb.set_message(&format!(
+                    "{} {}/{}",
+                    if state.at_verification {
+                        "Checking"
+                    } else {
+                        "Writing"
+                    },
+                    state.stage + 1,
+                    task.scheme.stages.len()
+                ));
+                self.pb = Some(pb);
+            }
+            WipeEvent::Progress(position) => {
+                if let Some(pb) = &self.pb {
+                    pb.set_position(position);
+                }
+            }
+            WipeEvent::MarkedBlockAsBad(block) => {
+                if let Some(pb) = &self.pb {
+                    pb.println(format!(
+                        "{}: unable to access sector at {}.",
+                        self.current, block
+                    ));
+                }
+            }
+            WipeEvent::StageCompleted(_) => {
+                if let Some(pb) = self.pb.take() {
+                    pb.finish_and_clear();
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Overall assessment of the drive, short enough for the device list.
+pub fn describe_health_status(health: &HealthReport) -> &'static str {
+    match health.passed {
+        Some(true) => "passed",
+        Some(false) => "FAILED",
+        None => "unknown",
+    }
+}
+
+fn describe_health(health: &HealthReport) -> String {
+    let mut parts = vec![describe_health_status(health).to_string()];
+    let counters = [
+        (health.power_on_hours, "hours"),
+        (health.reallocated_sectors, "reallocated"),
+        (health.pending_sectors, "pending"),
+        (health.uncorrectable_sectors, "uncorrectable"),
+        (health.media_errors, "media errors"),
+    ];
+    for (value, name) in counters.iter() {
+        if let Some(v) = value {
+            parts.push(format!("{} {}", v, name));
+        }
+    }
+    parts.join(", ")
+}
+
+/// Count of the marked sectors followed by the first ranges of adjacent ones as byte offsets.
+fn describe_sector_ranges(sectors: &[u64], sector_size: usize) -> String {
+    let sector_size = sector_size as u64;
+    let mut ranges: Vec<(u64, u64)> = vec![];
+    for s in sectors {
+        match ranges.last_mut() {
+            Some((_, end)) if *end + 1 == *s => *end = *s,
+            _ => ranges.push((*s, *s)),
+        }
+    }
+
+    let mut s = format!("{}\n", sectors.len());
+    for (start, end) in ranges.iter().take(SURVEY_RANGES_SHOWN) {
+        s.push_str(&format!(
+            "{}-{} ({})\n",
+            start * sector_size,
+            (end + 1) * sector_size - 1,
+            HumanBytes((end - start + 1) * sector_size)
+        ));
+    }
+    if ranges.len() > SURVEY_RANGES_SHOWN {
+        s.push_str("...\n");
+    }
+
+    s
+}
+
+fn describe_throughput(profile: &ThroughputProfile) -> String {
+    let mut s = String::new();
+
+    for (start, end, throughput) in profile.buckets() {
+        let throughput = match throughput {
+            Some(t) => format!("{}/s", HumanBytes(t as u64)),
+            None => String::from("-"),
+        };
+        s.push_str(&format!(
+            "{:>9} - {:>9}: {}\n",
+            HumanBytes(start).to_string(),
+            HumanBytes(end).to_string(),
+            throughput
+        ));
+    }
+
+    s
+}
+
+/// Sparkline of each stage with its slowest and fastest throughput.
+fn describe_stage_throughput(series: &[(String, ThroughputProfile)]) -> String {
+    let mut s = String::new();
+
+    for (label, profile) in series {
+        let range = match profile.range() {
+            Some((min, max)) => format!(
+                "{}/s - {}/s",
+                HumanBytes(min as u64),
+                HumanBytes(max as u64)
+            ),
+            None => String::from("-"),
+        };
+        s.push_str(&format!(
+            "{}: {} {}\n",
+            label,
+            chart::sparkline(profile),
+            range
+        ));
+    }
+
+    s
+}
+
+fn describe_latency(latency: &LatencyHistogram) -> String {
+    let mut s = String::new();
+
+    for (bound, count) in latency.buckets() {
+        let bucket = match bound {
+            Some(b) => format!("< {:?}", b),
+            None => String::from("more"),
+        };
+        s.push_str(&format!(
+            "{:>8}: {} ({:.2}%)\n",
+            bucket,
+            count,
+            count as f64 * 100.0 / latency.total() as f64
+        ));
+    }
+    s.push_str(&format!("max: {:?}\n", latency.max()));
+
+    s
+}
+
 fn ask_for_confirmation() -> bool {
     use std::io::prelude::*;
 
@@ -213,3 +1219,86 @@ fn create_progress_bar(size: u64) -> ProgressBar {
 
     pb
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use anyhow::Context;
+    use std::io;
+
+    #[test]
+    fn test_exit_codes_of_errors() {
+        let failed = |e: anyhow::Error| exit_code_of(&e.context("Wipe failed"));
+
+        assert_eq!(EXIT_FAILURE, failed(anyhow!("Unexpected")));
+        assert_eq!(EXIT_CANCELLED, failed(WipeError::Cancelled.into()));
+        assert_eq!(
+            EXIT_VERIFICATION_FAILED,
+            failed(WipeError::VerificationFailed.into())
+        );
+        assert_eq!(
+            EXIT_DEVICE_NOT_FOUND,
+            failed(CommandError::DeviceNotFound(String::from("/dev/sdx")).into())
+        );
+        assert_eq!(
+            EXIT_REFUSED,
+            failed(CommandError::AnotherDevice("checkpoint", String::from("/dev/sdx")).into())
+        );
+        assert_eq!(
+            EXIT_REFUSED,
+            failed(CommandError::Mounted(String::from("/dev/sdx1"), String::from("/mnt")).into())
+        );
+        assert_eq!(
+            EXIT_REFUSED,
+            failed(
+                CommandError::InUse(String::from("/dev/sdx"), String::from("/dev/mapper/x")).into()
+            )
+        );
+        assert_eq!(EXIT_IO_ERROR, failed(StorageError::BadBlock.into()));
+        assert_eq!(
+            EXIT_PERMISSION_DENIED,
+            failed(StorageError::Other(io::Error::from(io::ErrorKind::PermissionDenied)).into())
+        );
+        assert_eq!(
+            EXIT_PERMISSION_DENIED,
+            exit_code_of(
+                &Err::<(), _>(io::Error::from(io::ErrorKind::PermissionDenied))
+                    .context("Unable to open the device")
+                    .unwrap_err()
+            )
+        );
+        #[cfg(unix)]
+        assert_eq!(
+            EXIT_REFUSED,
+            failed(io::Error::from_raw_os_error(libc::EBUSY).into())
+        );
+        #[cfg(windows)]
+        assert_eq!(
+            EXIT_REFUSED,
+            failed(io::Error::from_raw_os_error(32).into())
+        );
+        assert_eq!(EXIT_IO_ERROR, failed(ScsiError::Status(2).into()));
+    }
+
+    #[test]
+    fn test_wipe_failure_advice() {
+        let destroy = "physical destruction";
+        assert!(describe_wipe_failure(&WipeError::BadBlockLimitExceeded(
+            crate::actions::BadBlockLimit::Total(10)
+        ))
+        .contains(destroy));
+        assert!(!describe_wipe_failure(&WipeError::VerificationFailed).contains(destroy));
+        assert!(!describe_wipe_failure(&WipeError::IoTimeout(4096)).contains(destroy));
+    }
+
+    #[test]
+    fn test_shred_exit_code_of_first_failure() {
+        let mut session = ConsoleFrontend::new().shred_session(true);
+        session.shredded(&Ok(()));
+        assert_eq!(0, session.exit_code());
+
+        session.shredded(&Err(io::Error::from(io::ErrorKind::PermissionDenied).into()));
+        session.shredded(&Err(anyhow!("Unexpected")));
+        assert_eq!(EXIT_PERMISSION_DENIED, session.exit_code());
+    }
+}
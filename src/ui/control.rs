@@ -0,0 +1,116 @@
+use crate::actions::{IoWatchdog, RateLimit, WipeControl};
+use crate::ui::args::parse_rate;
+use crate::ui::cli::EXIT_IO_ERROR;
+use anyhow::Result;
+use indicatif::HumanDuration;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const CONTROL_FILE_POLL_SECONDS: u64 = 1;
+const WATCHDOG_POLL_MILLIS: u64 = 100;
+
+/// Watches the file for changes and applies its content as a new rate limit,
+/// so the throughput can be adjusted without restarting the wipe.
+pub fn watch_rate_limit_file(path: PathBuf, limit: RateLimit) {
+    // the initial content applies before anything is written
+    let mut last_value = read_rate_limit_file(&path, &limit, None);
+    thread::spawn(move || loop {
+        thread::sleep(Duration::from_secs(CONTROL_FILE_POLL_SECONDS));
+        last_value = read_rate_limit_file(&path, &limit, last_value.take());
+    });
+}
+
+/// Applies the content of the file if it changed, returns the content read.
+fn read_rate_limit_file(
+    path: &Path,
+    limit: &RateLimit,
+    last_value: Option<String>,
+) -> Option<String> {
+    let value = match std::fs::read_to_string(path) {
+        Ok(content) => content.trim().to_owned(),
+        Err(_) => return last_value,
+    };
+    if last_value.as_ref() != Some(&value) {
+        match parse_rate(&value) {
+            Ok(rate) => limit.set(rate),
+            Err(err) => eprintln!(
+                "⚠ Ignoring the rate limit \"{}\" in {}: {:#}",
+                value,
+                path.display(),
+                err
+            ),
+        }
+    }
+    Some(value)
+}
+
+/// Checks the I/O in flight against the timeout. A stalled operation stops the wipe once
+/// it completes. The process exits with `EXIT_IO_ERROR` if it's still stuck after twice
+/// the timeout, as the operation can't be interrupted. The wiping thread is blocked then,
+/// so no checkpoint is saved and the progress since the last one is lost.
+pub fn watch_stalled_io(watchdog: IoWatchdog, timeout: Duration) {
+    thread::spawn(move || {
+        let mut reported = false;
+        loop {
+            match watchdog.check(timeout, Instant::now()) {
+                Some((position, elapsed)) if elapsed > timeout * 2 => {
+                    eprintln!(
+                        "❌ I/O at {} hasn't completed in {}, giving up without saving a checkpoint.",
+                        position,
+                        HumanDuration(elapsed)
+                    );
+                    std::process::exit(EXIT_IO_ERROR);
+                }
+                Some((position, elapsed)) if elapsed > timeout && !reported => {
+                    eprintln!(
+                        "⚠ I/O at {} is stalled, the wipe stops once it completes.",
+                        position
+                    );
+                    reported = true;
+                }
+                _ => (),
+            }
+            thread::sleep(Duration::from_millis(WATCHDOG_POLL_MILLIS));
+        }
+    });
+}
+
+/// Pauses the wipe on SIGUSR1 or SIGTSTP (Ctrl+Z), resumes on SIGUSR2 or SIGCONT and cancels it
+/// gracefully on SIGINT (Ctrl+C) or SIGTERM. A repeated cancellation terminates immediately.
+/// Should be called before spawning any other threads, so they inherit the signal mask.
+#[cfg(unix)]
+pub fn handle_signals(control: WipeControl) -> Result<()> {
+    use crate::ui::cli::EXIT_CANCELLED;
+    use nix::sys::signal::{SigSet, Signal};
+
+    let mut signals = SigSet::empty();
+    for s in &[
+        Signal::SIGUSR1,
+        Signal::SIGUSR2,
+        Signal::SIGTSTP,
+        Signal::SIGCONT,
+        Signal::SIGINT,
+        Signal::SIGTERM,
+    ] {
+        signals.add(*s);
+    }
+    signals.thread_block()?;
+
+    thread::spawn(move || loop {
+        match signals.wait() {
+            Ok(Signal::SIGUSR1) | Ok(Signal::SIGTSTP) => control.pause(),
+            Ok(Signal::SIGUSR2) | Ok(Signal::SIGCONT) => control.resume(),
+            Ok(_) if control.is_cancelled() => std::process::exit(EXIT_CANCELLED),
+            Ok(_) => control.cancel(),
+            Err(_) => break,
+        }
+    });
+
+    Ok(())
+}
+
+#[cfg(windows)]
+pub fn handle_signals(_control: WipeControl) -> Result<()> {
+    Ok(())
+}
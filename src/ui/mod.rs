@@ -1,4 +1,6 @@
 pub mod args;
+pub mod chart;
 pub mod cli;
+pub mod control;
 pub mod idshortcuts;
 pub mod storage_repo;
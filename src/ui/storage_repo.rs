@@ -1,5 +1,7 @@
 use crate::storage::StorageRef;
+use crate::ui::cli::CommandError;
 use crate::ui::idshortcuts::IdShortcuts;
+use anyhow::Result;
 use std::collections::HashSet;
 
 pub struct StorageRepo {
@@ -45,4 +47,13 @@ impl StorageRepo {
             }
         })
     }
+
+    /// Finds a device by ID, or describes a disk image if the ID is a path to a regular file.
+    pub fn find_by_id_or_path(&self, id: &str) -> Result<StorageRef> {
+        match self.find_by_id(id) {
+            Some(device) => Ok(device.clone()),
+            None if std::path::Path::new(id).is_file() => StorageRef::from_file(id),
+            None => Err(CommandError::DeviceNotFound(id.to_owned()).into()),
+        }
+    }
 }